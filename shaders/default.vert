@@ -18,6 +18,6 @@ void main()
     position = vec3(uModel * vec4(inPosition, 1.0f));
     color = inColor;
     texCoord = inTexCoord;
-    normal = inNormal;
+    normal = transpose(inverse(mat3(uModel))) * inNormal;
     gl_Position = uCameraViewProjection * uModel * vec4(inPosition, 1.0f);
-}
\ No newline at end of file
+}
@@ -0,0 +1,27 @@
+#version 450 core
+
+// G-buffer layout, see deferred.rs
+layout (location = 0) out vec4 outAlbedo;
+layout (location = 1) out vec4 outNormal;
+layout (location = 2) out vec4 outMaterial;
+layout (location = 3) out vec4 outEmissive;
+
+in vec3 color;
+in vec2 texCoord;
+in vec3 normal;
+in vec3 position;
+
+#include "../material.glsl"
+
+void main()
+{
+    MaterialSample material = sampleMaterial(texCoord);
+    if (material.baseColor.a < uAlphaCutoff) {
+        discard;
+    }
+
+    outAlbedo = vec4(material.baseColor.rgb, 1.0f);
+    outNormal = vec4(facingNormal(normal), 0.0f);
+    outMaterial = vec4(material.metallic, material.roughness, material.occlusion, float(uShaderVariant));
+    outEmissive = vec4(material.emissive, 0.0f);
+}
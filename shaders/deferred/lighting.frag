@@ -0,0 +1,79 @@
+#version 450 core
+
+out vec4 outColor;
+
+in vec2 texCoord;
+
+uniform sampler2D uGBufferAlbedo;
+uniform sampler2D uGBufferNormal;
+uniform sampler2D uGBufferMaterial;
+uniform sampler2D uGBufferEmissive;
+uniform sampler2D uGBufferDepth;
+uniform mat4 uInverseViewProjection;
+uniform float uCameraFar;
+
+// Same as ShaderVariant in material.rs
+const int SHADER_UNLIT = 1;
+
+// Same as GBufferView in deferred.rs
+const int VIEW_LIT = 0;
+const int VIEW_ALBEDO = 1;
+const int VIEW_NORMAL = 2;
+const int VIEW_MATERIAL = 3;
+const int VIEW_EMISSIVE = 4;
+const int VIEW_DEPTH = 5;
+uniform int uGBufferView;
+
+#include "../lighting.glsl"
+
+void main()
+{
+    vec3 albedo = texture(uGBufferAlbedo, texCoord).rgb;
+    vec3 normal = texture(uGBufferNormal, texCoord).xyz;
+    vec4 material = texture(uGBufferMaterial, texCoord);
+    vec3 emissive = texture(uGBufferEmissive, texCoord).rgb;
+    float depth = texture(uGBufferDepth, texCoord).r;
+
+    vec4 clipPosition = uInverseViewProjection * vec4(vec3(texCoord, depth) * 2.0f - 1.0f, 1.0f);
+    vec3 position = clipPosition.xyz / clipPosition.w;
+
+    switch (uGBufferView) {
+        case VIEW_ALBEDO:
+            outColor = vec4(albedo, 1.0f);
+            return;
+        case VIEW_NORMAL:
+            outColor = vec4(normal * 0.5f + 0.5f, 1.0f);
+            return;
+        case VIEW_MATERIAL:
+            outColor = vec4(material.rgb, 1.0f);
+            return;
+        case VIEW_EMISSIVE:
+            outColor = vec4(emissive, 1.0f);
+            return;
+        case VIEW_DEPTH: {
+            float viewDepth = -(uCameraView * vec4(position, 1.0f)).z;
+            outColor = vec4(vec3(depth == 1.0f ? 1.0f : viewDepth / uCameraFar), 1.0f);
+            return;
+        }
+    }
+
+    // Keep the clear color where nothing was drawn
+    if (depth == 1.0f) {
+        discard;
+    }
+    if (int(round(material.a)) == SHADER_UNLIT) {
+        outColor = vec4(albedo + emissive, 1.0f);
+        return;
+    }
+
+    Surface surface;
+    surface.position = position;
+    surface.normal = normal;
+    surface.albedo = albedo;
+    surface.metallic = material.r;
+    surface.roughness = material.g;
+    surface.occlusion = material.b;
+    surface.emissive = emissive;
+
+    outColor = vec4(shadeSurface(surface), 1.0f);
+}
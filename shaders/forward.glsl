@@ -0,0 +1,33 @@
+// Forward shading of the scene meshes, shared by the opaque and transparent
+// passes
+
+in vec3 color;
+in vec2 texCoord;
+in vec3 normal;
+in vec3 position;
+
+#include "material.glsl"
+#include "lighting.glsl"
+
+// Lit color and alpha of the fragment, discarded below the alpha cutoff
+vec4 shadeFragment()
+{
+    MaterialSample material = sampleMaterial(texCoord);
+    if (material.baseColor.a < uAlphaCutoff) {
+        discard;
+    }
+    if (uShaderVariant == SHADER_UNLIT) {
+        return vec4(material.baseColor.rgb + material.emissive, material.baseColor.a);
+    }
+
+    Surface surface;
+    surface.position = position;
+    surface.normal = facingNormal(normal);
+    surface.albedo = material.baseColor.rgb;
+    surface.metallic = material.metallic;
+    surface.roughness = material.roughness;
+    surface.occlusion = material.occlusion;
+    surface.emissive = material.emissive;
+
+    return vec4(shadeSurface(surface), material.baseColor.a);
+}
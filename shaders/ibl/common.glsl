@@ -0,0 +1,45 @@
+// Helpers shared by the image based lighting precomputation passes
+
+const float PI = 3.14159265f;
+
+// Direction through the center of a texel of a cubemap face, uv in [-1, 1]
+vec3 cubeDirection(uint face, vec2 uv)
+{
+    switch (face) {
+        case 0: return normalize(vec3(1.0f, -uv.y, -uv.x));
+        case 1: return normalize(vec3(-1.0f, -uv.y, uv.x));
+        case 2: return normalize(vec3(uv.x, 1.0f, uv.y));
+        case 3: return normalize(vec3(uv.x, -1.0f, -uv.y));
+        case 4: return normalize(vec3(uv.x, -uv.y, 1.0f));
+        default: return normalize(vec3(-uv.x, -uv.y, -1.0f));
+    }
+}
+
+// Direction of the texel of the cubemap face processed by this invocation
+vec3 invocationDirection(int size)
+{
+    vec2 uv = (vec2(gl_GlobalInvocationID.xy) + 0.5f) / float(size) * 2.0f - 1.0f;
+    return cubeDirection(gl_GlobalInvocationID.z, uv);
+}
+
+// Low discrepancy sequence point i out of count
+vec2 hammersley(uint i, uint count)
+{
+    uint bits = bitfieldReverse(i);
+    return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10f);
+}
+
+// GGX distributed half vector around normal
+vec3 importanceSampleGgx(vec2 xi, vec3 normal, float roughness)
+{
+    float alpha = roughness * roughness;
+    float phi = 2.0f * PI * xi.x;
+    float cosTheta = sqrt((1.0f - xi.y) / (1.0f + (alpha * alpha - 1.0f) * xi.y));
+    float sinTheta = sqrt(1.0f - cosTheta * cosTheta);
+    vec3 halfVector = vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+
+    vec3 up = abs(normal.z) < 0.999f ? vec3(0.0f, 0.0f, 1.0f) : vec3(1.0f, 0.0f, 0.0f);
+    vec3 tangent = normalize(cross(up, normal));
+    vec3 bitangent = cross(normal, tangent);
+    return normalize(tangent * halfVector.x + bitangent * halfVector.y + normal * halfVector.z);
+}
@@ -0,0 +1,13 @@
+#version 450 core
+
+out vec4 outColor;
+
+in vec3 direction;
+
+uniform samplerCube uEnvironment;
+uniform float uEnvironmentIntensity;
+
+void main()
+{
+    outColor = vec4(textureLod(uEnvironment, direction, 0.0f).rgb * uEnvironmentIntensity, 1.0f);
+}
@@ -0,0 +1,15 @@
+#version 450 core
+
+out vec3 direction;
+
+// Inverse of the camera projection and rotation, without its translation
+uniform mat4 uInverseViewProjection;
+
+void main()
+{
+    // Fullscreen triangle on the far plane, drawn where the depth buffer is still clear
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2.0f - 1.0f;
+    vec4 worldPosition = uInverseViewProjection * vec4(position, 1.0f, 1.0f);
+    direction = worldPosition.xyz / worldPosition.w;
+    gl_Position = vec4(position, 1.0f, 1.0f);
+}
@@ -2,7 +2,9 @@
 
 out vec4 outColor;
 
+uniform vec3 uColor;
+
 void main()
 {
-    outColor = vec4(1.0f, 1.0f, 1.0f, 1.0f);
-}
\ No newline at end of file
+    outColor = vec4(uColor, 1.0f);
+}
@@ -0,0 +1,359 @@
+// Lights, shadows and clusters shared by the forward and deferred lighting shaders
+
+uniform vec3 uCameraPosition;
+uniform mat4 uCameraView;
+
+// Image based lighting, see environment.rs
+uniform samplerCube uIrradianceMap;
+uniform samplerCube uPrefilteredMap;
+uniform sampler2D uBrdfLut;
+uniform float uPrefilteredMaxLod;
+uniform float uEnvironmentIntensity;
+
+// Screen-space ambient occlusion, see ssao.rs
+uniform sampler2D uAmbientOcclusion;
+uniform bool uSsaoEnabled;
+
+// Light list, see light_buffer.rs for the layout
+const int LIGHT_POINT = 0;
+const int LIGHT_SPOT = 1;
+const int LIGHT_DIRECTIONAL = 2;
+
+const int FLAG_SHADOW = 1;
+const int FLAG_COOKIE = 2;
+
+struct Light {
+    vec4 positionRange;
+    vec4 directionKind;
+    vec4 colorIntensity;
+    vec4 coneFlags;
+};
+
+layout (std430, binding = 0) readonly buffer LightBuffer {
+    Light lights[];
+};
+
+// Clustered lighting, see clustered.rs
+layout (std430, binding = 2) readonly buffer ClusterLightIndices {
+    uint clusterLightIndices[];
+};
+// x: offset in clusterLightIndices, y: light count
+layout (std430, binding = 3) readonly buffer ClusterLightGrid {
+    uvec2 clusterLightGrid[];
+};
+uniform uvec3 uClusterGrid;
+uniform vec2 uClusterTileSize;
+uniform float uClusterScale;
+uniform float uClusterBias;
+uniform bool uClusterHeatmap;
+
+// Point light shadows
+uniform samplerCube uShadowMap;
+uniform float uShadowFarPlane;
+uniform float uShadowBias;
+uniform float uShadowPcfRadius;
+
+// Directional light shadows
+uniform sampler2DArray uCascadeShadowMap;
+uniform int uCascadeCount;
+uniform float uCascadeSplits[4];
+uniform mat4 uCascadeMatrices[4];
+uniform float uCascadeBias;
+uniform float uCascadeBlend;
+uniform bool uCascadeDebug;
+
+// Spot light cookie and shadows
+uniform mat4 uSpotLightMatrix;
+uniform sampler2D uSpotLightCookie;
+uniform sampler2D uSpotShadowMap;
+uniform float uSpotShadowBias;
+
+const float PI = 3.14159265f;
+
+// Reflectance at normal incidence of dielectrics
+const vec3 dielectricF0 = vec3(0.04f);
+
+struct Surface {
+    vec3 position;
+    vec3 normal;
+    vec3 albedo;
+    float metallic;
+    float roughness;
+    float occlusion;
+    vec3 emissive;
+};
+
+// PCF sampling directions, spread over the 3 axes to avoid sampling the same texel twice
+const vec3 pcfOffsets[20] = vec3[](
+    vec3(1, 1, 1), vec3(1, -1, 1), vec3(-1, -1, 1), vec3(-1, 1, 1),
+    vec3(1, 1, -1), vec3(1, -1, -1), vec3(-1, -1, -1), vec3(-1, 1, -1),
+    vec3(1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0), vec3(-1, 1, 0),
+    vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
+    vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, -1, -1), vec3(0, 1, -1)
+);
+
+const vec3 cascadeDebugColors[4] = vec3[](
+    vec3(1.0f, 0.3f, 0.3f), vec3(0.3f, 1.0f, 0.3f), vec3(0.3f, 0.3f, 1.0f), vec3(1.0f, 1.0f, 0.3f)
+);
+
+// Index of the froxel containing the fragment
+uint clusterIndex(float viewDepth)
+{
+    uint slice = uint(max(log(viewDepth) * uClusterScale + uClusterBias, 0.0f));
+    uvec3 cell = min(uvec3(uvec2(gl_FragCoord.xy / uClusterTileSize), slice), uClusterGrid - 1);
+    return cell.x + cell.y * uClusterGrid.x + cell.z * uClusterGrid.x * uClusterGrid.y;
+}
+
+// Blue to green to red ramp for the light count heatmap
+vec3 heatmap(float value)
+{
+    return clamp(vec3(2.0f * value - 1.0f, 1.0f - abs(2.0f * value - 1.0f), 1.0f - 2.0f * value), 0.0f, 1.0f);
+}
+
+// Returns 1 when the fragment is fully in the shadow of the point light at lightPosition
+float pointShadow(vec3 position, vec3 lightPosition)
+{
+    vec3 lightToFragment = position - lightPosition;
+    float currentDistance = length(lightToFragment) - uShadowBias;
+
+    if (uShadowPcfRadius <= 0.0f) {
+        float closestDistance = texture(uShadowMap, lightToFragment).r * uShadowFarPlane;
+        return currentDistance > closestDistance ? 1.0f : 0.0f;
+    }
+
+    float shadow = 0.0f;
+    for (int i = 0; i < 20; i++) {
+        float closestDistance = texture(uShadowMap, lightToFragment + pcfOffsets[i] * uShadowPcfRadius).r * uShadowFarPlane;
+        shadow += currentDistance > closestDistance ? 1.0f : 0.0f;
+    }
+    return shadow / 20.0f;
+}
+
+// Returns the fraction of the 3x3 texels around the fragment in which it is shadowed
+float sampleCascade(vec3 position, int cascade)
+{
+    vec4 lightSpacePosition = uCascadeMatrices[cascade] * vec4(position, 1.0f);
+    vec3 projected = lightSpacePosition.xyz / lightSpacePosition.w * 0.5f + 0.5f;
+    if (projected.z > 1.0f) {
+        return 0.0f;
+    }
+
+    vec2 texelSize = 1.0f / vec2(textureSize(uCascadeShadowMap, 0).xy);
+    float shadow = 0.0f;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            float closestDepth = texture(uCascadeShadowMap, vec3(projected.xy + vec2(x, y) * texelSize, cascade)).r;
+            shadow += projected.z - uCascadeBias > closestDepth ? 1.0f : 0.0f;
+        }
+    }
+    return shadow / 9.0f;
+}
+
+int cascadeIndex(float viewDepth)
+{
+    for (int i = 0; i < uCascadeCount; i++) {
+        if (viewDepth < uCascadeSplits[i]) {
+            return i;
+        }
+    }
+    return -1;
+}
+
+// Returns 1 when the fragment is fully in the shadow of the directional light
+float directionalShadow(vec3 position, float viewDepth)
+{
+    int cascade = cascadeIndex(viewDepth);
+    if (cascade < 0) {
+        return 0.0f;
+    }
+
+    float shadow = sampleCascade(position, cascade);
+
+    // Fade into the next cascade, or out of the shadowed distance for the last one
+    float start = cascade == 0 ? 0.0f : uCascadeSplits[cascade - 1];
+    float end = uCascadeSplits[cascade];
+    float blendStart = end - (end - start) * uCascadeBlend;
+    if (viewDepth > blendStart) {
+        float next = cascade + 1 < uCascadeCount ? sampleCascade(position, cascade + 1) : 0.0f;
+        shadow = mix(shadow, next, (viewDepth - blendStart) / (end - blendStart));
+    }
+    return shadow;
+}
+
+// Returns 1 when the fragment is fully in the shadow of the spot light
+float spotShadow(vec3 projected)
+{
+    if (projected.z > 1.0f) {
+        return 0.0f;
+    }
+
+    vec2 texelSize = 1.0f / vec2(textureSize(uSpotShadowMap, 0));
+    float shadow = 0.0f;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            float closestDepth = texture(uSpotShadowMap, projected.xy + vec2(x, y) * texelSize).r;
+            shadow += projected.z - uSpotShadowBias > closestDepth ? 1.0f : 0.0f;
+        }
+    }
+    return shadow / 9.0f;
+}
+
+// Inverse square falloff windowed to reach 0 at the range of the light
+float rangeAttenuation(float distance, float range)
+{
+    float rangeRatio = distance / range;
+    float window = clamp(1.0f - rangeRatio * rangeRatio * rangeRatio * rangeRatio, 0.0f, 1.0f);
+    return window * window / max(distance * distance, 0.01f);
+}
+
+// Color of a light reaching position from lightDirection, including attenuation and shadows
+vec3 incomingLight(Light light, vec3 position, float viewDepth, out vec3 lightDirection)
+{
+    int kind = int(light.directionKind.w);
+    int flags = int(light.coneFlags.z);
+    vec3 color = light.colorIntensity.rgb * light.colorIntensity.a;
+
+    if (kind == LIGHT_DIRECTIONAL) {
+        lightDirection = -normalize(light.directionKind.xyz);
+        if ((flags & FLAG_SHADOW) != 0) {
+            color *= 1.0f - directionalShadow(position, viewDepth);
+        }
+        return color;
+    }
+
+    lightDirection = light.positionRange.xyz - position;
+    float distance = length(lightDirection);
+    lightDirection /= distance;
+    if (distance > light.positionRange.w) {
+        return vec3(0.0f);
+    }
+    color *= rangeAttenuation(distance, light.positionRange.w);
+
+    if (kind == LIGHT_POINT) {
+        if ((flags & FLAG_SHADOW) != 0) {
+            color *= 1.0f - pointShadow(position, light.positionRange.xyz);
+        }
+        return color;
+    }
+
+    // Smooth cone edges
+    float cosAngle = dot(-lightDirection, normalize(light.directionKind.xyz));
+    color *= smoothstep(light.coneFlags.y, light.coneFlags.x, cosAngle);
+
+    if ((flags & (FLAG_SHADOW | FLAG_COOKIE)) != 0) {
+        vec4 lightSpacePosition = uSpotLightMatrix * vec4(position, 1.0f);
+        vec3 projected = lightSpacePosition.xyz / lightSpacePosition.w * 0.5f + 0.5f;
+        if ((flags & FLAG_COOKIE) != 0) {
+            color *= texture(uSpotLightCookie, projected.xy).rgb;
+        }
+        if ((flags & FLAG_SHADOW) != 0) {
+            color *= 1.0f - spotShadow(projected);
+        }
+    }
+    return color;
+}
+
+// Trowbridge-Reitz GGX normal distribution
+float distributionGgx(float nDotH, float roughness)
+{
+    float alpha = roughness * roughness;
+    float alpha2 = alpha * alpha;
+    float denominator = nDotH * nDotH * (alpha2 - 1.0f) + 1.0f;
+    return alpha2 / (PI * denominator * denominator);
+}
+
+// Smith geometry term with the Schlick-GGX approximation for direct lighting
+float geometrySmith(float nDotV, float nDotL, float roughness)
+{
+    float k = (roughness + 1.0f) * (roughness + 1.0f) / 8.0f;
+    float geometryView = nDotV / (nDotV * (1.0f - k) + k);
+    float geometryLight = nDotL / (nDotL * (1.0f - k) + k);
+    return geometryView * geometryLight;
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 f0)
+{
+    return f0 + (1.0f - f0) * pow(clamp(1.0f - cosTheta, 0.0f, 1.0f), 5.0f);
+}
+
+// Fresnel of the whole specular lobe, rougher surfaces reflecting less at grazing angles
+vec3 fresnelSchlickRoughness(float cosTheta, vec3 f0, float roughness)
+{
+    return f0 + (max(vec3(1.0f - roughness), f0) - f0) * pow(clamp(1.0f - cosTheta, 0.0f, 1.0f), 5.0f);
+}
+
+// Diffuse and specular reflection of the environment, using the split sum approximation
+vec3 environmentLight(Surface surface, vec3 viewDirection)
+{
+    float nDotV = max(dot(surface.normal, viewDirection), 1e-4f);
+    vec3 f0 = mix(dielectricF0, surface.albedo, surface.metallic);
+    vec3 fresnel = fresnelSchlickRoughness(nDotV, f0, surface.roughness);
+
+    vec3 irradiance = texture(uIrradianceMap, surface.normal).rgb;
+    vec3 diffuse = (1.0f - fresnel) * (1.0f - surface.metallic) * irradiance * surface.albedo;
+
+    vec3 reflected = reflect(-viewDirection, surface.normal);
+    vec3 prefiltered = textureLod(uPrefilteredMap, reflected, surface.roughness * uPrefilteredMaxLod).rgb;
+    vec2 brdf = texture(uBrdfLut, vec2(nDotV, surface.roughness)).rg;
+    vec3 specular = prefiltered * (f0 * brdf.x + brdf.y);
+
+    return (diffuse + specular) * surface.occlusion * uEnvironmentIntensity;
+}
+
+// Cook-Torrance specular and Lambertian diffuse reflection of a light coming from lightDirection
+vec3 cookTorrance(Surface surface, vec3 viewDirection, vec3 lightDirection, vec3 lightColor)
+{
+    float nDotL = max(dot(surface.normal, lightDirection), 0.0f);
+    if (nDotL <= 0.0f) {
+        return vec3(0.0f);
+    }
+    float nDotV = max(dot(surface.normal, viewDirection), 1e-4f);
+    vec3 halfDirection = normalize(lightDirection + viewDirection);
+    float nDotH = max(dot(surface.normal, halfDirection), 0.0f);
+
+    // Keep a minimum roughness so that point lights still have a visible highlight
+    float roughness = max(surface.roughness, 0.045f);
+    vec3 f0 = mix(dielectricF0, surface.albedo, surface.metallic);
+    vec3 fresnel = fresnelSchlick(max(dot(halfDirection, viewDirection), 0.0f), f0);
+    vec3 specular = distributionGgx(nDotH, roughness) * geometrySmith(nDotV, nDotL, roughness) * fresnel
+        / (4.0f * nDotV * nDotL + 1e-4f);
+
+    // Metals have no diffuse reflection, and light reflected specularly is not diffused
+    vec3 diffuse = (1.0f - fresnel) * (1.0f - surface.metallic) * surface.albedo / PI;
+    return (diffuse + specular) * lightColor * nDotL;
+}
+
+// Radiance leaving a surface towards the camera from the environment, the lights of its cluster
+// and its emission, replaced by the debug views when enabled
+vec3 shadeSurface(Surface surface)
+{
+    float viewDepth = -(uCameraView * vec4(surface.position, 1.0f)).z;
+    uvec2 cluster = clusterLightGrid[clusterIndex(viewDepth)];
+    if (uClusterHeatmap) {
+        return heatmap(float(cluster.y) / 16.0f);
+    }
+
+    // Only the environment lighting is occluded, direct lights have their own shadows
+    if (uSsaoEnabled) {
+        surface.occlusion *= texelFetch(uAmbientOcclusion, ivec2(gl_FragCoord.xy), 0).r;
+    }
+
+    vec3 viewDirection = normalize(uCameraPosition - surface.position);
+    vec3 color = environmentLight(surface, viewDirection) + surface.emissive;
+    for (uint i = 0; i < cluster.y; i++) {
+        vec3 lightDirection;
+        vec3 lightColor = incomingLight(lights[clusterLightIndices[cluster.x + i]], surface.position, viewDepth, lightDirection);
+        if (lightColor == vec3(0.0f)) {
+            continue;
+        }
+        color += cookTorrance(surface, viewDirection, lightDirection, lightColor);
+    }
+
+    if (uCascadeDebug) {
+        int cascade = cascadeIndex(viewDepth);
+        if (cascade >= 0) {
+            color *= cascadeDebugColors[cascade];
+        }
+    }
+    return color;
+}
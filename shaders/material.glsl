@@ -0,0 +1,65 @@
+// Metallic-roughness material inputs, see material.rs
+
+uniform sampler2D uBaseColorMap;
+uniform sampler2D uMetallicRoughnessMap;
+uniform sampler2D uOcclusionMap;
+uniform sampler2D uEmissiveMap;
+uniform vec4 uBaseColorFactor;
+uniform float uMetallicFactor;
+uniform float uRoughnessFactor;
+uniform float uOcclusionStrength;
+uniform vec3 uEmissiveFactor;
+uniform float uAlphaCutoff;
+
+// Textures bound for the material, the factors alone are used for the others
+const int MAP_BASE_COLOR = 1;
+const int MAP_METALLIC_ROUGHNESS = 2;
+const int MAP_OCCLUSION = 4;
+const int MAP_EMISSIVE = 8;
+uniform int uMaterialMaps;
+
+// Same as ShaderVariant in material.rs
+const int SHADER_LIT = 0;
+const int SHADER_UNLIT = 1;
+uniform int uShaderVariant;
+
+struct MaterialSample {
+    vec4 baseColor;
+    float metallic;
+    float roughness;
+    float occlusion;
+    vec3 emissive;
+};
+
+MaterialSample sampleMaterial(vec2 texCoord)
+{
+    MaterialSample material;
+    material.baseColor = uBaseColorFactor;
+    material.metallic = uMetallicFactor;
+    material.roughness = uRoughnessFactor;
+    material.occlusion = 1.0f;
+    material.emissive = uEmissiveFactor;
+
+    if ((uMaterialMaps & MAP_BASE_COLOR) != 0) {
+        material.baseColor *= texture(uBaseColorMap, texCoord);
+    }
+    if ((uMaterialMaps & MAP_METALLIC_ROUGHNESS) != 0) {
+        vec4 metallicRoughness = texture(uMetallicRoughnessMap, texCoord);
+        material.roughness *= metallicRoughness.g;
+        material.metallic *= metallicRoughness.b;
+    }
+    if ((uMaterialMaps & MAP_OCCLUSION) != 0) {
+        material.occlusion = mix(1.0f, texture(uOcclusionMap, texCoord).r, uOcclusionStrength);
+    }
+    if ((uMaterialMaps & MAP_EMISSIVE) != 0) {
+        material.emissive *= texture(uEmissiveMap, texCoord).rgb;
+    }
+    return material;
+}
+
+// Normal of the visible side, for double sided meshes
+vec3 facingNormal(vec3 normal)
+{
+    vec3 normalized = normalize(normal);
+    return gl_FrontFacing ? normalized : -normalized;
+}
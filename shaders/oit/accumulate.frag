@@ -0,0 +1,26 @@
+#version 450 core
+
+// Weighted blended order-independent transparency targets, see oit.rs
+layout (location = 0) out vec4 outAccumulation;
+layout (location = 1) out vec4 outRevealage;
+
+#include "../forward.glsl"
+
+uniform int uPremultipliedAlpha;
+
+void main()
+{
+    vec4 color = shadeFragment();
+    vec3 premultiplied = uPremultipliedAlpha != 0 ? color.rgb : color.rgb * color.a;
+
+    // Closer and more opaque fragments weigh more, equation 10 of McGuire and
+    // Bavoil, Weighted Blended Order-Independent Transparency, 2013
+    float weight = clamp(
+        pow(min(1.0f, color.a * 10.0f) + 0.01f, 3.0f) * 1e8f * pow(1.0f - gl_FragCoord.z * 0.9f, 3.0f),
+        1e-2f,
+        3e3f
+    );
+    outAccumulation = vec4(premultiplied, color.a) * weight;
+    // Multiplies the revealage by 1 - alpha
+    outRevealage = vec4(color.a);
+}
@@ -0,0 +1,26 @@
+#version 450 core
+
+out vec4 outColor;
+
+uniform sampler2D uAccumulation;
+uniform sampler2D uRevealage;
+
+void main()
+{
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    float revealage = texelFetch(uRevealage, texel, 0).r;
+    // No transparent surface covers this pixel
+    if (revealage == 1.0f) {
+        discard;
+    }
+
+    vec4 accumulation = texelFetch(uAccumulation, texel, 0);
+    // Keep the average finite when the weights overflow the half floats
+    if (isinf(max(max(abs(accumulation.r), abs(accumulation.g)), abs(accumulation.b)))) {
+        accumulation.rgb = vec3(accumulation.a);
+    }
+    vec3 averageColor = accumulation.rgb / max(accumulation.a, 1e-5f);
+
+    // Blended over the opaque scene by 1 - revealage
+    outColor = vec4(averageColor, 1.0f - revealage);
+}
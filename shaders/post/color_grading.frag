@@ -0,0 +1,24 @@
+#version 450 core
+
+out vec4 outColor;
+
+in vec2 texCoord;
+
+uniform sampler2D uScreenTexture;
+uniform sampler3D uLut;
+
+// Parameters
+uniform float uLutSize;
+uniform float uIntensity;
+
+void main()
+{
+    vec4 color = texture(uScreenTexture, texCoord);
+    vec3 clamped = clamp(color.rgb, 0.0f, 1.0f);
+
+    // Sample texel centers so that the LUT edges are not blended with the border
+    vec3 lutCoord = clamped * ((uLutSize - 1.0f) / uLutSize) + 0.5f / uLutSize;
+    vec3 graded = texture(uLut, lutCoord).rgb;
+
+    outColor = vec4(mix(color.rgb, graded, uIntensity), color.a);
+}
@@ -0,0 +1,12 @@
+#version 450 core
+
+out vec4 outColor;
+
+in vec2 texCoord;
+
+uniform sampler2D uScreenTexture;
+
+void main()
+{
+    outColor = texture(uScreenTexture, texCoord);
+}
@@ -0,0 +1,28 @@
+#version 450 core
+
+out vec4 outColor;
+
+in vec2 texCoord;
+
+uniform sampler2D uScreenTexture;
+uniform float uTime;
+
+// Parameters
+uniform float uIntensity;
+
+float random(vec2 seed)
+{
+    return fract(sin(dot(seed, vec2(12.9898f, 78.233f))) * 43758.5453f);
+}
+
+void main()
+{
+    vec4 color = texture(uScreenTexture, texCoord);
+    float noise = random(texCoord + fract(uTime)) - 0.5f;
+
+    // Grain is more visible in mid-tones than in shadows and highlights
+    float luminance = dot(color.rgb, vec3(0.299f, 0.587f, 0.114f));
+    float response = 1.0f - abs(luminance * 2.0f - 1.0f);
+
+    outColor = vec4(color.rgb + noise * uIntensity * response, color.a);
+}
@@ -0,0 +1,11 @@
+#version 450 core
+
+out vec2 texCoord;
+
+void main()
+{
+    // Single triangle covering the whole screen, no vertex buffer needed
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    texCoord = position;
+    gl_Position = vec4(position * 2.0f - 1.0f, 0.0f, 1.0f);
+}
@@ -0,0 +1,48 @@
+#version 450 core
+
+out vec4 outColor;
+
+in vec2 texCoord;
+
+uniform sampler2D uScreenTexture;
+uniform vec2 uTexelSize;
+
+// Parameters
+uniform float uSpanMax;
+uniform float uReduceMul;
+uniform float uReduceMin;
+
+const vec3 luma = vec3(0.299f, 0.587f, 0.114f);
+
+void main()
+{
+    float lumaNW = dot(texture(uScreenTexture, texCoord + vec2(-1.0f, -1.0f) * uTexelSize).rgb, luma);
+    float lumaNE = dot(texture(uScreenTexture, texCoord + vec2(1.0f, -1.0f) * uTexelSize).rgb, luma);
+    float lumaSW = dot(texture(uScreenTexture, texCoord + vec2(-1.0f, 1.0f) * uTexelSize).rgb, luma);
+    float lumaSE = dot(texture(uScreenTexture, texCoord + vec2(1.0f, 1.0f) * uTexelSize).rgb, luma);
+    vec4 center = texture(uScreenTexture, texCoord);
+    float lumaM = dot(center.rgb, luma);
+
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // Blur direction is perpendicular to the local luma gradient
+    vec2 direction = vec2(-((lumaNW + lumaNE) - (lumaSW + lumaSE)), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+    float directionReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25f * uReduceMul, uReduceMin);
+    float inverseDirectionMin = 1.0f / (min(abs(direction.x), abs(direction.y)) + directionReduce);
+    direction = clamp(direction * inverseDirectionMin, vec2(-uSpanMax), vec2(uSpanMax)) * uTexelSize;
+
+    vec3 resultA = 0.5f * (
+        texture(uScreenTexture, texCoord + direction * (1.0f / 3.0f - 0.5f)).rgb +
+        texture(uScreenTexture, texCoord + direction * (2.0f / 3.0f - 0.5f)).rgb);
+    vec3 resultB = resultA * 0.5f + 0.25f * (
+        texture(uScreenTexture, texCoord + direction * -0.5f).rgb +
+        texture(uScreenTexture, texCoord + direction * 0.5f).rgb);
+
+    float lumaB = dot(resultB, luma);
+    if (lumaB < lumaMin || lumaB > lumaMax) {
+        outColor = vec4(resultA, center.a);
+    } else {
+        outColor = vec4(resultB, center.a);
+    }
+}
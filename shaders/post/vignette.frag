@@ -0,0 +1,28 @@
+#version 450 core
+
+out vec4 outColor;
+
+in vec2 texCoord;
+
+uniform sampler2D uScreenTexture;
+uniform vec2 uTexelSize;
+
+// Parameters
+uniform float uIntensity;
+uniform float uRadius;
+uniform float uSoftness;
+uniform vec2 uCenter;
+uniform vec3 uColor;
+
+void main()
+{
+    vec4 color = texture(uScreenTexture, texCoord);
+
+    // Keep the vignette round whatever the aspect ratio
+    vec2 centered = texCoord - uCenter;
+    centered.x *= uTexelSize.y / uTexelSize.x;
+    float distance = length(centered);
+
+    float vignette = smoothstep(uRadius, uRadius - uSoftness, distance);
+    outColor = vec4(mix(color.rgb, uColor, (1.0f - vignette) * uIntensity), color.a);
+}
@@ -0,0 +1,13 @@
+#version 450 core
+
+in vec2 texCoord;
+
+uniform sampler2D uBaseColorMap;
+uniform float uAlphaCutoff;
+
+void main()
+{
+    if (texture(uBaseColorMap, texCoord).a < uAlphaCutoff) {
+        discard;
+    }
+}
@@ -0,0 +1,15 @@
+#version 450 core
+
+layout (location = 0) in vec3 inPosition;
+layout (location = 3) in vec2 inTexCoord;
+
+out vec2 texCoord;
+
+uniform mat4 uLightViewProjection;
+uniform mat4 uModel;
+
+void main()
+{
+    texCoord = inTexCoord;
+    gl_Position = uLightViewProjection * uModel * vec4(inPosition, 1.0f);
+}
@@ -0,0 +1,19 @@
+#version 450 core
+
+in vec3 position;
+in vec2 texCoord;
+
+uniform sampler2D uBaseColorMap;
+uniform vec3 uLightPosition;
+uniform float uFarPlane;
+uniform float uAlphaCutoff;
+
+void main()
+{
+    if (texture(uBaseColorMap, texCoord).a < uAlphaCutoff) {
+        discard;
+    }
+
+    // Store the linear distance to the light so that it can be compared in world units
+    gl_FragDepth = length(position - uLightPosition) / uFarPlane;
+}
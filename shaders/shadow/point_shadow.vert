@@ -0,0 +1,14 @@
+#version 450 core
+
+layout (location = 0) in vec3 inPosition;
+layout (location = 3) in vec2 inTexCoord;
+
+out vec2 vertexTexCoord;
+
+uniform mat4 uModel;
+
+void main()
+{
+    vertexTexCoord = inTexCoord;
+    gl_Position = uModel * vec4(inPosition, 1.0f);
+}
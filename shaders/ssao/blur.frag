@@ -0,0 +1,30 @@
+#version 450 core
+
+out float outOcclusion;
+
+// r: ambient occlusion, g: view depth
+uniform sampler2D uOcclusionTexture;
+
+// Depth difference, relative to the depth of the pixel, at which neighbors stop contributing
+const float depthTolerance = 0.05f;
+
+void main()
+{
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    ivec2 lastPixel = textureSize(uOcclusionTexture, 0) - 1;
+    vec2 center = texelFetch(uOcclusionTexture, pixel, 0).rg;
+
+    // 4x4 box matching the noise tile, skipping neighbors across depth discontinuities
+    float occlusion = 0.0f;
+    float totalWeight = 0.0f;
+    for (int x = -2; x < 2; x++) {
+        for (int y = -2; y < 2; y++) {
+            vec2 neighbor = texelFetch(uOcclusionTexture, clamp(pixel + ivec2(x, y), ivec2(0), lastPixel), 0).rg;
+            float weight = max(0.0f, 1.0f - abs(neighbor.g - center.g) / (center.g * depthTolerance + 1e-4f));
+            occlusion += neighbor.r * weight;
+            totalWeight += weight;
+        }
+    }
+
+    outOcclusion = totalWeight > 0.0f ? occlusion / totalWeight : center.r;
+}
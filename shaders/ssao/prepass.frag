@@ -0,0 +1,18 @@
+#version 450 core
+
+out vec4 outNormal;
+
+in vec3 color;
+in vec2 texCoord;
+in vec3 normal;
+in vec3 position;
+
+#include "../material.glsl"
+
+void main()
+{
+    if (sampleMaterial(texCoord).baseColor.a < uAlphaCutoff) {
+        discard;
+    }
+    outNormal = vec4(facingNormal(normal), 0.0f);
+}
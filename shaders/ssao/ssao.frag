@@ -0,0 +1,63 @@
+#version 450 core
+
+// r: ambient occlusion, g: view depth for the blur
+out vec2 outOcclusion;
+
+in vec2 texCoord;
+
+uniform sampler2D uNormalTexture;
+uniform sampler2D uDepthTexture;
+uniform sampler2D uNoiseTexture;
+uniform mat4 uView;
+uniform mat4 uProjection;
+uniform mat4 uInverseProjection;
+uniform vec2 uNoiseScale;
+
+// Same as MAX_SSAO_SAMPLES in ssao.rs
+const int MAX_SAMPLES = 64;
+uniform vec3 uKernel[MAX_SAMPLES];
+uniform int uSampleCount;
+uniform float uRadius;
+uniform float uBias;
+uniform float uIntensity;
+
+vec3 viewPosition(vec2 uv)
+{
+    float depth = texture(uDepthTexture, uv).r;
+    vec4 position = uInverseProjection * vec4(vec3(uv, depth) * 2.0f - 1.0f, 1.0f);
+    return position.xyz / position.w;
+}
+
+void main()
+{
+    float depth = texture(uDepthTexture, texCoord).r;
+    if (depth == 1.0f) {
+        outOcclusion = vec2(1.0f, 0.0f);
+        return;
+    }
+
+    vec3 position = viewPosition(texCoord);
+    vec3 normal = normalize(mat3(uView) * texture(uNormalTexture, texCoord).xyz);
+
+    // Tile a small random rotation of the kernel around the normal, the blur removes the pattern
+    vec3 randomVector = vec3(texture(uNoiseTexture, texCoord * uNoiseScale).xy, 0.0f);
+    vec3 tangent = normalize(randomVector - normal * dot(randomVector, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0.0f;
+    for (int i = 0; i < uSampleCount; i++) {
+        vec3 samplePosition = position + tbn * uKernel[i] * uRadius;
+
+        vec4 projected = uProjection * vec4(samplePosition, 1.0f);
+        vec2 sampleUv = projected.xy / projected.w * 0.5f + 0.5f;
+        float sceneDepth = viewPosition(sampleUv).z;
+
+        // Ignore occluders far in front of the sampled hemisphere
+        float rangeCheck = smoothstep(0.0f, 1.0f, uRadius / abs(position.z - sceneDepth));
+        occlusion += (sceneDepth >= samplePosition.z + uBias ? 1.0f : 0.0f) * rangeCheck;
+    }
+
+    float ambientOcclusion = 1.0f - occlusion / float(max(uSampleCount, 1));
+    outOcclusion = vec2(pow(ambientOcclusion, uIntensity), -position.z);
+}
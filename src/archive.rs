@@ -0,0 +1,341 @@
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// First bytes of an archive, followed by the offset of the index.
+pub const MAGIC: &[u8; 4] = b"PAK1";
+
+/// Bytes before the contents of the files: `MAGIC` and the index offset.
+const HEADER_SIZE: u64 = MAGIC.len() as u64 + 8;
+/// Bytes of an index entry with an empty name.
+const MIN_ENTRY_SIZE: u64 = 4 + 3 * 8 + 1;
+/// Longest name accepted in an index, longer ones meaning it is corrupt.
+const MAX_NAME_LEN: u32 = 4096;
+/// Largest size deflate can expand a stored byte to.
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+/// How a file is stored, written as its discriminant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    Deflate = 1,
+}
+
+impl Compression {
+    fn from_byte(byte: u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            _ => Err(invalid_data("unknown compression")),
+        }
+    }
+}
+
+/// Location of a file in an archive.
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub offset: u64,
+    /// Bytes in the archive, compressed or not
+    pub stored_size: u64,
+    pub size: u64,
+    pub compression: Compression,
+}
+
+/// Files packed in a single file, built by the `pack` binary:
+///
+/// - `MAGIC` then the offset of the index as a `u64`
+/// - the contents of the files, each optionally compressed with deflate
+/// - the index: the number of files as a `u32`, then for each file the
+///   length of its name as a `u32`, its name, the offset, stored size and
+///   size as `u64` and the compression as a byte
+///
+/// All numbers are little endian. Names are the relative paths of the
+/// files with `/` separators, see `entry_name`. Archives are written by
+/// `ArchiveWriter`.
+pub struct Archive {
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+}
+
+impl Archive {
+    /// Reads the index of an archive, the files being read on demand.
+    /// Indexes with entries outside of the archive are rejected.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut file = BufReader::new(file);
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not an archive"));
+        }
+        let index_offset = read_u64(&mut file)?;
+        if !(HEADER_SIZE..=len).contains(&index_offset) {
+            return Err(invalid_data("index out of the archive"));
+        }
+        file.seek(SeekFrom::Start(index_offset))?;
+
+        let count = read_u32(&mut file)?;
+        let max_count = (len - index_offset) / MIN_ENTRY_SIZE;
+        let mut entries = HashMap::with_capacity((count as u64).min(max_count) as usize);
+        for _ in 0..count {
+            let name_len = read_u32(&mut file)?;
+            if name_len > MAX_NAME_LEN {
+                return Err(invalid_data("name too long"));
+            }
+            let mut name = vec![0; name_len as usize];
+            file.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| invalid_data("invalid name"))?;
+            let entry = Entry {
+                offset: read_u64(&mut file)?,
+                stored_size: read_u64(&mut file)?,
+                size: read_u64(&mut file)?,
+                compression: Compression::from_byte(read_u8(&mut file)?)?,
+            };
+            let end = entry.offset.checked_add(entry.stored_size);
+            if entry.offset < HEADER_SIZE || end.is_none_or(|end| end > index_offset) {
+                return Err(invalid_data("file out of the archive"));
+            }
+            let max_size = match entry.compression {
+                Compression::None => entry.stored_size,
+                Compression::Deflate => entry.stored_size.saturating_mul(MAX_DEFLATE_RATIO),
+            };
+            if entry.size > max_size {
+                return Err(invalid_data("file larger than stored"));
+            }
+            entries.insert(name, entry);
+        }
+
+        Ok(Archive {
+            path: path.to_owned(),
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        entry_name(path).is_some_and(|name| self.entries.contains_key(&name))
+    }
+
+    /// Contents of the file at `path`, or `None` if it is not in the archive.
+    pub fn read(&self, path: &Path) -> Option<io::Result<Vec<u8>>> {
+        let entry = *self.entries.get(&entry_name(path)?)?;
+        Some(self.read_entry(entry))
+    }
+
+    fn read_entry(&self, entry: Entry) -> io::Result<Vec<u8>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let stored = file.take(entry.stored_size);
+        let mut contents = Vec::with_capacity(entry.size as usize);
+        // One byte past the size tells files longer than their entry apart
+        match entry.compression {
+            Compression::None => BufReader::new(stored).read_to_end(&mut contents)?,
+            Compression::Deflate => DeflateDecoder::new(stored)
+                .take(entry.size + 1)
+                .read_to_end(&mut contents)?,
+        };
+        if contents.len() as u64 != entry.size {
+            return Err(invalid_data("file size differs from its entry"));
+        }
+        Ok(contents)
+    }
+}
+
+/// Writes files in the format described on `Archive`, storing them
+/// uncompressed when deflate does not make them smaller.
+// Only the pack binary writes archives
+#[allow(dead_code)]
+pub struct ArchiveWriter {
+    file: BufWriter<File>,
+    compress: bool,
+    entries: Vec<(String, Entry)>,
+    /// Offset of the next file
+    offset: u64,
+}
+
+#[allow(dead_code)]
+impl ArchiveWriter {
+    pub fn create(path: &Path, compress: bool) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        // Offset of the index, known once the files are written
+        file.write_all(&0u64.to_le_bytes())?;
+        Ok(ArchiveWriter {
+            file,
+            compress,
+            entries: vec![],
+            offset: HEADER_SIZE,
+        })
+    }
+
+    /// Adds a file named `name`, see `entry_name`.
+    pub fn add(&mut self, name: String, contents: &[u8]) -> io::Result<()> {
+        let deflated = if self.compress {
+            let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::best());
+            encoder.write_all(contents)?;
+            Some(encoder.finish()?).filter(|deflated| deflated.len() < contents.len())
+        } else {
+            None
+        };
+        let (stored, compression) = match &deflated {
+            Some(deflated) => (deflated.as_slice(), Compression::Deflate),
+            None => (contents, Compression::None),
+        };
+        self.file.write_all(stored)?;
+        self.entries.push((
+            name,
+            Entry {
+                offset: self.offset,
+                stored_size: stored.len() as u64,
+                size: contents.len() as u64,
+                compression,
+            },
+        ));
+        self.offset += stored.len() as u64;
+        Ok(())
+    }
+
+    /// Writes the index, returning the entries of the files.
+    pub fn finish(mut self) -> io::Result<Vec<(String, Entry)>> {
+        let file = &mut self.file;
+        file.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        for (name, entry) in &self.entries {
+            file.write_all(&(name.len() as u32).to_le_bytes())?;
+            file.write_all(name.as_bytes())?;
+            file.write_all(&entry.offset.to_le_bytes())?;
+            file.write_all(&entry.stored_size.to_le_bytes())?;
+            file.write_all(&entry.size.to_le_bytes())?;
+            file.write_all(&[entry.compression as u8])?;
+        }
+        file.seek(SeekFrom::Start(MAGIC.len() as u64))?;
+        file.write_all(&self.offset.to_le_bytes())?;
+        file.flush()?;
+        Ok(self.entries)
+    }
+}
+
+/// Name of the file at `path` in an archive: its components joined with
+/// `/`, `.` and `..` being resolved. `None` for paths leaving the root.
+pub fn entry_name(path: &Path) -> Option<String> {
+    let mut components: Vec<&str> = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_str()?),
+            Component::ParentDir => {
+                components.pop()?;
+            }
+            Component::CurDir => (),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(components.join("/"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn archive_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("archive-test-{}-{name}.pak", std::process::id()))
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let path = archive_path("round-trip");
+        let repeated = b"repeated ".repeat(100);
+        let short = b"short".to_vec();
+        let mut writer = ArchiveWriter::create(&path, true).unwrap();
+        writer
+            .add("res/repeated.txt".to_owned(), &repeated)
+            .unwrap();
+        writer.add("short.txt".to_owned(), &short).unwrap();
+        writer.finish().unwrap();
+
+        let archive = Archive::open(&path).unwrap();
+        let compression = |name| archive.entries.get(name).unwrap().compression;
+        assert_eq!(compression("res/repeated.txt"), Compression::Deflate);
+        // Deflate would make it larger
+        assert_eq!(compression("short.txt"), Compression::None);
+        let read = |name: &str| archive.read(Path::new(name)).unwrap().unwrap();
+        assert_eq!(read("res/./repeated.txt"), repeated);
+        assert_eq!(read("short.txt"), short);
+        assert!(archive.read(Path::new("missing.txt")).is_none());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_indexes_are_rejected() {
+        let path = archive_path("corrupt");
+        let mut writer = ArchiveWriter::create(&path, false).unwrap();
+        writer.add("file".to_owned(), b"contents").unwrap();
+        writer.finish().unwrap();
+        let valid = fs::read(&path).unwrap();
+        // The index follows the 8 bytes of contents
+        let entry = HEADER_SIZE as usize + 8 + 4;
+        let open_patched = |at: usize, bytes: &[u8]| {
+            let mut patched = valid.clone();
+            patched[at..at + bytes.len()].copy_from_slice(bytes);
+            fs::write(&path, patched).unwrap();
+            Archive::open(&path).map(|_| ()).unwrap_err().kind()
+        };
+
+        let invalid = io::ErrorKind::InvalidData;
+        assert_eq!(open_patched(4, &u64::MAX.to_le_bytes()), invalid);
+        assert_eq!(open_patched(entry, &u32::MAX.to_le_bytes()), invalid);
+        // Offset, then stored size past the index
+        assert_eq!(open_patched(entry + 8, &0u64.to_le_bytes()), invalid);
+        assert_eq!(open_patched(entry + 16, &9u64.to_le_bytes()), invalid);
+        assert_eq!(open_patched(entry + 24, &9u64.to_le_bytes()), invalid);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn entry_names() {
+        let name = |path: &str| entry_name(Path::new(path));
+        assert_eq!(
+            name("res/textures/a.png").as_deref(),
+            Some("res/textures/a.png")
+        );
+        assert_eq!(
+            name("./res/../shaders/./a.vert").as_deref(),
+            Some("shaders/a.vert")
+        );
+        assert_eq!(name("res/.."), Some(String::new()));
+        assert_eq!(name("../res/a.png"), None);
+        assert_eq!(name("res/../../a.png"), None);
+        assert_eq!(name("/res/a.png"), None);
+    }
+}
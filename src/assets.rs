@@ -0,0 +1,426 @@
+use crate::geometry::MeshData;
+use crate::material::{Material, MaterialError};
+use crate::model::{self, Import};
+use crate::texture::{Texture, TextureData, TextureKind};
+use crate::thread_pool::{Task, ThreadPool};
+use crate::watcher::FileWatcher;
+use crate::{primitives, Mesh, Shader};
+use image::ImageResult;
+use log::{error, info};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AssetKind {
+    Texture,
+    Shader,
+    Material,
+    Mesh,
+}
+
+/// Entry of the listing of the loaded assets.
+#[derive(Debug)]
+pub struct LoadedAsset {
+    pub kind: AssetKind,
+    pub name: String,
+    /// Bytes of GPU memory, 0 for materials which own none, their textures
+    /// being listed apart
+    pub memory: usize,
+    /// Number of handles keeping the asset loaded
+    pub handles: usize,
+}
+
+/// Assets deduplicated by key, only weakly referenced so that they are
+/// freed along with their last handle.
+struct Cache<K, T> {
+    assets: HashMap<K, Weak<T>>,
+}
+
+impl<K, T> Default for Cache<K, T> {
+    fn default() -> Self {
+        Cache {
+            assets: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, T> Cache<K, T> {
+    fn get(&self, key: &K) -> Option<Rc<T>> {
+        self.assets.get(key)?.upgrade()
+    }
+
+    fn insert(&mut self, key: K, asset: T) -> Rc<T> {
+        let asset = Rc::new(asset);
+        self.assets.insert(key, Rc::downgrade(&asset));
+        asset
+    }
+
+    /// Forgets the assets whose handles were all dropped, returning their
+    /// keys.
+    fn prune(&mut self) -> Vec<K> {
+        let freed: Vec<K> = self
+            .assets
+            .iter()
+            .filter(|(_, asset)| asset.strong_count() == 0)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &freed {
+            self.assets.remove(key);
+        }
+        freed
+    }
+
+    fn loaded(&self) -> impl Iterator<Item = (&K, Rc<T>)> {
+        self.assets
+            .iter()
+            .filter_map(|(key, asset)| Some((key, asset.upgrade()?)))
+    }
+}
+
+/// Texture file being decoded by a worker.
+struct PendingTexture {
+    path: PathBuf,
+    /// Not keeping the texture loaded, the upload being skipped if it was
+    /// dropped meanwhile
+    texture: Weak<Texture>,
+    data: Task<ImageResult<TextureData>>,
+}
+
+/// Loads textures, shaders, materials and meshes once per path and load
+/// options, handing out `Rc` handles to them.
+///
+/// GPU resources are freed when the last handle to an asset is dropped, a
+/// later request loading it again.
+///
+/// Files are read and decoded by worker threads, placeholders standing for
+/// the assets until `finish_uploads` sends them to the GPU. Texture and
+/// model files are watched to be reloaded when they change, models being
+/// handed to `model::finish_loading`.
+pub struct Assets {
+    textures: Cache<(PathBuf, TextureKind), Texture>,
+    shaders: Cache<(PathBuf, PathBuf), Shader>,
+    materials: Cache<PathBuf, Material>,
+    meshes: Cache<String, Mesh>,
+    workers: ThreadPool,
+    pending_textures: Vec<PendingTexture>,
+    /// Model files being imported again after they changed
+    pending_models: Vec<(PathBuf, Task<gltf::Result<Import>>)>,
+    watcher: FileWatcher<AssetKind>,
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        // The GL thread keeps a core for itself
+        let threads = thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1));
+        Assets {
+            textures: Cache::default(),
+            shaders: Cache::default(),
+            materials: Cache::default(),
+            meshes: Cache::default(),
+            workers: ThreadPool::new("asset loader", threads),
+            pending_textures: vec![],
+            pending_models: vec![],
+            watcher: FileWatcher::new(Duration::from_secs(1)),
+        }
+    }
+
+    /// Texture read from an image file, a placeholder until it is decoded
+    /// and uploaded. Decoding errors are logged, the placeholder staying.
+    pub fn texture(&mut self, path: &Path, kind: TextureKind) -> Rc<Texture> {
+        let key = (path.to_owned(), kind);
+        if let Some(texture) = self.textures.get(&key) {
+            return texture;
+        }
+        let texture = self.textures.insert(key, Texture::placeholder(kind));
+        self.queue_texture(path, &texture);
+        self.watcher.watch(path, AssetKind::Texture);
+        texture
+    }
+
+    fn queue_texture(&mut self, path: &Path, texture: &Rc<Texture>) {
+        let file = path.to_owned();
+        self.pending_textures.push(PendingTexture {
+            path: path.to_owned(),
+            texture: Rc::downgrade(texture),
+            data: self.workers.spawn(move || TextureData::open(&file)),
+        });
+    }
+
+    /// Textures loaded from `path` or named `path`, for all their kinds.
+    pub fn loaded_textures(&self, path: &Path) -> Vec<Rc<Texture>> {
+        self.textures
+            .loaded()
+            .filter(|((texture_path, _), _)| texture_path == path)
+            .map(|(_, texture)| texture)
+            .collect()
+    }
+
+    /// Texture which is not read from its own file, like the images embedded
+    /// in a model, `name` identifying it.
+    pub fn texture_with(
+        &mut self,
+        name: &str,
+        kind: TextureKind,
+        create: impl FnOnce() -> Texture,
+    ) -> Rc<Texture> {
+        let key = (PathBuf::from(name), kind);
+        match self.textures.get(&key) {
+            Some(texture) => texture,
+            None => self.textures.insert(key, create()),
+        }
+    }
+
+    pub fn shader(&mut self, vertex_file: &Path, fragment_file: &Path) -> Rc<Shader> {
+        let key = (vertex_file.to_owned(), fragment_file.to_owned());
+        match self.shaders.get(&key) {
+            Some(shader) => shader,
+            None => self
+                .shaders
+                .insert(key, Shader::new(vertex_file, fragment_file)),
+        }
+    }
+
+    pub fn material(&mut self, path: &Path) -> Result<Rc<Material>, MaterialError> {
+        let key = path.to_owned();
+        if let Some(material) = self.materials.get(&key) {
+            return Ok(material);
+        }
+        let material = Material::load(path, self)?;
+        Ok(self.materials.insert(key, material))
+    }
+
+    /// Material which is not read from its own file, like the materials of
+    /// a model, `name` identifying it.
+    pub fn material_with(
+        &mut self,
+        name: &str,
+        create: impl FnOnce(&mut Self) -> Material,
+    ) -> Rc<Material> {
+        let key = PathBuf::from(name);
+        match self.materials.get(&key) {
+            Some(material) => material,
+            None => {
+                let material = create(self);
+                self.materials.insert(key, material)
+            }
+        }
+    }
+
+    /// Small cube drawn in place of the meshes of a model still loading.
+    pub fn placeholder_mesh(&mut self) -> Rc<Mesh> {
+        self.mesh("placeholder", |assets| {
+            let material = assets.material_with("placeholder", |_| Material::default());
+            let MeshData { vertices, indices } = primitives::cube(1.0, 1);
+            Mesh::new(&vertices, &indices, material)
+        })
+    }
+
+    /// Runs `job` on the loading threads, for decoding assets off the GL
+    /// thread.
+    pub fn decode<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> Task<T> {
+        self.workers.spawn(job)
+    }
+
+    /// Watches a model file, for `reload_modified` to update its meshes and
+    /// images.
+    pub fn watch_model(&mut self, path: &Path) {
+        self.watcher.watch(path, AssetKind::Mesh);
+    }
+
+    /// Decodes again the texture and model files modified since they were
+    /// loaded, `finish_uploads` replacing the data of textures in place.
+    /// Files whose assets were all freed stop being watched.
+    pub fn reload_modified(&mut self) {
+        self.forget_freed();
+        for (path, kind) in self.watcher.modified() {
+            info!("Reloading {}", path.display());
+            if kind == AssetKind::Texture {
+                for texture in self.loaded_textures(&path) {
+                    self.queue_texture(&path, &texture);
+                }
+            } else {
+                let file = path.clone();
+                let import = self.workers.spawn(move || model::import(&file));
+                self.pending_models.push((path, import));
+            }
+        }
+    }
+
+    fn forget_freed(&mut self) {
+        self.shaders.prune();
+        self.materials.prune();
+        for (path, _) in self.textures.prune() {
+            if self.loaded_textures(&path).is_empty() {
+                self.watcher.unwatch(&path);
+            }
+        }
+        let freed_models: Vec<PathBuf> = self
+            .meshes
+            .prune()
+            .iter()
+            .filter_map(|name| model::mesh_file(name))
+            .collect();
+        for path in freed_models {
+            let loaded = self
+                .meshes
+                .loaded()
+                .any(|(name, _)| model::mesh_file(name).as_ref() == Some(&path));
+            if !loaded {
+                self.watcher.unwatch(&path);
+            }
+        }
+    }
+
+    /// Uploads the textures decoded since the last call, until `deadline` so
+    /// that loading does not stall the frame. Errors are logged, the
+    /// previous version of the texture staying.
+    pub fn finish_uploads(&mut self, deadline: Instant) {
+        while Instant::now() < deadline {
+            match take_done(&mut self.pending_textures, |p| &p.data) {
+                Some((pending, data)) => match (data, pending.texture.upgrade()) {
+                    (Ok(data), Some(texture)) => texture.upload(&data),
+                    (Ok(_), None) => (),
+                    (Err(err), _) => error!("Unable to load {}: {err}", pending.path.display()),
+                },
+                None => break,
+            }
+        }
+    }
+
+    /// Model file imported again by `reload_modified`, if one is done.
+    pub fn take_reloaded_model(&mut self) -> Option<(PathBuf, Result<Import, String>)> {
+        let ((path, _), import) = take_done(&mut self.pending_models, |(_, import)| import)?;
+        Some((path, import))
+    }
+
+    /// Mesh generated or read from a model, `name` identifying its source.
+    pub fn mesh(&mut self, name: &str, create: impl FnOnce(&mut Self) -> Mesh) -> Rc<Mesh> {
+        match self.meshes.get(&name.to_owned()) {
+            Some(mesh) => mesh,
+            None => {
+                let mesh = create(self);
+                self.meshes.insert(name.to_owned(), mesh)
+            }
+        }
+    }
+
+    pub fn loaded_mesh(&self, name: &str) -> Option<Rc<Mesh>> {
+        self.meshes.get(&name.to_owned())
+    }
+
+    /// Assets still having handles, sorted by kind then by name.
+    pub fn loaded(&self) -> Vec<LoadedAsset> {
+        let entry = |kind, name: String, memory, handles| LoadedAsset {
+            kind,
+            name,
+            memory,
+            handles,
+        };
+        let mut assets: Vec<_> = self
+            .textures
+            .loaded()
+            .map(|((path, kind), texture)| {
+                let name = format!("{} ({kind:?})", path.display());
+                entry(
+                    AssetKind::Texture,
+                    name,
+                    texture.memory_size(),
+                    Rc::strong_count(&texture) - 1,
+                )
+            })
+            .chain(self.shaders.loaded().map(|((vertex, fragment), shader)| {
+                let name = format!("{} + {}", vertex.display(), fragment.display());
+                entry(
+                    AssetKind::Shader,
+                    name,
+                    shader.memory_size(),
+                    Rc::strong_count(&shader) - 1,
+                )
+            }))
+            .chain(self.materials.loaded().map(|(path, material)| {
+                let name = path.display().to_string();
+                entry(
+                    AssetKind::Material,
+                    name,
+                    0,
+                    Rc::strong_count(&material) - 1,
+                )
+            }))
+            .chain(self.meshes.loaded().map(|(name, mesh)| {
+                entry(
+                    AssetKind::Mesh,
+                    name.clone(),
+                    mesh.memory_size(),
+                    Rc::strong_count(&mesh) - 1,
+                )
+            }))
+            .collect();
+        assets.sort_by(|a, b| (a.kind, &a.name).cmp(&(b.kind, &b.name)));
+        assets
+    }
+}
+
+/// Removes the first of `pending` whose task is done, returning it with the
+/// result or the error message of the task.
+fn take_done<P, T, E: Display>(
+    pending: &mut Vec<P>,
+    task: impl Fn(&P) -> &Task<Result<T, E>>,
+) -> Option<(P, Result<T, String>)> {
+    let (i, result) = pending
+        .iter()
+        .enumerate()
+        .find_map(|(i, p)| Some((i, task(p).poll_result()?)))?;
+    Some((pending.swap_remove(i), result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Key = (PathBuf, TextureKind);
+
+    fn key(path: &str, kind: TextureKind) -> Key {
+        (PathBuf::from(path), kind)
+    }
+
+    #[test]
+    fn same_keys_share_their_asset() {
+        let mut cache: Cache<Key, String> = Cache::default();
+        let first = cache.insert(key("a.png", TextureKind::BaseColor), "a".to_owned());
+        let again = cache.get(&key("a.png", TextureKind::BaseColor)).unwrap();
+        assert!(Rc::ptr_eq(&first, &again));
+        assert!(cache.get(&key("b.png", TextureKind::BaseColor)).is_none());
+    }
+
+    #[test]
+    fn load_options_give_other_entries() {
+        let mut cache: Cache<Key, String> = Cache::default();
+        let diffuse = cache.insert(key("a.png", TextureKind::BaseColor), "srgb".to_owned());
+        assert!(cache.get(&key("a.png", TextureKind::Occlusion)).is_none());
+        let normal = cache.insert(key("a.png", TextureKind::Occlusion), "linear".to_owned());
+        assert!(!Rc::ptr_eq(&diffuse, &normal));
+        assert_eq!(cache.loaded().count(), 2);
+    }
+
+    #[test]
+    fn entries_are_pruned_with_their_last_handle() {
+        let mut cache: Cache<Key, String> = Cache::default();
+        let asset = cache.insert(key("a.png", TextureKind::BaseColor), "a".to_owned());
+        let handle = asset.clone();
+        drop(asset);
+        assert!(cache.get(&key("a.png", TextureKind::BaseColor)).is_some());
+        drop(handle);
+        assert!(cache.get(&key("a.png", TextureKind::BaseColor)).is_none());
+        assert_eq!(cache.loaded().count(), 0);
+
+        let _other = cache.insert(key("b.png", TextureKind::BaseColor), "b".to_owned());
+        assert_eq!(cache.prune(), [key("a.png", TextureKind::BaseColor)]);
+        assert_eq!(cache.assets.len(), 1);
+        assert!(cache.prune().is_empty());
+    }
+}
@@ -0,0 +1,397 @@
+//! Converts a source asset into a cooked file that the engine uploads as it
+//! is read:
+//!
+//! ```sh
+//! cargo run --release --bin cook -- res/model.gltf res/model.cmesh
+//! cargo run --release --bin cook -- [--compress] res/albedo.png res/albedo.ctex
+//! ```
+//!
+//! glTF and OBJ models become `.cmesh` files of interleaved vertices with
+//! their tangents and bounds, the glTF node transforms being applied. Each
+//! glTF primitive and each OBJ object, group or material is a part.
+//!
+//! PNG and JPEG images become `.ctex` files with all their mipmaps, which
+//! `--compress` encodes as DXT5 after resizing the image to power of two
+//! dimensions.
+
+// The engine reads cooked files, this tool only uses the format
+#[allow(dead_code)]
+#[path = "../cooked.rs"]
+mod cooked;
+#[allow(dead_code)]
+#[path = "../geometry.rs"]
+mod geometry;
+#[allow(dead_code)]
+#[path = "../optimize.rs"]
+mod optimize;
+#[allow(dead_code)]
+#[path = "../vertex.rs"]
+mod vertex;
+
+use cooked::{CookedPart, CookedTexture, PixelFormat};
+use geometry::{Aabb, MeshData};
+use glam::{Mat4, Vec2, Vec3};
+use gltf::mesh::Mode;
+#[allow(deprecated)]
+use image::codecs::dxt::{DxtEncoder, DxtVariant};
+use image::imageops::{self, FilterType};
+use image::{ImageResult, RgbaImage};
+use std::collections::HashMap;
+use std::error::Error;
+use std::f32::consts::FRAC_PI_3;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use vertex::Vertex;
+
+type CookResult<T> = Result<T, Box<dyn Error>>;
+
+/// Largest angle between the faces of an OBJ file without normals whose
+/// normals are smoothed together, 60 degrees.
+const SMOOTHING_ANGLE: f32 = FRAC_PI_3;
+
+fn main() {
+    let mut compress = false;
+    let mut args = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--compress" => compress = true,
+            _ => args.push(PathBuf::from(arg)),
+        }
+    }
+    if args.len() != 2 {
+        eprintln!("Usage: cook [--compress] <model or image> <output>");
+        exit(1);
+    }
+    let (input, output) = (&args[0], &args[1]);
+
+    let extension = input
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let result = match extension.as_str() {
+        "gltf" | "glb" => cook_gltf(input).and_then(|parts| write_mesh(output, &parts)),
+        "obj" => cook_obj(input).and_then(|parts| write_mesh(output, &parts)),
+        "png" | "jpg" | "jpeg" => {
+            cook_image(input, compress).and_then(|texture| write_texture(output, &texture))
+        }
+        _ => Err("unsupported file type, expected glTF, OBJ, PNG or JPEG".into()),
+    };
+    if let Err(err) = result {
+        eprintln!("Unable to cook {}: {err}", input.display());
+        exit(1);
+    }
+}
+
+/// Reads the triangle primitives of the default scene of a glTF file, in
+/// the space of the scene.
+fn cook_gltf(path: &Path) -> CookResult<Vec<CookedPart>> {
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(path)?;
+    let buffers = gltf::import_buffers(&document, path.parent(), blob)?;
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or("no scene")?;
+
+    let mut parts = vec![];
+    let mut nodes: Vec<_> = scene.nodes().map(|node| (node, Mat4::IDENTITY)).collect();
+    while let Some((node, parent)) = nodes.pop() {
+        let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        nodes.extend(node.children().map(|child| (child, transform)));
+        let mesh = match node.mesh() {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions = match reader.read_positions() {
+                Some(positions) => positions,
+                None => continue,
+            };
+            let mut normals = reader.read_normals();
+            let has_normals = normals.is_some();
+            let mut tex_coords = reader.read_tex_coords(0).map(|t| t.into_f32());
+            let mut tangents = reader.read_tangents();
+            let has_tangents = tangents.is_some();
+            let vertices: Vec<Vertex> = positions
+                .map(|position| {
+                    let normal = normals.as_mut().and_then(Iterator::next);
+                    let uv = tex_coords.as_mut().and_then(Iterator::next);
+                    let tangent = tangents.as_mut().and_then(Iterator::next);
+                    Vertex {
+                        position: Vec3::from(position),
+                        normals: normal.map_or(Vec3::Y, Vec3::from),
+                        color: Vec3::ONE,
+                        // Scene materials load their textures from the
+                        // bottom row, glTF ones start from the top row
+                        texture_coordinates: uv.map_or(Vec2::ZERO, |[u, v]| Vec2::new(u, 1.0 - v)),
+                        tangent: tangent.map_or(Vec3::X, |[x, y, z, _]| Vec3::new(x, y, z)),
+                        // Flipping v flips the bitangent
+                        bitangent_sign: tangent.map_or(1.0, |[.., sign]| -sign),
+                    }
+                })
+                .collect();
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+
+            let mut mesh = MeshData::new(vertices, indices);
+            // glTF requires flat normals when they are left out
+            if !has_normals {
+                geometry::flat_normals(&mut mesh);
+            } else if !has_tangents {
+                geometry::compute_tangents(&mut mesh.vertices, &mesh.indices);
+            }
+            mesh.transform(transform);
+            parts.push(cook_part(mesh));
+        }
+    }
+    Ok(parts)
+}
+
+/// Reads the faces of an OBJ file, triangulated as fans. Materials are not
+/// read, a part being started at each object, group or material.
+fn cook_obj(path: &Path) -> CookResult<Vec<CookedPart>> {
+    let source = fs::read_to_string(path)?;
+    let mut positions = vec![];
+    let mut tex_coords = vec![];
+    let mut normals = vec![];
+    let mut parts = vec![];
+    let mut part = ObjPart::default();
+
+    for (number, line) in source.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        let result = match words.next() {
+            Some("v") => parse_floats(words).map(|v| positions.push(Vec3::from(v))),
+            Some("vt") => parse_floats(words).map(|vt| tex_coords.push(Vec2::from(vt))),
+            Some("vn") => parse_floats(words).map(|vn| normals.push(Vec3::from(vn))),
+            Some("f") => words
+                .map(|corner| part.vertex(corner, &positions, &tex_coords, &normals))
+                .collect::<CookResult<Vec<_>>>()
+                .map(|corners| {
+                    for i in 2..corners.len() {
+                        part.indices
+                            .extend([corners[0], corners[i - 1], corners[i]]);
+                    }
+                }),
+            Some("o" | "g" | "usemtl") => {
+                part.finish(&mut parts);
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        result.map_err(|err| format!("line {}: {err}", number + 1))?;
+    }
+    part.finish(&mut parts);
+    Ok(parts)
+}
+
+/// Vertices and indices of the OBJ faces read since the last part.
+#[derive(Default)]
+struct ObjPart {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    /// Index of the vertex made of each combination of position, texture
+    /// coordinates and normal indices
+    vertex_indices: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    /// Whether a face corner has no normal
+    missing_normals: bool,
+}
+
+impl ObjPart {
+    /// Index of the vertex of a face corner, `position/uv/normal` with the
+    /// last two being optional.
+    fn vertex(
+        &mut self,
+        corner: &str,
+        positions: &[Vec3],
+        tex_coords: &[Vec2],
+        normals: &[Vec3],
+    ) -> CookResult<u32> {
+        let mut indices = corner.split('/');
+        let position = obj_index(indices.next(), positions.len())?.ok_or("missing position")?;
+        let tex_coord = obj_index(indices.next(), tex_coords.len())?;
+        let normal = obj_index(indices.next(), normals.len())?;
+        self.missing_normals |= normal.is_none();
+
+        let key = (position, tex_coord, normal);
+        if let Some(&index) = self.vertex_indices.get(&key) {
+            return Ok(index);
+        }
+        let index = self.vertices.len() as u32;
+        self.vertices.push(Vertex {
+            position: positions[position],
+            normals: normal.map_or(Vec3::Y, |normal| normals[normal]),
+            color: Vec3::ONE,
+            texture_coordinates: tex_coord.map_or(Vec2::ZERO, |uv| tex_coords[uv]),
+            ..Vertex::default()
+        });
+        self.vertex_indices.insert(key, index);
+        Ok(index)
+    }
+
+    fn finish(&mut self, parts: &mut Vec<CookedPart>) {
+        let part = std::mem::take(self);
+        if part.indices.is_empty() {
+            return;
+        }
+        let mut mesh = MeshData::new(part.vertices, part.indices);
+        if part.missing_normals {
+            geometry::smooth_normals(&mut mesh, SMOOTHING_ANGLE);
+        } else {
+            geometry::compute_tangents(&mut mesh.vertices, &mesh.indices);
+        }
+        parts.push(cook_part(mesh));
+    }
+}
+
+/// Index into an OBJ list of `len` elements, counted from 1 or from the end
+/// if negative. `None` if absent.
+fn obj_index(index: Option<&str>, len: usize) -> CookResult<Option<usize>> {
+    let index = match index {
+        Some(index) if !index.is_empty() => index.parse::<i64>()?,
+        _ => return Ok(None),
+    };
+    let resolved = if index < 0 {
+        len as i64 + index
+    } else {
+        index - 1
+    };
+    if (0..len as i64).contains(&resolved) {
+        Ok(Some(resolved as usize))
+    } else {
+        Err(format!("index {index} out of range").into())
+    }
+}
+
+fn parse_floats<'a, const N: usize>(
+    mut words: impl Iterator<Item = &'a str>,
+) -> CookResult<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in &mut values {
+        *value = words.next().ok_or("missing value")?.parse()?;
+    }
+    Ok(values)
+}
+
+/// Orders the triangles and vertices of a part to be drawn faster and
+/// computes its bounds.
+fn cook_part(mut mesh: MeshData) -> CookedPart {
+    let stats = optimize::optimize(&mut mesh);
+    println!("Optimized a part: {stats}");
+    let aabb = Aabb::from_points(mesh.vertices.iter().map(|vertex| vertex.position));
+    CookedPart {
+        min: aabb.min,
+        max: aabb.max,
+        // Cooked files are little endian like the platforms the engine runs on
+        vertices: Vertex::as_bytes(&mesh.vertices).to_vec(),
+        indices: mesh
+            .indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect(),
+    }
+}
+
+/// Reads an image and its mipmaps down to 1x1, starting from the bottom row.
+fn cook_image(path: &Path, compress: bool) -> CookResult<CookedTexture> {
+    let mut image = image::open(path)?.flipv().into_rgba8();
+    if compress {
+        // Halving power of two dimensions keeps whole blocks down to 4x4
+        let width = image.width().next_power_of_two();
+        let height = image.height().next_power_of_two();
+        if image.dimensions() != (width, height) {
+            image = imageops::resize(&image, width, height, FilterType::Triangle);
+        }
+    }
+
+    let (width, height) = image.dimensions();
+    let count = 32 - width.max(height).leading_zeros();
+    let levels = (0..count)
+        .map(|level| {
+            let mipmap = if level == 0 {
+                image.clone()
+            } else {
+                let level_width = (width >> level).max(1);
+                let level_height = (height >> level).max(1);
+                imageops::resize(&image, level_width, level_height, FilterType::Triangle)
+            };
+            if compress {
+                encode_dxt5(&mipmap)
+            } else {
+                Ok(mipmap.into_raw())
+            }
+        })
+        .collect::<ImageResult<_>>()?;
+
+    Ok(CookedTexture {
+        width,
+        height,
+        format: if compress {
+            PixelFormat::Dxt5
+        } else {
+            PixelFormat::Rgba8
+        },
+        levels,
+    })
+}
+
+/// Encodes an image as DXT5 blocks. Images smaller than a block are padded
+/// by repeating their last row and column.
+// The encoder of the image crate is deprecated for lack of maintenance but
+// works for cooking offline
+#[allow(deprecated)]
+fn encode_dxt5(image: &RgbaImage) -> ImageResult<Vec<u8>> {
+    let (width, height) = image.dimensions();
+    let padded = RgbaImage::from_fn(width.max(4), height.max(4), |x, y| {
+        *image.get_pixel(x.min(width - 1), y.min(height - 1))
+    });
+    let mut blocks = vec![];
+    DxtEncoder::new(&mut blocks).encode(
+        &padded,
+        padded.width(),
+        padded.height(),
+        DxtVariant::DXT5,
+    )?;
+    Ok(blocks)
+}
+
+fn write_mesh(output: &Path, parts: &[CookedPart]) -> CookResult<()> {
+    let mut file = BufWriter::new(File::create(output)?);
+    cooked::write_mesh(&mut file, parts)?;
+    file.flush()?;
+
+    let triangles: usize = parts.iter().map(|part| part.indices.len() / 12).sum();
+    println!(
+        "Cooked {} parts, {} triangles into {}",
+        parts.len(),
+        triangles,
+        output.display()
+    );
+    Ok(())
+}
+
+fn write_texture(output: &Path, texture: &CookedTexture) -> CookResult<()> {
+    let mut file = BufWriter::new(File::create(output)?);
+    cooked::write_texture(&mut file, texture)?;
+    file.flush()?;
+
+    let size: usize = texture.levels.iter().map(Vec::len).sum();
+    println!(
+        "Cooked {}x{} {:?} texture with {} levels into {}: {} KiB",
+        texture.width,
+        texture.height,
+        texture.format,
+        texture.levels.len(),
+        output.display(),
+        size / 1024
+    );
+    Ok(())
+}
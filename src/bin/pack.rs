@@ -0,0 +1,96 @@
+//! Packs files into an archive mountable by the engine with `--mount`:
+//!
+//! ```sh
+//! cargo run --release --bin pack -- [--compress] assets.pak res shaders
+//! ```
+//!
+//! Directories are packed recursively, each file under its path as given
+//! on the command line.
+
+// The engine reads archives, this tool only uses the format
+#[allow(dead_code)]
+#[path = "../archive.rs"]
+mod archive;
+
+use archive::{entry_name, ArchiveWriter, Entry};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+fn main() {
+    let mut compress = false;
+    let mut args = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--compress" => compress = true,
+            _ => args.push(PathBuf::from(arg)),
+        }
+    }
+    if args.len() < 2 {
+        eprintln!("Usage: pack [--compress] <archive> <file or directory>...");
+        exit(1);
+    }
+    let output = &args[0];
+
+    let mut files = vec![];
+    for path in &args[1..] {
+        if let Err(err) = collect_files(path, &mut files) {
+            eprintln!("Unable to read {}: {err}", path.display());
+            exit(1);
+        }
+    }
+    files.sort();
+    // An archive packed again is not packed into itself, however it is named
+    if let Ok(output) = fs::canonicalize(output) {
+        files.retain(|file| fs::canonicalize(file).ok().as_ref() != Some(&output));
+    }
+
+    match write_archive(output, &files, compress) {
+        Ok(entries) => {
+            let size: u64 = entries.iter().map(|(_, entry)| entry.size).sum();
+            let stored_size: u64 = entries.iter().map(|(_, entry)| entry.stored_size).sum();
+            println!(
+                "Packed {} files into {}: {} KiB, {} KiB stored",
+                entries.len(),
+                output.display(),
+                size / 1024,
+                stored_size / 1024
+            );
+        }
+        Err(err) => {
+            eprintln!("Unable to write {}: {err}", output.display());
+            exit(1);
+        }
+    }
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_files(&entry?.path(), files)?;
+        }
+    } else {
+        files.push(path.to_owned());
+    }
+    Ok(())
+}
+
+/// Writes `files` with `ArchiveWriter`, each under its path.
+fn write_archive(
+    output: &Path,
+    files: &[PathBuf],
+    compress: bool,
+) -> io::Result<Vec<(String, Entry)>> {
+    let mut archive = ArchiveWriter::create(output, compress)?;
+    for file in files {
+        let name = entry_name(file).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is outside of the working directory", file.display()),
+            )
+        })?;
+        archive.add(name, &fs::read(file)?)?;
+    }
+    archive.finish()
+}
@@ -3,7 +3,7 @@ use glutin::dpi::PhysicalPosition;
 use glutin::event::VirtualKeyCode;
 use std::time::Duration;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Camera {
     pub position: Vec3,
     orientation: Vec3,
@@ -16,13 +16,17 @@ pub struct Camera {
     width: u32,
     height: u32,
     fov: f32,
+    near: f32,
+    far: f32,
 }
 
 impl Camera {
     pub fn new(fov: f32, position: Vec3, width: u32, height: u32) -> Self {
+        let near = 0.1;
+        let far = 100.0;
         Camera {
             position,
-            projection: Mat4::perspective_rh(fov, width as f32 / height as f32, 0.1, 100.0),
+            projection: Mat4::perspective_rh(fov, width as f32 / height as f32, near, far),
             width,
             height,
             orientation: Vec3::new(0.0, 0.0, -1.0),
@@ -32,12 +36,61 @@ impl Camera {
             pitch: 0.0,
             yaw: 180.0,
             fov,
+            near,
+            far,
         }
     }
 
     pub fn get_matrix(&self) -> Mat4 {
-        let view = Mat4::look_at_rh(self.position, self.position + self.orientation, self.up);
-        return self.projection * view;
+        self.projection * self.get_view_matrix()
+    }
+
+    pub fn get_view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position, self.position + self.orientation, self.up)
+    }
+
+    pub fn get_projection_matrix(&self) -> Mat4 {
+        self.projection
+    }
+
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    /// World space corners of the part of the view frustum between the
+    /// `near` and `far` distances.
+    pub fn frustum_corners(&self, near: f32, far: f32) -> [Vec3; 8] {
+        let projection =
+            Mat4::perspective_rh(self.fov, self.width as f32 / self.height as f32, near, far);
+        let inverse = (projection * self.get_view_matrix()).inverse();
+
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let ndc = Vec3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+            );
+            *corner = inverse.project_point3(ndc);
+        }
+        corners
+    }
+
+    /// Places the camera at the origin of `transform`, looking along its -Z
+    /// axis.
+    pub fn set_world_transform(&mut self, transform: Mat4) {
+        self.position = transform.w_axis.truncate();
+        self.orientation = transform.transform_vector3(-Vec3::Z).normalize();
+        self.pitch = self.orientation.y.asin().to_degrees();
+        self.yaw = self.orientation.x.atan2(self.orientation.z).to_degrees();
     }
 
     pub fn update_orientation(&mut self, position: PhysicalPosition<f64>) {
@@ -47,12 +100,7 @@ impl Camera {
         self.yaw += delta_x;
         self.pitch += delta_y;
 
-        if self.pitch > 89.9 {
-            self.pitch = 89.9
-        }
-        if self.pitch < -89.9 {
-            self.pitch = -89.9
-        }
+        self.pitch = self.pitch.clamp(-89.9, 89.9);
 
         self.orientation = Vec3::new(
             self.pitch.to_radians().cos() * self.yaw.to_radians().sin(),
@@ -88,6 +136,7 @@ impl Camera {
     pub fn update_viewport(&mut self, width: u32, height: u32) {
         self.width = width;
         self.height = height;
-        self.projection = Mat4::perspective_rh(self.fov, width as f32 / height as f32, 0.1, 100.0);
+        self.projection =
+            Mat4::perspective_rh(self.fov, width as f32 / height as f32, self.near, self.far);
     }
 }
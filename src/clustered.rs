@@ -0,0 +1,144 @@
+use crate::gl;
+use crate::ssbo::Ssbo;
+use crate::{Camera, Shader};
+use glam::{Mat4, UVec3, Vec2};
+use std::mem::size_of;
+use std::path::Path;
+
+/// Number of clusters along the x, y and depth axes of the view frustum.
+pub const CLUSTER_GRID: UVec3 = glam::const_uvec3!([16, 9, 24]);
+/// Lights past this count in a single cluster are ignored.
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 64;
+
+// Storage buffer bindings, the light list uses LIGHT_BUFFER_BINDING
+const CLUSTER_BOUNDS_BINDING: u32 = 1;
+const CLUSTER_INDICES_BINDING: u32 = 2;
+const CLUSTER_GRID_BINDING: u32 = 3;
+const CLUSTER_COUNTER_BINDING: u32 = 4;
+
+/// Invocations per work group of the compute shaders.
+const WORK_GROUP_SIZE: u32 = 128;
+
+/// Splits the view frustum into a grid of froxels and, each frame, lists
+/// the lights touching each of them so the lighting shaders only evaluate
+/// the lights of the fragment's cluster.
+///
+/// The depth slices are spaced exponentially between the near and far
+/// planes of the camera so that clusters stay roughly cubic.
+pub struct ClusteredLighting {
+    build_shader: Shader,
+    cull_shader: Shader,
+    /// View space AABB of each cluster
+    bounds: Ssbo,
+    /// Light indices of every cluster, packed one after the other
+    light_indices: Ssbo,
+    /// Offset in `light_indices` and light count of each cluster
+    light_grid: Ssbo,
+    /// Next free slot in `light_indices`
+    counter: Ssbo,
+    width: u32,
+    height: u32,
+    /// Projection the cluster bounds were built for, `None` when the screen
+    /// size changed since
+    bounds_projection: Option<Mat4>,
+    /// Shows the number of lights per cluster instead of the shaded scene
+    pub debug_heatmap: bool,
+}
+
+impl ClusteredLighting {
+    pub fn new(width: u32, height: u32) -> Self {
+        let cluster_count = cluster_count();
+        ClusteredLighting {
+            build_shader: Shader::compute(Path::new("shaders/clustered/build_clusters.comp")),
+            cull_shader: Shader::compute(Path::new("shaders/clustered/cull_lights.comp")),
+            bounds: Ssbo::new(cluster_count * 2 * size_of::<[f32; 4]>()),
+            light_indices: Ssbo::new(cluster_count * MAX_LIGHTS_PER_CLUSTER * size_of::<u32>()),
+            light_grid: Ssbo::new(cluster_count * size_of::<[u32; 2]>()),
+            counter: Ssbo::new(size_of::<u32>()),
+            width,
+            height,
+            bounds_projection: None,
+            debug_heatmap: false,
+        }
+    }
+
+    /// Rebuilds the clusters for the new screen size on the next update.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.bounds_projection = None;
+    }
+
+    /// Bins the `light_count` lights of the bound light list into the
+    /// clusters of the camera frustum, rebuilding the clusters first if the
+    /// projection of the camera changed.
+    pub fn update(&mut self, camera: &Camera, light_count: usize) {
+        let groups = (cluster_count() as u32).div_ceil(WORK_GROUP_SIZE);
+
+        self.bounds.bind_base(CLUSTER_BOUNDS_BINDING);
+        let projection = camera.get_projection_matrix();
+        if self.bounds_projection != Some(projection) {
+            self.build_shader.bind();
+            self.build_shader
+                .set_uniform_mat4("uInverseProjection", projection.inverse());
+            self.build_shader
+                .set_uniform_vec2("uScreenSize", self.screen_size());
+            self.build_shader.set_uniform_1f("uNear", camera.near());
+            self.build_shader.set_uniform_1f("uFar", camera.far());
+            self.build_shader
+                .set_uniform_uvec3("uClusterGrid", CLUSTER_GRID);
+            unsafe {
+                gl::DispatchCompute(groups, 1, 1);
+                gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT);
+            }
+            self.bounds_projection = Some(projection);
+        }
+
+        self.counter.upload(&[0u32]);
+        self.light_indices.bind_base(CLUSTER_INDICES_BINDING);
+        self.light_grid.bind_base(CLUSTER_GRID_BINDING);
+        self.counter.bind_base(CLUSTER_COUNTER_BINDING);
+
+        self.cull_shader.bind();
+        self.cull_shader
+            .set_uniform_mat4("uCameraView", camera.get_view_matrix());
+        self.cull_shader
+            .set_uniform_uvec3("uClusterGrid", CLUSTER_GRID);
+        self.cull_shader
+            .set_uniform_1i("uLightCount", light_count as i32);
+        unsafe {
+            gl::DispatchCompute(groups, 1, 1);
+            gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT);
+        }
+        self.cull_shader.unbind();
+    }
+
+    /// Binds the cluster light lists and sets the uniforms a lighting shader
+    /// needs to find the cluster of a fragment.
+    pub fn bind(&self, shader: &Shader, camera: &Camera) {
+        self.light_indices.bind_base(CLUSTER_INDICES_BINDING);
+        self.light_grid.bind_base(CLUSTER_GRID_BINDING);
+
+        // slice = log(depth) * scale + bias, the inverse of the slice spacing
+        let log_ratio = (camera.far() / camera.near()).ln();
+        let scale = CLUSTER_GRID.z as f32 / log_ratio;
+        let bias = -(CLUSTER_GRID.z as f32) * camera.near().ln() / log_ratio;
+
+        shader.set_uniform_uvec3("uClusterGrid", CLUSTER_GRID);
+        shader.set_uniform_vec2(
+            "uClusterTileSize",
+            self.screen_size() / CLUSTER_GRID.truncate().as_vec2(),
+        );
+        shader.set_uniform_1f("uClusterScale", scale);
+        shader.set_uniform_1f("uClusterBias", bias);
+        shader.set_uniform_1i("uClusterHeatmap", self.debug_heatmap as i32);
+    }
+
+    fn screen_size(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32)
+    }
+}
+
+fn cluster_count() -> usize {
+    (CLUSTER_GRID.x * CLUSTER_GRID.y * CLUSTER_GRID.z) as usize
+}
@@ -0,0 +1,224 @@
+use log::warn;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// How the lit scene is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderPath {
+    /// Meshes are lit while they are drawn
+    Forward,
+    /// Meshes fill a G-buffer lit afterwards in a fullscreen pass
+    Deferred,
+}
+
+impl FromStr for RenderPath {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "forward" => Ok(RenderPath::Forward),
+            "deferred" => Ok(RenderPath::Deferred),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How transparent meshes are blended together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransparencyMode {
+    /// Drawn from back to front, wrong where meshes intersect
+    Sorted,
+    /// Weighted blended order-independent transparency
+    WeightedBlended,
+}
+
+impl TransparencyMode {
+    pub fn next(self) -> Self {
+        match self {
+            TransparencyMode::Sorted => TransparencyMode::WeightedBlended,
+            TransparencyMode::WeightedBlended => TransparencyMode::Sorted,
+        }
+    }
+}
+
+impl FromStr for TransparencyMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sorted" => Ok(TransparencyMode::Sorted),
+            "oit" => Ok(TransparencyMode::WeightedBlended),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Startup options, read from the command line arguments.
+#[derive(Debug)]
+pub struct Config {
+    pub render_path: RenderPath,
+    pub transparency: TransparencyMode,
+    /// Directories and archives files are read from, the last ones first
+    pub mounts: Vec<PathBuf>,
+    /// Scene file loaded at startup
+    pub scene: PathBuf,
+    /// Equirectangular HDR image lighting the scene and drawn as the sky
+    pub environment: PathBuf,
+    /// Samples of the off-screen scene target, 0 disables MSAA
+    pub msaa_samples: u32,
+    /// Samples requested for the default framebuffer, 0 disables MSAA
+    pub screen_msaa_samples: u32,
+    /// Size in pixels of each face of the point light shadow cubemaps
+    pub shadow_resolution: u32,
+    /// Size in pixels of each cascade of the directional light shadow map
+    pub cascade_resolution: u32,
+    /// Number of cascades of the directional light shadow map
+    pub cascade_count: usize,
+    /// Maximum number of lights uploaded to the lighting shaders
+    pub max_lights: usize,
+    /// Number of colored point lights orbiting around the scene
+    pub point_lights: usize,
+    /// Samples per pixel of the ambient occlusion, 0 disables it
+    pub ssao_samples: usize,
+    /// Radius in world units around each point searched for occluders
+    pub ssao_radius: f32,
+    /// Exponent darkening the ambient occlusion
+    pub ssao_intensity: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            render_path: RenderPath::Forward,
+            transparency: TransparencyMode::Sorted,
+            mounts: vec![],
+            scene: PathBuf::from("res/scenes/demo.ron"),
+            environment: PathBuf::from("res/environments/sky.hdr"),
+            msaa_samples: 4,
+            screen_msaa_samples: 0,
+            shadow_resolution: 1024,
+            cascade_resolution: 2048,
+            cascade_count: 4,
+            max_lights: 256,
+            point_lights: 16,
+            ssao_samples: 32,
+            ssao_radius: 0.5,
+            ssao_intensity: 1.5,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args() -> Self {
+        Config::parse(std::env::args().skip(1))
+    }
+
+    /// Reads the flags in `args`, only taking the next argument as a value
+    /// for the flags that have one.
+    fn parse(args: impl IntoIterator<Item = String>) -> Self {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next();
+            match arg.as_str() {
+                "--renderer" => parse_value(&arg, value(), &mut config.render_path),
+                "--transparency" => parse_value(&arg, value(), &mut config.transparency),
+                "--mount" => match value() {
+                    Some(value) => config.mounts.push(PathBuf::from(value)),
+                    None => warn!("Missing value for {arg}"),
+                },
+                "--scene" => parse_value(&arg, value(), &mut config.scene),
+                "--environment" => parse_value(&arg, value(), &mut config.environment),
+                "--msaa" => parse_value(&arg, value(), &mut config.msaa_samples),
+                "--screen-msaa" => parse_value(&arg, value(), &mut config.screen_msaa_samples),
+                "--shadow-resolution" => parse_value(&arg, value(), &mut config.shadow_resolution),
+                "--cascade-resolution" => {
+                    parse_value(&arg, value(), &mut config.cascade_resolution)
+                }
+                "--cascades" => parse_value(&arg, value(), &mut config.cascade_count),
+                "--max-lights" => parse_value(&arg, value(), &mut config.max_lights),
+                "--point-lights" => parse_value(&arg, value(), &mut config.point_lights),
+                "--ssao-samples" => parse_value(&arg, value(), &mut config.ssao_samples),
+                "--ssao-radius" => parse_value(&arg, value(), &mut config.ssao_radius),
+                "--ssao-intensity" => parse_value(&arg, value(), &mut config.ssao_intensity),
+                _ => warn!("Unknown argument {arg}"),
+            }
+        }
+
+        config
+    }
+}
+
+fn parse_value<T: FromStr>(arg: &str, value: Option<String>, target: &mut T) {
+    match value.as_deref().map(str::parse) {
+        Some(Ok(value)) => *target = value,
+        Some(Err(_)) => warn!("Invalid value for {arg}, using the default"),
+        None => warn!("Missing value for {arg}, using the default"),
+    }
+}
+
+/// Returns a sample count usable for multisampling: the largest power of two
+/// not greater than `requested` nor `max_samples`, or 0 when MSAA is off.
+pub fn validate_samples(requested: u32, max_samples: u32) -> u32 {
+    if requested <= 1 || max_samples <= 1 {
+        return 0;
+    }
+    let samples = prev_power_of_two(requested.min(max_samples));
+    if samples != requested {
+        warn!("{requested}x MSAA is not supported, using {samples}x instead (max: {max_samples}x)");
+    }
+    samples
+}
+
+/// Rounds `requested` down to a power of two usable for multisampling, or
+/// to 0 when MSAA is off, for counts requested before the limits are known.
+pub fn round_samples(requested: u32) -> u32 {
+    if requested <= 1 {
+        return 0;
+    }
+    let samples = prev_power_of_two(requested);
+    if samples != requested {
+        warn!("{requested}x MSAA is not a power of two, using {samples}x instead");
+    }
+    samples
+}
+
+fn prev_power_of_two(value: u32) -> u32 {
+    1 << (31 - value.leading_zeros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Config {
+        Config::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn unknown_flags_keep_the_next_argument() {
+        let config = parse(&["--foo", "--msaa", "8", "--scene", "res/a.ron"]);
+        assert_eq!(config.msaa_samples, 8);
+        assert_eq!(config.scene, PathBuf::from("res/a.ron"));
+    }
+
+    #[test]
+    fn invalid_values_keep_the_default() {
+        let config = parse(&["--msaa", "many", "--ssao-radius"]);
+        assert_eq!(config.msaa_samples, Config::default().msaa_samples);
+        assert_eq!(config.ssao_radius, Config::default().ssao_radius);
+    }
+
+    #[test]
+    fn samples_are_clamped_to_powers_of_two() {
+        assert_eq!(validate_samples(0, 16), 0);
+        assert_eq!(validate_samples(1, 16), 0);
+        assert_eq!(validate_samples(6, 16), 4);
+        assert_eq!(validate_samples(32, 8), 8);
+        assert_eq!(validate_samples(4, 0), 0);
+        assert_eq!(round_samples(1), 0);
+        assert_eq!(round_samples(12), 8);
+        assert_eq!(round_samples(64), 64);
+    }
+}
@@ -0,0 +1,320 @@
+use glam::Vec3;
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+
+/// First bytes of a cooked mesh file.
+pub const MESH_MAGIC: &[u8; 4] = b"CMSH";
+/// First bytes of a cooked texture file.
+pub const TEXTURE_MAGIC: &[u8; 4] = b"CTEX";
+/// Version of both formats, following their magic, increased whenever the
+/// layout of `Vertex` or of the files changes.
+pub const VERSION: u32 = 1;
+/// Bytes of a vertex in cooked meshes, the size of `Vertex`.
+pub const VERTEX_SIZE: usize = 15 * size_of::<f32>();
+/// Bytes of a part with no vertices in cooked meshes.
+const PART_HEADER_SIZE: usize = 2 * 4 + 6 * 4;
+
+/// Layout of the texels of a texture, written as its discriminant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 8 bits RGBA
+    Rgba8 = 0,
+    /// 4x4 blocks of 16 bytes compressed with DXT5 (BC3)
+    Dxt5 = 1,
+}
+
+impl PixelFormat {
+    /// Bytes of the mipmap `level` of a texture of `width` by `height`
+    /// texels.
+    pub fn level_size(self, width: u32, height: u32, level: usize) -> usize {
+        let width = (width >> level).max(1) as usize;
+        let height = (height >> level).max(1) as usize;
+        match self {
+            PixelFormat::Rgba8 => width * height * 4,
+            PixelFormat::Dxt5 => width.div_ceil(4) * height.div_ceil(4) * 16,
+        }
+    }
+}
+
+/// Part of a cooked mesh drawn with a single material, whose buffers are
+/// uploaded as they are read.
+pub struct CookedPart {
+    /// Bounding box of the vertices
+    pub min: Vec3,
+    pub max: Vec3,
+    /// Vertices laid out as `Vertex`
+    pub vertices: Vec<u8>,
+    /// Little endian `u32` triangle indices
+    pub indices: Vec<u8>,
+}
+
+/// Texture with its mipmaps, starting from the bottom row.
+pub struct CookedTexture {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    /// Texels of each mipmap level, from the full size one
+    pub levels: Vec<Vec<u8>>,
+}
+
+/// Reads a mesh cooked by the `cook` binary:
+///
+/// - `MESH_MAGIC`, `VERSION` and the number of parts as `u32`
+/// - for each part, its vertex and index counts as `u32`, the minimum and
+///   maximum corners of its bounding box as 3 `f32`, then the vertices and
+///   the indices
+///
+/// All numbers are little endian. Parts whose indices are not triangles of
+/// their vertices are rejected.
+pub fn read_mesh(mut bytes: &[u8]) -> io::Result<Vec<CookedPart>> {
+    read_header(&mut bytes, MESH_MAGIC)?;
+    let count = read_u32(&mut bytes)? as usize;
+    let mut parts = Vec::with_capacity(count.min(bytes.len() / PART_HEADER_SIZE));
+    for _ in 0..count {
+        let vertex_count = read_u32(&mut bytes)? as usize;
+        let index_count = read_u32(&mut bytes)? as usize;
+        if !index_count.is_multiple_of(3) {
+            return Err(invalid_data("indices are not triangles"));
+        }
+        let min = read_vec3(&mut bytes)?;
+        let max = read_vec3(&mut bytes)?;
+        let vertices = read_bytes(&mut bytes, vertex_count * VERTEX_SIZE)?;
+        let indices = read_bytes(&mut bytes, index_count * size_of::<u32>())?;
+        let out_of_range = indices
+            .chunks_exact(4)
+            .any(|index| u32::from_le_bytes(index.try_into().unwrap()) as usize >= vertex_count);
+        if out_of_range {
+            return Err(invalid_data("index out of the vertices"));
+        }
+        parts.push(CookedPart {
+            min,
+            max,
+            vertices,
+            indices,
+        });
+    }
+    Ok(parts)
+}
+
+/// Writes the parts in the format described on `read_mesh`.
+// Only the cook binary writes cooked files
+#[allow(dead_code)]
+pub fn write_mesh(writer: &mut impl Write, parts: &[CookedPart]) -> io::Result<()> {
+    writer.write_all(MESH_MAGIC)?;
+    write_u32(writer, VERSION)?;
+    write_u32(writer, parts.len() as u32)?;
+    for part in parts {
+        write_u32(writer, (part.vertices.len() / VERTEX_SIZE) as u32)?;
+        write_u32(writer, (part.indices.len() / size_of::<u32>()) as u32)?;
+        for value in part.min.to_array().iter().chain(&part.max.to_array()) {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&part.vertices)?;
+        writer.write_all(&part.indices)?;
+    }
+    Ok(())
+}
+
+/// Reads a texture cooked by the `cook` binary:
+///
+/// - `TEXTURE_MAGIC` and `VERSION` as `u32`
+/// - the width and height as `u32`, the `PixelFormat` as a byte and the
+///   number of levels as `u32`
+/// - for each level, its size in bytes as `u32` then its texels
+///
+/// All numbers are little endian. Textures with more levels than a full
+/// mipmap chain or whose levels do not have the size of their dimensions
+/// are rejected.
+pub fn read_texture(mut bytes: &[u8]) -> io::Result<CookedTexture> {
+    read_header(&mut bytes, TEXTURE_MAGIC)?;
+    let width = read_u32(&mut bytes)?;
+    let height = read_u32(&mut bytes)?;
+    let format = match read_bytes(&mut bytes, 1)?[0] {
+        0 => PixelFormat::Rgba8,
+        1 => PixelFormat::Dxt5,
+        _ => return Err(invalid_data("unknown pixel format")),
+    };
+    let count = read_u32(&mut bytes)? as usize;
+    let max_count = (u32::BITS - width.max(height).leading_zeros()) as usize;
+    if count == 0 || count > max_count {
+        return Err(invalid_data("invalid number of mipmap levels"));
+    }
+    let mut levels = Vec::with_capacity(count);
+    for level in 0..count {
+        let size = read_u32(&mut bytes)? as usize;
+        if size != format.level_size(width, height, level) {
+            return Err(invalid_data(
+                "mipmap level size differs from its dimensions",
+            ));
+        }
+        levels.push(read_bytes(&mut bytes, size)?);
+    }
+    Ok(CookedTexture {
+        width,
+        height,
+        format,
+        levels,
+    })
+}
+
+/// Writes the texture in the format described on `read_texture`.
+#[allow(dead_code)]
+pub fn write_texture(writer: &mut impl Write, texture: &CookedTexture) -> io::Result<()> {
+    writer.write_all(TEXTURE_MAGIC)?;
+    write_u32(writer, VERSION)?;
+    write_u32(writer, texture.width)?;
+    write_u32(writer, texture.height)?;
+    writer.write_all(&[texture.format as u8])?;
+    write_u32(writer, texture.levels.len() as u32)?;
+    for level in &texture.levels {
+        write_u32(writer, level.len() as u32)?;
+        writer.write_all(level)?;
+    }
+    Ok(())
+}
+
+fn read_header(reader: &mut impl Read, magic: &[u8; 4]) -> io::Result<()> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    if &bytes != magic {
+        return Err(invalid_data("not a cooked file of this kind"));
+    }
+    let version = read_u32(reader)?;
+    if version != VERSION {
+        return Err(invalid_data(&format!(
+            "cooked with version {version} instead of {VERSION}, cook it again"
+        )));
+    }
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Next `size` bytes, checked to be there before being allocated.
+fn read_bytes(bytes: &mut &[u8], size: usize) -> io::Result<Vec<u8>> {
+    if size > bytes.len() {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let (read, rest) = bytes.split_at(size);
+    *bytes = rest;
+    Ok(read.to_vec())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_u32(writer: &mut impl Write, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_vec3(reader: &mut impl Read) -> io::Result<Vec3> {
+    let mut values = [0.0; 3];
+    for value in &mut values {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        *value = f32::from_le_bytes(bytes);
+    }
+    Ok(Vec3::from(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> CookedPart {
+        CookedPart {
+            min: Vec3::ZERO,
+            max: Vec3::ONE,
+            vertices: (0..3 * VERTEX_SIZE as u8).collect(),
+            indices: [0u32, 1, 2]
+                .iter()
+                .flat_map(|index| index.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    fn mesh_bytes(parts: &[CookedPart]) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_mesh(&mut bytes, parts).unwrap();
+        bytes
+    }
+
+    fn texture_bytes(texture: &CookedTexture) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_texture(&mut bytes, texture).unwrap();
+        bytes
+    }
+
+    fn is_invalid<T>(result: io::Result<T>) -> bool {
+        result.is_err_and(|err| err.kind() == io::ErrorKind::InvalidData)
+    }
+
+    #[test]
+    fn meshes_round_trip() {
+        let parts = read_mesh(&mesh_bytes(&[triangle(), triangle()])).unwrap();
+        assert_eq!(parts.len(), 2);
+        let expected = triangle();
+        assert_eq!((parts[1].min, parts[1].max), (expected.min, expected.max));
+        assert_eq!(parts[1].vertices, expected.vertices);
+        assert_eq!(parts[1].indices, expected.indices);
+    }
+
+    #[test]
+    fn meshes_need_triangles_of_their_vertices() {
+        let mut part = triangle();
+        part.indices[8] = 3;
+        assert!(is_invalid(read_mesh(&mesh_bytes(&[part]))));
+
+        let mut part = triangle();
+        part.indices.truncate(8);
+        assert!(is_invalid(read_mesh(&mesh_bytes(&[part]))));
+
+        // A part count larger than the file is not allocated
+        let mut bytes = mesh_bytes(&[triangle()]);
+        bytes[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_mesh(&bytes).is_err());
+    }
+
+    #[test]
+    fn textures_round_trip() {
+        let rgba = CookedTexture {
+            width: 4,
+            height: 2,
+            format: PixelFormat::Rgba8,
+            levels: vec![vec![1; 32], vec![2; 8], vec![3; 4]],
+        };
+        let read = read_texture(&texture_bytes(&rgba)).unwrap();
+        assert_eq!((read.width, read.height), (4, 2));
+        assert_eq!(read.format, PixelFormat::Rgba8);
+        assert_eq!(read.levels, rgba.levels);
+
+        // Blocks of 4x4 texels, partial ones included
+        let dxt5 = CookedTexture {
+            width: 8,
+            height: 8,
+            format: PixelFormat::Dxt5,
+            levels: vec![vec![0; 64], vec![0; 16], vec![0; 16], vec![0; 16]],
+        };
+        assert_eq!(read_texture(&texture_bytes(&dxt5)).unwrap().levels.len(), 4);
+    }
+
+    #[test]
+    fn textures_need_levels_of_their_size() {
+        let texture = |levels| CookedTexture {
+            width: 2,
+            height: 2,
+            format: PixelFormat::Rgba8,
+            levels,
+        };
+        let read = |levels| read_texture(&texture_bytes(&texture(levels)));
+        assert!(read(vec![vec![0; 16], vec![0; 4]]).is_ok());
+        assert!(is_invalid(read(vec![vec![0; 16], vec![0; 8]])));
+        assert!(is_invalid(read(vec![vec![0; 16], vec![0; 4], vec![0; 4]])));
+        assert!(is_invalid(read(vec![])));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::framebuffer::Framebuffer;
+use crate::{gl, Camera, Shader, Vao};
+use std::path::Path;
+
+// Texture units of the G-buffer in the lighting pass, after the shadow maps
+// and material textures
+const ALBEDO_UNIT: u32 = 8;
+const NORMAL_UNIT: u32 = 9;
+const MATERIAL_UNIT: u32 = 10;
+const EMISSIVE_UNIT: u32 = 11;
+const DEPTH_UNIT: u32 = 12;
+
+/// Index of the world space normal attachment of the G-buffer.
+pub const NORMAL_ATTACHMENT: usize = 1;
+
+/// What the lighting pass outputs: the lit scene or one G-buffer channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GBufferView {
+    Lit = 0,
+    Albedo = 1,
+    Normal = 2,
+    Material = 3,
+    Emissive = 4,
+    Depth = 5,
+}
+
+impl GBufferView {
+    pub fn next(self) -> Self {
+        match self {
+            GBufferView::Lit => GBufferView::Albedo,
+            GBufferView::Albedo => GBufferView::Normal,
+            GBufferView::Normal => GBufferView::Material,
+            GBufferView::Material => GBufferView::Emissive,
+            GBufferView::Emissive => GBufferView::Depth,
+            GBufferView::Depth => GBufferView::Lit,
+        }
+    }
+}
+
+/// Renders the scene's surface attributes to a G-buffer, then lights every
+/// pixel in a single fullscreen pass.
+///
+/// G-buffer layout:
+/// - 0: linear albedo (RGBA16F)
+/// - 1: world space normal (RGBA16F)
+/// - 2: r: metallic, g: roughness, b: occlusion, a: shader variant (RGBA8)
+/// - 3: emitted color (RGBA16F)
+/// - depth (DEPTH24_STENCIL8), from which positions are reconstructed
+pub struct DeferredRenderer {
+    gbuffer: Framebuffer,
+    geometry_shader: Shader,
+    lighting_shader: Shader,
+    vao: Vao,
+    pub view: GBufferView,
+}
+
+impl DeferredRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let gbuffer = Framebuffer::new(width, height)
+            .with_color_attachment(gl::RGBA16F)
+            .with_color_attachment(gl::RGBA16F)
+            .with_color_attachment(gl::RGBA8)
+            .with_color_attachment(gl::RGBA16F)
+            .with_depth_attachment();
+
+        let geometry_shader = Shader::new(
+            Path::new("shaders/default.vert"),
+            Path::new("shaders/deferred/gbuffer.frag"),
+        );
+
+        let lighting_shader = Shader::new(
+            Path::new("shaders/post/fullscreen.vert"),
+            Path::new("shaders/deferred/lighting.frag"),
+        );
+        lighting_shader.bind();
+        lighting_shader.set_uniform_1i("uGBufferAlbedo", ALBEDO_UNIT as i32);
+        lighting_shader.set_uniform_1i("uGBufferNormal", NORMAL_UNIT as i32);
+        lighting_shader.set_uniform_1i("uGBufferMaterial", MATERIAL_UNIT as i32);
+        lighting_shader.set_uniform_1i("uGBufferEmissive", EMISSIVE_UNIT as i32);
+        lighting_shader.set_uniform_1i("uGBufferDepth", DEPTH_UNIT as i32);
+        lighting_shader.unbind();
+
+        DeferredRenderer {
+            gbuffer,
+            geometry_shader,
+            lighting_shader,
+            vao: Vao::new(),
+            view: GBufferView::Lit,
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.gbuffer.resize(width, height);
+    }
+
+    pub fn gbuffer(&self) -> &Framebuffer {
+        &self.gbuffer
+    }
+
+    /// Fills the G-buffer, `draw` sets `uModel`, the material uniforms and
+    /// `uAlphaCutoff` and draws the opaque meshes.
+    pub fn geometry_pass(&mut self, camera: &Camera, mut draw: impl FnMut(&Shader)) {
+        self.gbuffer.bind();
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+
+        self.geometry_shader.bind();
+        self.geometry_shader
+            .set_uniform_mat4("uCameraViewProjection", camera.get_matrix());
+        draw(&self.geometry_shader);
+        self.geometry_shader.unbind();
+    }
+
+    /// Lights the G-buffer into `target`, which receives the G-buffer depth
+    /// so that forward passes can be drawn on top. `bind_lighting` sets the
+    /// light, shadow and cluster uniforms.
+    pub fn lighting_pass(
+        &mut self,
+        target: &Framebuffer,
+        camera: &Camera,
+        bind_lighting: impl FnOnce(&Shader),
+    ) {
+        self.gbuffer.copy_depth_into(target);
+        target.bind();
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::Disable(gl::DEPTH_TEST);
+        }
+
+        self.gbuffer.bind_color_texture(0, ALBEDO_UNIT);
+        self.gbuffer
+            .bind_color_texture(NORMAL_ATTACHMENT, NORMAL_UNIT);
+        self.gbuffer.bind_color_texture(2, MATERIAL_UNIT);
+        self.gbuffer.bind_color_texture(3, EMISSIVE_UNIT);
+        self.gbuffer.bind_depth_texture(DEPTH_UNIT);
+
+        self.lighting_shader.bind();
+        self.lighting_shader
+            .set_uniform_mat4("uInverseViewProjection", camera.get_matrix().inverse());
+        self.lighting_shader
+            .set_uniform_1f("uCameraFar", camera.far());
+        self.lighting_shader
+            .set_uniform_1i("uGBufferView", self.view as i32);
+        bind_lighting(&self.lighting_shader);
+
+        self.vao.bind();
+        unsafe {
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            gl::Enable(gl::DEPTH_TEST);
+        }
+        self.vao.unbind();
+        self.lighting_shader.unbind();
+    }
+}
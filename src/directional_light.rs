@@ -0,0 +1,50 @@
+use crate::shadow::CascadedShadowMap;
+use crate::{Camera, Shader};
+use glam::{Mat4, Vec3};
+
+/// Light infinitely far away, like the sun, lighting the whole scene from a
+/// single direction.
+pub struct DirectionalLight {
+    /// Direction in which the light travels
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub shadow_map: Option<CascadedShadowMap>,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        DirectionalLight {
+            direction: direction.normalize(),
+            color,
+            intensity,
+            shadow_map: None,
+        }
+    }
+
+    pub fn with_shadow_map(mut self, shadow_map: CascadedShadowMap) -> Self {
+        self.shadow_map = Some(shadow_map);
+        self
+    }
+
+    /// Orients the light along the -Z axis of `transform`.
+    pub fn set_world_transform(&mut self, transform: Mat4) {
+        self.direction = transform.transform_vector3(-Vec3::Z).normalize();
+    }
+
+    /// Updates the cascades, if any, to cover the view frustum of `camera`.
+    pub fn render_shadow_map(&mut self, camera: &Camera, draw_casters: impl FnMut(&Shader)) {
+        if let Some(shadow_map) = &mut self.shadow_map {
+            shadow_map.render(camera, self.direction, draw_casters);
+        }
+    }
+
+    /// Sets the cascade uniforms of a lighting shader, binding them to
+    /// `shadow_unit` if the light casts shadows.
+    pub fn bind_shadow_map(&self, shader: &Shader, shadow_unit: u32) {
+        match &self.shadow_map {
+            Some(shadow_map) => shadow_map.bind(shader, shadow_unit),
+            None => shader.set_uniform_1i("uCascadeCount", 0),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// Identifier of an entity in its `World`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    /// Number of entities despawned from the same slot before, telling this
+    /// one apart from them
+    generation: u32,
+}
+
+impl Entity {
+    fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+/// Components of one type, indexed by entity.
+type Column<T> = Vec<Option<T>>;
+
+/// Column of any component type.
+trait AnyColumn {
+    /// Drops the component of the entity at `index`.
+    fn clear(&mut self, index: usize);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyColumn for Column<T> {
+    fn clear(&mut self, index: usize) {
+        if let Some(component) = self.get_mut(index) {
+            *component = None;
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Entities carrying components of any type, at most one of each type per
+/// entity.
+///
+/// Components are stored in one array per type, indexed by the slot of
+/// their entity. Queries visit the entities in the order of their slots, the
+/// slots of despawned entities being reused by the next ones.
+#[derive(Default)]
+pub struct World {
+    /// Generation of the entity in each slot, increased when it is despawned
+    generations: Vec<u32>,
+    /// Slots of the despawned entities
+    free: Vec<u32>,
+    columns: HashMap<TypeId, Box<dyn AnyColumn>>,
+}
+
+impl World {
+    pub fn spawn(&mut self) -> Entity {
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.generations.push(0);
+            self.generations.len() as u32 - 1
+        });
+        self.entity(index as usize)
+    }
+
+    /// Drops the components of `entity` and frees its slot, doing nothing if
+    /// it was already despawned.
+    pub fn despawn(&mut self, entity: Entity) {
+        if !self.contains(entity) {
+            return;
+        }
+        for column in self.columns.values_mut() {
+            column.clear(entity.index());
+        }
+        self.generations[entity.index()] += 1;
+        self.free.push(entity.index);
+    }
+
+    /// Whether `entity` was spawned and not despawned yet.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.generations.get(entity.index()) == Some(&entity.generation)
+    }
+
+    /// Adds `component` to `entity`, replacing the one of the same type.
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) {
+        assert!(self.contains(entity), "{entity:?} was despawned");
+        let column = self
+            .columns
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Column::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<Column<T>>()
+            .unwrap();
+        if column.len() <= entity.index() {
+            column.resize_with(entity.index() + 1, || None);
+        }
+        column[entity.index()] = Some(component);
+    }
+
+    /// Takes the `T` component of `entity` away.
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        if !self.contains(entity) {
+            return None;
+        }
+        self.column_mut::<T>()?.get_mut(entity.index())?.take()
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        if !self.contains(entity) {
+            return None;
+        }
+        self.column::<T>()?.get(entity.index())?.as_ref()
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        if !self.contains(entity) {
+            return None;
+        }
+        self.column_mut::<T>()?.get_mut(entity.index())?.as_mut()
+    }
+
+    /// Entities having a `T` component.
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.column::<T>()
+            .into_iter()
+            .flat_map(|column| column.iter().enumerate())
+            .filter_map(|(i, component)| Some((self.entity(i), component.as_ref()?)))
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        let generations = &self.generations;
+        self.columns
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|column| column.as_any_mut().downcast_mut::<Column<T>>())
+            .into_iter()
+            .flat_map(|column| column.iter_mut().enumerate())
+            .filter_map(move |(i, component)| {
+                let entity = Entity {
+                    index: i as u32,
+                    generation: generations[i],
+                };
+                Some((entity, component.as_mut()?))
+            })
+    }
+
+    /// Entities having both an `A` and a `B` component.
+    pub fn query2<A: 'static, B: 'static>(&self) -> impl Iterator<Item = (Entity, &A, &B)> {
+        self.query::<A>()
+            .filter_map(|(entity, a)| Some((entity, a, self.get::<B>(entity)?)))
+    }
+
+    /// Runs `system` on each `T` component, with read access to the other
+    /// components of the world. `T` components are not visible to `system`
+    /// through the world meanwhile.
+    pub fn for_each_mut<T: 'static>(&mut self, mut system: impl FnMut(Entity, &mut T, &World)) {
+        let mut column = match self.columns.remove(&TypeId::of::<T>()) {
+            Some(column) => column,
+            None => return,
+        };
+        let components = column.as_any_mut().downcast_mut::<Column<T>>().unwrap();
+        for (i, component) in components.iter_mut().enumerate() {
+            if let Some(component) = component {
+                system(self.entity(i), component, self);
+            }
+        }
+        self.columns.insert(TypeId::of::<T>(), column);
+    }
+
+    /// Entity currently in the slot at `index`.
+    fn entity(&self, index: usize) -> Entity {
+        Entity {
+            index: index as u32,
+            generation: self.generations[index],
+        }
+    }
+
+    fn column<T: 'static>(&self) -> Option<&Column<T>> {
+        self.columns
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref()
+    }
+
+    fn column_mut<T: 'static>(&mut self) -> Option<&mut Column<T>> {
+        self.columns
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_replaces_the_component() {
+        let mut world = World::default();
+        let entity = world.spawn();
+        world.insert(entity, 1u32);
+        world.insert(entity, 2u32);
+        assert_eq!(world.get::<u32>(entity), Some(&2));
+        assert_eq!(world.query::<u32>().count(), 1);
+    }
+
+    #[test]
+    fn remove_takes_the_component() {
+        let mut world = World::default();
+        let entity = world.spawn();
+        world.insert(entity, 1u32);
+        world.insert(entity, "name");
+        assert_eq!(world.remove::<u32>(entity), Some(1));
+        assert_eq!(world.remove::<u32>(entity), None);
+        assert_eq!(world.get::<&str>(entity), Some(&"name"));
+    }
+
+    #[test]
+    fn query2_needs_both_components() {
+        let mut world = World::default();
+        let both = world.spawn();
+        world.insert(both, 1u32);
+        world.insert(both, 1.0f32);
+        let first = world.spawn();
+        world.insert(first, 2u32);
+        let second = world.spawn();
+        world.insert(second, 2.0f32);
+
+        let found: Vec<_> = world.query2::<u32, f32>().collect();
+        assert_eq!(found, [(both, &1, &1.0)]);
+    }
+
+    #[test]
+    fn for_each_mut_hides_its_column() {
+        let mut world = World::default();
+        for i in 0..3u32 {
+            let entity = world.spawn();
+            world.insert(entity, i);
+            world.insert(entity, i as f32);
+        }
+        world.for_each_mut::<u32>(|entity, value, world| {
+            assert!(world.get::<u32>(entity).is_none());
+            assert_eq!(world.query::<u32>().count(), 0);
+            *value += *world.get::<f32>(entity).unwrap() as u32;
+        });
+        let values: Vec<_> = world.query::<u32>().map(|(_, &value)| value).collect();
+        assert_eq!(values, [0, 2, 4]);
+    }
+
+    #[test]
+    fn despawn_clears_every_column() {
+        let mut world = World::default();
+        let entity = world.spawn();
+        world.insert(entity, 1u32);
+        world.insert(entity, 1.0f32);
+        world.despawn(entity);
+        assert!(!world.contains(entity));
+        assert_eq!(world.query::<u32>().count(), 0);
+        assert_eq!(world.query::<f32>().count(), 0);
+
+        // The slot is reused without handing the old components or id out
+        let reused = world.spawn();
+        assert_eq!(reused.index(), entity.index());
+        assert_ne!(reused, entity);
+        assert!(world.get::<u32>(reused).is_none());
+        world.insert(reused, 2u32);
+        assert!(world.get::<u32>(entity).is_none());
+        world.despawn(entity);
+        assert!(world.contains(reused));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{gl, vfs, Camera, Shader, Vao};
+use glam::{Mat3, Mat4};
+use image::codecs::hdr::HdrDecoder;
+use image::ImageResult;
+use std::io::Cursor;
+use std::path::Path;
+
+const ENVIRONMENT_SIZE: u32 = 512;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+/// Roughness goes from 0 on the first mip to 1 on the last one
+const PREFILTERED_MIPS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 512;
+
+// Texture units of the lighting shaders, after the G-buffer
+const IRRADIANCE_UNIT: u32 = 13;
+const PREFILTERED_UNIT: u32 = 14;
+const BRDF_LUT_UNIT: u32 = 15;
+const ENVIRONMENT_UNIT: u32 = 0;
+
+/// Invocations per work group along x and y of the precomputation shaders.
+const WORK_GROUP_SIZE: u32 = 8;
+
+/// Distant lighting captured in an equirectangular HDR image, precomputed
+/// for the split sum approximation of image based lighting and drawn as the
+/// sky behind the scene.
+pub struct Environment {
+    environment: u32,
+    /// Cosine weighted incoming radiance, for diffuse reflection
+    irradiance: u32,
+    /// Incoming radiance convolved with GGX lobes of increasing roughness
+    prefiltered: u32,
+    /// Scale and bias applied to F0, by view angle and roughness
+    brdf_lut: u32,
+    skybox_shader: Shader,
+    vao: Vao,
+    pub intensity: f32,
+}
+
+impl Environment {
+    pub fn from_equirectangular(path: &Path) -> ImageResult<Self> {
+        let decoder = HdrDecoder::new(Cursor::new(vfs::read(path)?))?;
+        let metadata = decoder.metadata();
+        let texels = decoder.read_image_hdr()?;
+
+        let environment;
+        let irradiance;
+        let prefiltered;
+        let mut brdf_lut = 0;
+        unsafe {
+            let mut equirectangular = 0;
+            gl::CreateTextures(gl::TEXTURE_2D, 1, &mut equirectangular);
+            gl::TextureStorage2D(
+                equirectangular,
+                1,
+                gl::RGB32F,
+                metadata.width as i32,
+                metadata.height as i32,
+            );
+            gl::TextureSubImage2D(
+                equirectangular,
+                0,
+                0,
+                0,
+                metadata.width as i32,
+                metadata.height as i32,
+                gl::RGB,
+                gl::FLOAT,
+                texels.as_ptr() as *const _,
+            );
+            gl::TextureParameteri(equirectangular, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TextureParameteri(equirectangular, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TextureParameteri(equirectangular, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TextureParameteri(
+                equirectangular,
+                gl::TEXTURE_WRAP_T,
+                gl::CLAMP_TO_EDGE as i32,
+            );
+
+            environment = create_cubemap(ENVIRONMENT_SIZE, mip_count(ENVIRONMENT_SIZE));
+            let shader = Shader::compute(Path::new("shaders/ibl/equirectangular_to_cube.comp"));
+            shader.bind();
+            gl::BindTextureUnit(0, equirectangular);
+            shader.set_uniform_1i("uEquirectangular", 0);
+            dispatch_cube(&shader, environment, 0, ENVIRONMENT_SIZE);
+            gl::GenerateTextureMipmap(environment);
+            gl::DeleteTextures(1, &equirectangular);
+
+            irradiance = create_cubemap(IRRADIANCE_SIZE, 1);
+            let shader = Shader::compute(Path::new("shaders/ibl/irradiance.comp"));
+            shader.bind();
+            gl::BindTextureUnit(0, environment);
+            shader.set_uniform_1i("uEnvironment", 0);
+            dispatch_cube(&shader, irradiance, 0, IRRADIANCE_SIZE);
+
+            prefiltered = create_cubemap(PREFILTERED_SIZE, PREFILTERED_MIPS);
+            let shader = Shader::compute(Path::new("shaders/ibl/prefilter.comp"));
+            shader.bind();
+            shader.set_uniform_1i("uEnvironment", 0);
+            shader.set_uniform_1f("uEnvironmentSize", ENVIRONMENT_SIZE as f32);
+            for mip in 0..PREFILTERED_MIPS {
+                let roughness = mip as f32 / (PREFILTERED_MIPS - 1) as f32;
+                shader.set_uniform_1f("uRoughness", roughness);
+                dispatch_cube(&shader, prefiltered, mip, PREFILTERED_SIZE >> mip);
+            }
+
+            gl::CreateTextures(gl::TEXTURE_2D, 1, &mut brdf_lut);
+            gl::TextureStorage2D(
+                brdf_lut,
+                1,
+                gl::RG16F,
+                BRDF_LUT_SIZE as i32,
+                BRDF_LUT_SIZE as i32,
+            );
+            gl::TextureParameteri(brdf_lut, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TextureParameteri(brdf_lut, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TextureParameteri(brdf_lut, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(brdf_lut, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            let shader = Shader::compute(Path::new("shaders/ibl/brdf_lut.comp"));
+            shader.bind();
+            shader.set_uniform_1i("uSize", BRDF_LUT_SIZE as i32);
+            gl::BindImageTexture(0, brdf_lut, 0, gl::FALSE, 0, gl::WRITE_ONLY, gl::RG16F);
+            let groups = BRDF_LUT_SIZE.div_ceil(WORK_GROUP_SIZE);
+            gl::DispatchCompute(groups, groups, 1);
+            gl::MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT);
+            shader.unbind();
+        }
+
+        let skybox_shader = Shader::new(
+            Path::new("shaders/ibl/skybox.vert"),
+            Path::new("shaders/ibl/skybox.frag"),
+        );
+        skybox_shader.bind();
+        skybox_shader.set_uniform_1i("uEnvironment", ENVIRONMENT_UNIT as i32);
+        skybox_shader.unbind();
+
+        Ok(Environment {
+            environment,
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            skybox_shader,
+            vao: Vao::new(),
+            intensity: 1.0,
+        })
+    }
+
+    /// Binds the precomputed maps to a lighting shader.
+    pub fn bind(&self, shader: &Shader) {
+        unsafe {
+            gl::BindTextureUnit(IRRADIANCE_UNIT, self.irradiance);
+            gl::BindTextureUnit(PREFILTERED_UNIT, self.prefiltered);
+            gl::BindTextureUnit(BRDF_LUT_UNIT, self.brdf_lut);
+        }
+        shader.set_uniform_1i("uIrradianceMap", IRRADIANCE_UNIT as i32);
+        shader.set_uniform_1i("uPrefilteredMap", PREFILTERED_UNIT as i32);
+        shader.set_uniform_1i("uBrdfLut", BRDF_LUT_UNIT as i32);
+        shader.set_uniform_1f("uPrefilteredMaxLod", (PREFILTERED_MIPS - 1) as f32);
+        shader.set_uniform_1f("uEnvironmentIntensity", self.intensity);
+    }
+
+    /// Draws the environment behind everything already in the bound
+    /// framebuffer.
+    pub fn draw_skybox(&mut self, camera: &Camera) {
+        let rotation = Mat4::from_mat3(Mat3::from_mat4(camera.get_view_matrix()));
+        let inverse_view_projection = (camera.get_projection_matrix() * rotation).inverse();
+
+        self.skybox_shader.bind();
+        self.skybox_shader
+            .set_uniform_mat4("uInverseViewProjection", inverse_view_projection);
+        self.skybox_shader
+            .set_uniform_1f("uEnvironmentIntensity", self.intensity);
+        self.vao.bind();
+        unsafe {
+            gl::BindTextureUnit(ENVIRONMENT_UNIT, self.environment);
+            gl::DepthFunc(gl::LEQUAL);
+            gl::DepthMask(gl::FALSE);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            gl::DepthMask(gl::TRUE);
+            gl::DepthFunc(gl::LESS);
+        }
+        self.vao.unbind();
+        self.skybox_shader.unbind();
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        let textures = [
+            self.environment,
+            self.irradiance,
+            self.prefiltered,
+            self.brdf_lut,
+        ];
+        unsafe {
+            gl::DeleteTextures(textures.len() as i32, textures.as_ptr());
+        }
+    }
+}
+
+fn mip_count(size: u32) -> u32 {
+    32 - size.leading_zeros()
+}
+
+unsafe fn create_cubemap(size: u32, mips: u32) -> u32 {
+    let mut id = 0;
+    gl::CreateTextures(gl::TEXTURE_CUBE_MAP, 1, &mut id);
+    gl::TextureStorage2D(id, mips as i32, gl::RGBA16F, size as i32, size as i32);
+    let min_filter = if mips > 1 {
+        gl::LINEAR_MIPMAP_LINEAR
+    } else {
+        gl::LINEAR
+    };
+    gl::TextureParameteri(id, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+    gl::TextureParameteri(id, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    gl::TextureParameteri(id, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TextureParameteri(id, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    gl::TextureParameteri(id, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+    id
+}
+
+/// Runs the bound compute shader over the 6 faces of a cubemap mip.
+unsafe fn dispatch_cube(shader: &Shader, cubemap: u32, mip: u32, size: u32) {
+    shader.set_uniform_1i("uSize", size as i32);
+    gl::BindImageTexture(
+        0,
+        cubemap,
+        mip as i32,
+        gl::TRUE,
+        0,
+        gl::WRITE_ONLY,
+        gl::RGBA16F,
+    );
+    let groups = size.div_ceil(WORK_GROUP_SIZE);
+    gl::DispatchCompute(groups, groups, 6);
+    gl::MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT | gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+}
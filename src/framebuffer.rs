@@ -0,0 +1,227 @@
+use crate::gl;
+use log::error;
+
+/// Off-screen render target made of color texture attachments and an
+/// optional depth-stencil texture, multisampled when `samples` is not 0.
+#[derive(Debug)]
+pub struct Framebuffer {
+    pub id: u32,
+    width: u32,
+    height: u32,
+    samples: u32,
+    color_formats: Vec<u32>,
+    color_textures: Vec<u32>,
+    has_depth: bool,
+    depth_texture: u32,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::CreateFramebuffers(1, &mut id);
+        }
+        Framebuffer {
+            id,
+            width,
+            height,
+            samples: 0,
+            color_formats: vec![],
+            color_textures: vec![],
+            has_depth: false,
+            depth_texture: 0,
+        }
+    }
+
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.samples = samples;
+        self.create_attachments();
+        self
+    }
+
+    pub fn with_color_attachment(mut self, internal_format: u32) -> Self {
+        self.color_formats.push(internal_format);
+        self.create_attachments();
+        self
+    }
+
+    pub fn with_depth_attachment(mut self) -> Self {
+        self.has_depth = true;
+        self.create_attachments();
+        self
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            gl::Viewport(0, 0, self.width as i32, self.height as i32);
+        }
+    }
+
+    pub fn unbind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    pub fn bind_color_texture(&self, index: usize, unit: u32) {
+        unsafe {
+            gl::BindTextureUnit(unit, self.color_textures[index]);
+        }
+    }
+
+    pub fn bind_depth_texture(&self, unit: u32) {
+        unsafe {
+            gl::BindTextureUnit(unit, self.depth_texture);
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+        self.create_attachments();
+    }
+
+    /// Resolves the first color attachment and the depth attachment into
+    /// `target`, which must have the same size.
+    pub fn resolve_into(&self, target: &Framebuffer) {
+        let mut mask = gl::COLOR_BUFFER_BIT;
+        if self.has_depth && target.has_depth {
+            mask |= gl::DEPTH_BUFFER_BIT;
+        }
+        self.blit_into(target, mask);
+    }
+
+    /// Copies the depth attachment into the one of `target`, which must have
+    /// the same size.
+    pub fn copy_depth_into(&self, target: &Framebuffer) {
+        self.blit_into(target, gl::DEPTH_BUFFER_BIT);
+    }
+
+    fn blit_into(&self, target: &Framebuffer, mask: u32) {
+        unsafe {
+            gl::BlitNamedFramebuffer(
+                self.id,
+                target.id,
+                0,
+                0,
+                self.width as i32,
+                self.height as i32,
+                0,
+                0,
+                target.width as i32,
+                target.height as i32,
+                mask,
+                gl::NEAREST,
+            );
+        }
+    }
+
+    fn create_attachments(&mut self) {
+        self.delete_attachments();
+
+        unsafe {
+            let mut draw_buffers = Vec::with_capacity(self.color_formats.len());
+            for (i, format) in self.color_formats.iter().enumerate() {
+                let texture = self.create_texture(*format);
+                if self.samples == 0 {
+                    gl::TextureParameteri(texture, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+                    gl::TextureParameteri(texture, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+                    gl::TextureParameteri(texture, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+                    gl::TextureParameteri(texture, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+                }
+                gl::NamedFramebufferTexture(self.id, gl::COLOR_ATTACHMENT0 + i as u32, texture, 0);
+                draw_buffers.push(gl::COLOR_ATTACHMENT0 + i as u32);
+                self.color_textures.push(texture);
+            }
+            if !draw_buffers.is_empty() {
+                gl::NamedFramebufferDrawBuffers(
+                    self.id,
+                    draw_buffers.len() as i32,
+                    draw_buffers.as_ptr(),
+                );
+            }
+
+            if self.has_depth {
+                self.depth_texture = self.create_texture(gl::DEPTH24_STENCIL8);
+                if self.samples == 0 {
+                    let texture = self.depth_texture;
+                    gl::TextureParameteri(texture, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+                    gl::TextureParameteri(texture, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+                }
+                gl::NamedFramebufferTexture(
+                    self.id,
+                    gl::DEPTH_STENCIL_ATTACHMENT,
+                    self.depth_texture,
+                    0,
+                );
+            }
+
+            let status = gl::CheckNamedFramebufferStatus(self.id, gl::FRAMEBUFFER);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                error!("Framebuffer {} is incomplete: 0x{:X}", self.id, status);
+            }
+        }
+    }
+
+    unsafe fn create_texture(&self, internal_format: u32) -> u32 {
+        let mut texture = 0;
+        if self.samples > 0 {
+            gl::CreateTextures(gl::TEXTURE_2D_MULTISAMPLE, 1, &mut texture);
+            gl::TextureStorage2DMultisample(
+                texture,
+                self.samples as i32,
+                internal_format,
+                self.width as i32,
+                self.height as i32,
+                gl::TRUE,
+            );
+        } else {
+            gl::CreateTextures(gl::TEXTURE_2D, 1, &mut texture);
+            gl::TextureStorage2D(
+                texture,
+                1,
+                internal_format,
+                self.width as i32,
+                self.height as i32,
+            );
+        }
+        texture
+    }
+
+    fn delete_attachments(&mut self) {
+        unsafe {
+            if !self.color_textures.is_empty() {
+                gl::DeleteTextures(
+                    self.color_textures.len() as i32,
+                    self.color_textures.as_ptr(),
+                );
+                self.color_textures.clear();
+            }
+            if self.depth_texture != 0 {
+                gl::DeleteTextures(1, &self.depth_texture);
+                self.depth_texture = 0;
+            }
+        }
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        self.delete_attachments();
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+        }
+    }
+}
@@ -0,0 +1,588 @@
+use crate::vertex::Vertex;
+use glam::{Mat3, Mat4, Vec3};
+use std::collections::HashMap;
+
+/// Triangles built or processed on the CPU before being uploaded as a
+/// `Mesh`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        MeshData { vertices, indices }
+    }
+
+    /// Applies `transform` to the positions and to the normals and tangents,
+    /// turning the triangles back outwards if it mirrors them.
+    pub fn transform(&mut self, transform: Mat4) {
+        let tangent_matrix = Mat3::from_mat4(transform);
+        let normal_matrix = tangent_matrix.inverse().transpose();
+        for vertex in &mut self.vertices {
+            vertex.position = transform.transform_point3(vertex.position);
+            vertex.normals = (normal_matrix * vertex.normals).normalize_or_zero();
+            vertex.tangent = (tangent_matrix * vertex.tangent).normalize_or_zero();
+        }
+        if tangent_matrix.determinant() < 0.0 {
+            for triangle in self.indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+            for vertex in &mut self.vertices {
+                vertex.bitangent_sign = -vertex.bitangent_sign;
+            }
+        }
+    }
+}
+
+/// Axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Smallest box containing `points`, empty with `min` above `max` if
+    /// there are none.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(
+            Aabb {
+                min: Vec3::splat(f32::MAX),
+                max: Vec3::splat(f32::MIN),
+            },
+            |aabb, point| Aabb {
+                min: aabb.min.min(point),
+                max: aabb.max.max(point),
+            },
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    /// Center of the box, the origin if it is empty.
+    pub fn center(&self) -> Vec3 {
+        if self.is_empty() {
+            Vec3::ZERO
+        } else {
+            (self.min + self.max) / 2.0
+        }
+    }
+
+    /// Sphere passing through the corners of the box.
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere {
+            center: self.center(),
+            radius: if self.is_empty() {
+                0.0
+            } else {
+                self.min.distance(self.max) / 2.0
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Sphere containing `points`, at most a few percent larger than the
+    /// smallest one (Ritter's algorithm).
+    pub fn from_points(points: &[Vec3]) -> Self {
+        let first = match points.first() {
+            Some(&first) => first,
+            None => {
+                return BoundingSphere {
+                    center: Vec3::ZERO,
+                    radius: 0.0,
+                }
+            }
+        };
+        // Starts from the two points farthest from the first and each other
+        let farthest = |from: Vec3| {
+            points
+                .iter()
+                .copied()
+                .max_by(|a, b| {
+                    a.distance_squared(from)
+                        .total_cmp(&b.distance_squared(from))
+                })
+                .unwrap()
+        };
+        let a = farthest(first);
+        let b = farthest(a);
+        let mut sphere = BoundingSphere {
+            center: (a + b) / 2.0,
+            radius: a.distance(b) / 2.0,
+        };
+        // Grows it just enough to contain each point outside of it
+        for &point in points {
+            let distance = point.distance(sphere.center);
+            if distance > sphere.radius {
+                let radius = (sphere.radius + distance) / 2.0;
+                sphere.center += (point - sphere.center) * ((radius - sphere.radius) / distance);
+                sphere.radius = radius;
+            }
+        }
+        sphere
+    }
+
+    /// Sphere containing this one once transformed, its radius being scaled
+    /// by the largest scale of `transform`.
+    pub fn transformed(&self, transform: Mat4) -> Self {
+        let scale = [transform.x_axis, transform.y_axis, transform.z_axis]
+            .map(|axis| axis.truncate().length())
+            .into_iter()
+            .fold(0.0, f32::max);
+        BoundingSphere {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+
+    pub fn intersects(&self, other: &BoundingSphere) -> bool {
+        self.center.distance_squared(other.center) <= (self.radius + other.radius).powi(2)
+    }
+}
+
+/// Concatenates meshes after applying their transform, to draw them at once.
+pub fn merge<'a>(meshes: impl IntoIterator<Item = (&'a MeshData, Mat4)>) -> MeshData {
+    let mut merged = MeshData::default();
+    for (mesh, transform) in meshes {
+        let mut mesh = mesh.clone();
+        mesh.transform(transform);
+        let offset = merged.vertices.len() as u32;
+        merged.vertices.extend(mesh.vertices);
+        merged
+            .indices
+            .extend(mesh.indices.iter().map(|index| index + offset));
+    }
+    merged
+}
+
+/// Sets the normals of each triangle corner to the average of the normals of
+/// the triangles around its position that are at most `max_angle` radians
+/// from the normal of its own triangle, weighted by their angle at that
+/// position. Vertices are split where their corners get different normals,
+/// a `max_angle` of 0 giving flat normals. Tangents are computed again.
+pub fn smooth_normals(mesh: &mut MeshData, max_angle: f32) {
+    let triangles: Vec<[u32; 3]> = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect();
+    let position = |index: u32| mesh.vertices[index as usize].position;
+    let face_normals: Vec<Vec3> = triangles
+        .iter()
+        .map(|&[a, b, c]| {
+            (position(b) - position(a))
+                .cross(position(c) - position(a))
+                .normalize_or_zero()
+        })
+        .collect();
+
+    // Corners around each position, as triangle and corner in the triangle
+    let mut corners: HashMap<[u32; 3], Vec<(usize, usize)>> = HashMap::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        for (k, &index) in triangle.iter().enumerate() {
+            let key = position(index).to_array().map(f32::to_bits);
+            corners.entry(key).or_default().push((t, k));
+        }
+    }
+    let corner_angle = |t: usize, k: usize| {
+        let triangle = triangles[t];
+        let corner = position(triangle[k]);
+        let to_next = position(triangle[(k + 1) % 3]) - corner;
+        let to_previous = position(triangle[(k + 2) % 3]) - corner;
+        to_next.angle_between(to_previous)
+    };
+
+    // Rounding errors must not split coplanar triangles
+    let min_cos = max_angle.cos() - 1e-5;
+    let mut vertices = Vec::with_capacity(mesh.vertices.len());
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    let mut split: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        for &index in triangle {
+            let vertex = mesh.vertices[index as usize];
+            let key = vertex.position.to_array().map(f32::to_bits);
+            let mut normal = corners[&key]
+                .iter()
+                .filter(|&&(other, _)| face_normals[other].dot(face_normals[t]) >= min_cos)
+                .fold(Vec3::ZERO, |sum, &(other, k)| {
+                    sum + face_normals[other] * corner_angle(other, k)
+                })
+                .normalize_or_zero();
+            // Degenerate triangles keep the normal they had
+            if normal == Vec3::ZERO {
+                normal = vertex.normals;
+            }
+            let new_index = *split
+                .entry((index, normal.to_array().map(f32::to_bits)))
+                .or_insert_with(|| {
+                    vertices.push(Vertex {
+                        normals: normal,
+                        ..vertex
+                    });
+                    vertices.len() as u32 - 1
+                });
+            indices.push(new_index);
+        }
+    }
+    mesh.vertices = vertices;
+    mesh.indices = indices;
+    compute_tangents(&mut mesh.vertices, &mesh.indices);
+}
+
+/// Sets the normals of each triangle to its face normal, splitting the
+/// vertices shared by triangles facing different directions.
+pub fn flat_normals(mesh: &mut MeshData) {
+    smooth_normals(mesh, 0.0);
+}
+
+/// Merges the vertices whose attributes all differ by at most `epsilon`,
+/// then removes the triangles left with less than 3 distinct vertices.
+/// Returns the number of vertices removed.
+pub fn weld(mesh: &mut MeshData, epsilon: f32) -> usize {
+    // Vertices close to one are in its cell of the grid or in a neighbor one
+    let cell_size = epsilon.max(f32::EPSILON);
+    let cell = |position: Vec3| (position / cell_size).floor().to_array().map(|c| c as i64);
+    let close = |a: &Vertex, b: &Vertex| {
+        a.position.abs_diff_eq(b.position, epsilon)
+            && a.normals.abs_diff_eq(b.normals, epsilon)
+            && a.color.abs_diff_eq(b.color, epsilon)
+            && a.texture_coordinates
+                .abs_diff_eq(b.texture_coordinates, epsilon)
+            && a.tangent.abs_diff_eq(b.tangent, epsilon)
+            && a.bitangent_sign == b.bitangent_sign
+    };
+
+    let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(mesh.vertices.len());
+    let mut remap = Vec::with_capacity(mesh.vertices.len());
+    for vertex in &mesh.vertices {
+        let [x, y, z] = cell(vertex.position);
+        let mut neighbors = (-1..=1).flat_map(|dx| {
+            (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [x + dx, y + dy, z + dz]))
+        });
+        let existing = neighbors.find_map(|key| {
+            grid.get(&key)?
+                .iter()
+                .copied()
+                .find(|&index| close(&vertices[index as usize], vertex))
+        });
+        remap.push(existing.unwrap_or_else(|| {
+            let index = vertices.len() as u32;
+            vertices.push(*vertex);
+            grid.entry([x, y, z]).or_default().push(index);
+            index
+        }));
+    }
+
+    let removed = mesh.vertices.len() - vertices.len();
+    mesh.vertices = vertices;
+    mesh.indices = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|triangle| [0, 1, 2].map(|k| remap[triangle[k] as usize]))
+        .filter(|[a, b, c]| a != b && b != c && c != a)
+        .flatten()
+        .collect();
+    removed
+}
+
+/// Sets the tangents of triangle vertices from their texture coordinates,
+/// averaged over the triangles sharing each vertex and made orthogonal to
+/// its normal.
+pub fn compute_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+        let edge1 = b.position - a.position;
+        let edge2 = c.position - a.position;
+        let delta1 = b.texture_coordinates - a.texture_coordinates;
+        let delta2 = c.texture_coordinates - a.texture_coordinates;
+        let determinant = delta1.x * delta2.y - delta2.x * delta1.y;
+        // Degenerate texture coordinates give no direction
+        if determinant.abs() <= f32::EPSILON {
+            continue;
+        }
+        let tangent = (edge1 * delta2.y - edge2 * delta1.y) / determinant;
+        let bitangent = (edge2 * delta1.x - edge1 * delta2.x) / determinant;
+        for &i in triangle {
+            tangents[i as usize] += tangent;
+            bitangents[i as usize] += bitangent;
+        }
+    }
+
+    for (vertex, (tangent, bitangent)) in vertices.iter_mut().zip(tangents.iter().zip(&bitangents))
+    {
+        let normal = vertex.normals;
+        let mut orthogonal = (*tangent - normal * normal.dot(*tangent)).normalize_or_zero();
+        if orthogonal == Vec3::ZERO {
+            let axis = if normal.x.abs() < 0.9 {
+                Vec3::X
+            } else {
+                Vec3::Y
+            };
+            orthogonal = (axis - normal * normal.dot(axis)).normalize_or_zero();
+        }
+        vertex.tangent = orthogonal;
+        vertex.bitangent_sign = if normal.cross(orthogonal).dot(*bitangent) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+    }
+}
+
+/// Reorders the vertices by their first use in `indices`, so that drawing
+/// reads the vertex buffer mostly sequentially. Unused vertices are removed.
+pub fn reorder_vertices(vertices: &mut Vec<Vertex>, indices: &mut [u32]) {
+    let mut remap = vec![u32::MAX; vertices.len()];
+    let mut reordered = Vec::with_capacity(vertices.len());
+    for index in indices.iter_mut() {
+        let new_index = &mut remap[*index as usize];
+        if *new_index == u32::MAX {
+            *new_index = reordered.len() as u32;
+            reordered.push(vertices[*index as usize]);
+        }
+        *index = *new_index;
+    }
+    *vertices = reordered;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec2;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn vertex(position: Vec3) -> Vertex {
+        Vertex {
+            position,
+            normals: Vec3::Y,
+            color: Vec3::ONE,
+            texture_coordinates: position.truncate(),
+            tangent: Vec3::X,
+            bitangent_sign: 1.0,
+        }
+    }
+
+    /// Unit cube centered on the origin whose corners are shared by its
+    /// faces, with triangles facing outwards.
+    fn cube() -> MeshData {
+        let vertices = (0..8)
+            .map(|i| {
+                let corner = Vec3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2) as f32);
+                vertex(corner - 0.5)
+            })
+            .collect();
+        let faces = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let indices = faces
+            .iter()
+            .flat_map(|&[a, b, c, d]| [a, b, c, a, c, d])
+            .collect();
+        MeshData::new(vertices, indices)
+    }
+
+    /// Quad in the XY plane drawn as 2 triangles without shared vertices.
+    fn quad() -> MeshData {
+        let corners = [
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::ZERO,
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::Y,
+        ];
+        MeshData::new(corners.map(vertex).to_vec(), (0..6).collect())
+    }
+
+    fn normal(mesh: &MeshData, triangle: usize) -> Vec3 {
+        let [a, b, c] =
+            [0, 1, 2].map(|k| mesh.vertices[mesh.indices[triangle * 3 + k] as usize].position);
+        (b - a).cross(c - a).normalize()
+    }
+
+    #[test]
+    fn flat_normals_split_corners() {
+        let mut mesh = cube();
+        flat_normals(&mut mesh);
+        assert_eq!(mesh.vertices.len(), 24);
+        for triangle in 0..12 {
+            let face_normal = normal(&mesh, triangle);
+            for k in 0..3 {
+                let vertex = mesh.vertices[mesh.indices[triangle * 3 + k] as usize];
+                assert!(vertex.normals.abs_diff_eq(face_normal, 1e-5));
+                assert!(vertex.tangent.dot(vertex.normals).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_normals_follow_threshold() {
+        let mut sharp = cube();
+        smooth_normals(&mut sharp, 80f32.to_radians());
+        assert_eq!(sharp.vertices.len(), 24);
+
+        let mut smooth = cube();
+        smooth_normals(&mut smooth, 100f32.to_radians());
+        assert_eq!(smooth.vertices.len(), 8);
+        for vertex in &smooth.vertices {
+            // Each corner sees its 3 faces at the same angle
+            assert!(vertex
+                .normals
+                .abs_diff_eq(vertex.position.normalize(), 1e-5));
+        }
+    }
+
+    #[test]
+    fn smooth_normals_keep_coplanar_triangles_together() {
+        let mut mesh = quad();
+        weld(&mut mesh, 0.0);
+        smooth_normals(&mut mesh, 0.0);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert!(mesh.vertices.iter().all(|vertex| vertex.normals == Vec3::Z));
+    }
+
+    #[test]
+    fn weld_merges_equal_vertices() {
+        let mut mesh = quad();
+        assert_eq!(weld(&mut mesh, 0.0), 2);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn weld_respects_epsilon() {
+        let mut mesh = quad();
+        mesh.vertices[3].position.x += 1e-3;
+        mesh.vertices[3].texture_coordinates.x += 1e-3;
+        let mut strict = mesh.clone();
+        assert_eq!(weld(&mut strict, 1e-4), 1);
+        assert_eq!(weld(&mut mesh, 1e-2), 2);
+
+        // Different attributes are kept apart
+        let mut seam = quad();
+        seam.vertices[3].texture_coordinates = Vec2::ONE;
+        assert_eq!(weld(&mut seam, 1e-2), 1);
+    }
+
+    #[test]
+    fn weld_removes_degenerate_triangles() {
+        let mut mesh = quad();
+        mesh.vertices[2].position = Vec3::new(1e-3, 0.0, 0.0);
+        mesh.vertices[2].texture_coordinates = Vec2::new(1e-3, 0.0);
+        weld(&mut mesh, 1e-2);
+        assert_eq!(mesh.indices.len(), 3);
+    }
+
+    #[test]
+    fn merge_offsets_indices_and_transforms() {
+        let cube = cube();
+        let shift = Mat4::from_translation(Vec3::X * 2.0);
+        let mirror = Mat4::from_scale(Vec3::new(-1.0, 1.0, 1.0));
+        let merged = merge([(&cube, Mat4::IDENTITY), (&cube, shift), (&cube, mirror)]);
+        assert_eq!(merged.vertices.len(), 24);
+        assert_eq!(merged.indices.len(), 108);
+        assert_eq!(
+            merged.indices[36..72],
+            cube.indices.iter().map(|i| i + 8).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            merged.vertices[8].position,
+            cube.vertices[0].position + Vec3::X * 2.0
+        );
+        // The mirrored cube still faces outwards
+        for triangle in 24..36 {
+            let [a, b, c] = [0, 1, 2]
+                .map(|k| merged.vertices[merged.indices[triangle * 3 + k] as usize].position);
+            let center = (a + b + c) / 3.0;
+            assert!(normal(&merged, triangle).dot(center) > 0.0);
+        }
+    }
+
+    #[test]
+    fn aabb_contains_points() {
+        let aabb = Aabb::from_points(cube().vertices.iter().map(|vertex| vertex.position));
+        assert_eq!(aabb.min, Vec3::splat(-0.5));
+        assert_eq!(aabb.max, Vec3::splat(0.5));
+        assert_eq!(aabb.center(), Vec3::ZERO);
+        let sphere = aabb.bounding_sphere();
+        assert!((sphere.radius - 3f32.sqrt() / 2.0).abs() < 1e-6);
+
+        let empty = Aabb::from_points([]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.bounding_sphere().radius, 0.0);
+    }
+
+    #[test]
+    fn bounding_sphere_contains_points() {
+        let points: Vec<Vec3> = (0..100)
+            .map(|i| {
+                let angle = i as f32 * 0.7;
+                Vec3::new(angle.cos() * 2.0, (i % 7) as f32 * 0.1, angle.sin()) + Vec3::X * 5.0
+            })
+            .collect();
+        let sphere = BoundingSphere::from_points(&points);
+        for point in &points {
+            assert!(point.distance(sphere.center) <= sphere.radius + 1e-4);
+        }
+        assert!(sphere.radius < 2.0 * 1.1);
+
+        let empty = BoundingSphere::from_points(&[]);
+        assert_eq!(empty.radius, 0.0);
+    }
+
+    #[test]
+    fn bounding_sphere_transforms_and_intersects() {
+        let sphere = BoundingSphere {
+            center: Vec3::X,
+            radius: 1.0,
+        };
+        let transform = Mat4::from_translation(Vec3::Y)
+            * Mat4::from_rotation_z(FRAC_PI_2)
+            * Mat4::from_scale(Vec3::new(1.0, 3.0, 2.0));
+        let transformed = sphere.transformed(transform);
+        assert!(transformed
+            .center
+            .abs_diff_eq(Vec3::new(0.0, 2.0, 0.0), 1e-6));
+        assert_eq!(transformed.radius, 3.0);
+
+        let far = BoundingSphere {
+            center: Vec3::new(0.0, 5.0, 0.0),
+            radius: 0.5,
+        };
+        assert!(!sphere.intersects(&far));
+        assert!(transformed.intersects(&far));
+    }
+
+    #[test]
+    fn tangents_follow_texture_coordinates() {
+        let mut mesh = quad();
+        for vertex in &mut mesh.vertices {
+            vertex.normals = Vec3::Z;
+            vertex.tangent = Vec3::ZERO;
+        }
+        compute_tangents(&mut mesh.vertices, &mesh.indices);
+        for vertex in &mesh.vertices {
+            assert!(vertex.tangent.abs_diff_eq(Vec3::X, 1e-6));
+            assert_eq!(vertex.bitangent_sign, 1.0);
+        }
+    }
+}
@@ -1 +1,9 @@
+// Generated by glad, only the lints its loader trips are allowed
+#[rustfmt::skip]
+#[allow(
+    static_mut_refs,
+    clippy::missing_transmute_annotations,
+    clippy::too_many_arguments,
+    clippy::upper_case_acronyms
+)]
 pub mod gl;
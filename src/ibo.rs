@@ -1,32 +1,53 @@
 use crate::gl;
-use std::mem::size_of;
+use std::cell::Cell;
+use std::mem::{size_of, size_of_val};
+use std::slice;
 
 #[derive(Debug)]
 pub struct Ibo {
     pub id: u32,
-    count: usize,
+    count: Cell<usize>,
 }
 
 impl Ibo {
     pub fn new(indices: &[u32]) -> Self {
+        Ibo::from_bytes(as_bytes(indices))
+    }
+
+    /// Buffer of `u32` indices in native byte order, like the little endian
+    /// ones of cooked meshes on little endian platforms.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
         let mut id = 0;
         unsafe {
             gl::CreateBuffers(1, &mut id);
+        }
+        let ibo = Ibo {
+            id,
+            count: Cell::new(0),
+        };
+        ibo.update_bytes(bytes);
+        ibo
+    }
+
+    /// Replaces the indices, keeping the buffer object.
+    pub fn update(&self, indices: &[u32]) {
+        self.update_bytes(as_bytes(indices));
+    }
+
+    fn update_bytes(&self, bytes: &[u8]) {
+        unsafe {
             gl::NamedBufferData(
-                id,
-                (indices.len() * size_of::<u32>()) as _,
-                indices.as_ptr() as *const _,
+                self.id,
+                bytes.len() as _,
+                bytes.as_ptr() as *const _,
                 gl::STATIC_DRAW,
             );
         }
-        Ibo {
-            id,
-            count: indices.len(),
-        }
+        self.count.set(bytes.len() / size_of::<u32>());
     }
 
     pub fn count(&self) -> i32 {
-        self.count as i32
+        self.count.get() as i32
     }
 }
 
@@ -37,3 +58,7 @@ impl Drop for Ibo {
         }
     }
 }
+
+fn as_bytes(indices: &[u32]) -> &[u8] {
+    unsafe { slice::from_raw_parts(indices.as_ptr() as *const u8, size_of_val(indices)) }
+}
@@ -0,0 +1,162 @@
+use crate::directional_light::DirectionalLight;
+use crate::point_light::PointLight;
+use crate::spot_light::SpotLight;
+use crate::ssbo::Ssbo;
+use glam::Vec3;
+use log::warn;
+use std::mem::size_of;
+
+/// Binding point of the light list in the lighting shaders.
+pub const LIGHT_BUFFER_BINDING: u32 = 0;
+
+/// The light uses the shadow map bound for its kind.
+const SHADOW_FLAG: u32 = 1;
+/// The light projects the cookie bound for spot lights.
+const COOKIE_FLAG: u32 = 2;
+
+#[derive(Debug, Clone, Copy)]
+enum LightKind {
+    Point = 0,
+    Spot = 1,
+    Directional = 2,
+}
+
+/// Light as laid out in the `std430` light list of the lighting shaders.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct GpuLight {
+    /// xyz: position, w: range
+    position_range: [f32; 4],
+    /// xyz: direction the light travels in, w: kind
+    direction_kind: [f32; 4],
+    /// rgb: color, a: intensity
+    color_intensity: [f32; 4],
+    /// x: cosine of the inner angle, y: cosine of the outer angle, z: flags
+    cone_flags: [f32; 4],
+}
+
+/// List of every light of the scene, uploaded to a shader storage buffer
+/// each frame and looped over by the lighting shaders.
+///
+/// Only one light of each kind can use the shadow map (and cookie) bound
+/// for that kind, which is the first light with a shadow map that was not
+/// dropped for overflowing the buffer.
+pub struct LightBuffer {
+    ssbo: Ssbo,
+    lights: Vec<GpuLight>,
+    max_lights: usize,
+    shadowed_kinds: [bool; 3],
+    overflow_warned: bool,
+}
+
+impl LightBuffer {
+    pub fn new(max_lights: usize) -> Self {
+        LightBuffer {
+            ssbo: Ssbo::new(max_lights.max(1) * size_of::<GpuLight>()),
+            lights: Vec::with_capacity(max_lights),
+            max_lights,
+            shadowed_kinds: [false; 3],
+            overflow_warned: false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lights.clear();
+        self.shadowed_kinds = [false; 3];
+    }
+
+    pub fn push_point(&mut self, light: &PointLight) {
+        let flags = self.shadow_flag(LightKind::Point, light.shadow_map.is_some());
+        let gpu_light = GpuLight {
+            position_range: light.position.extend(light.range).to_array(),
+            direction_kind: Vec3::ZERO.extend(LightKind::Point as u32 as f32).to_array(),
+            color_intensity: light.color.extend(light.intensity).to_array(),
+            cone_flags: [0.0, 0.0, flags as f32, 0.0],
+        };
+        self.push(LightKind::Point, flags != 0, gpu_light);
+    }
+
+    pub fn push_spot(&mut self, light: &SpotLight) {
+        // The cookie shares the projection bound along with the shadow map
+        let has_resources = light.shadow_map.is_some() || light.has_cookie();
+        let shadowed = self.shadow_flag(LightKind::Spot, has_resources) != 0;
+        let mut flags = 0;
+        if shadowed {
+            if light.shadow_map.is_some() {
+                flags |= SHADOW_FLAG;
+            }
+            if light.has_cookie() {
+                flags |= COOKIE_FLAG;
+            }
+        }
+        let gpu_light = GpuLight {
+            position_range: light.position.extend(light.range).to_array(),
+            direction_kind: light
+                .direction
+                .extend(LightKind::Spot as u32 as f32)
+                .to_array(),
+            color_intensity: light.color.extend(light.intensity).to_array(),
+            cone_flags: [
+                light.inner_angle.cos(),
+                light.outer_angle.cos(),
+                flags as f32,
+                0.0,
+            ],
+        };
+        self.push(LightKind::Spot, shadowed, gpu_light);
+    }
+
+    pub fn push_directional(&mut self, light: &DirectionalLight) {
+        let flags = self.shadow_flag(LightKind::Directional, light.shadow_map.is_some());
+        let gpu_light = GpuLight {
+            position_range: [0.0; 4],
+            direction_kind: light
+                .direction
+                .extend(LightKind::Directional as u32 as f32)
+                .to_array(),
+            color_intensity: light.color.extend(light.intensity).to_array(),
+            cone_flags: [0.0, 0.0, flags as f32, 0.0],
+        };
+        self.push(LightKind::Directional, flags != 0, gpu_light);
+    }
+
+    pub fn light_count(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// Uploads the lights pushed since the last clear and binds them for the
+    /// light culling and lighting shaders.
+    pub fn upload(&self) {
+        self.ssbo.upload(&self.lights);
+        self.ssbo.bind_base(LIGHT_BUFFER_BINDING);
+    }
+
+    /// Adds `light` unless the buffer is full, taking the shadow map of its
+    /// kind if `shadowed`.
+    fn push(&mut self, kind: LightKind, shadowed: bool, light: GpuLight) {
+        if self.lights.len() >= self.max_lights {
+            if !self.overflow_warned {
+                warn!(
+                    "More than {} lights in the scene, the others are ignored",
+                    self.max_lights
+                );
+                self.overflow_warned = true;
+            }
+            return;
+        }
+        if shadowed {
+            self.shadowed_kinds[kind as usize] = true;
+        }
+        self.lights.push(light);
+    }
+
+    /// `SHADOW_FLAG` if a light of `kind` with a shadow map would be the
+    /// first one pushed with it.
+    fn shadow_flag(&self, kind: LightKind, has_shadow_map: bool) -> u32 {
+        if has_shadow_map && !self.shadowed_kinds[kind as usize] {
+            SHADOW_FLAG
+        } else {
+            0
+        }
+    }
+}
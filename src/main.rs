@@ -1,43 +1,84 @@
+use crate::assets::{AssetKind, Assets};
 use crate::camera::Camera;
+use crate::clustered::ClusteredLighting;
+use crate::config::{round_samples, validate_samples, Config, RenderPath, TransparencyMode};
+use crate::deferred::DeferredRenderer;
+use crate::directional_light::DirectionalLight;
+use crate::ecs::World;
+use crate::environment::Environment;
+use crate::framebuffer::Framebuffer;
 use crate::ibo::Ibo;
+use crate::light_buffer::LightBuffer;
 use crate::mesh::Mesh;
-use crate::point_light::PointLight;
+use crate::oit::WeightedBlendedOit;
+use crate::point_light::{PointLight, PointLightMarker};
+use crate::post_process::{PostEffect, PostProcessStack, UniformValue};
+use crate::render_queue::TransparentQueue;
+use crate::scene::Transform;
 use crate::shader::Shader;
-use crate::texture::{Texture, TextureKind};
-use crate::utils::print_debug_infos;
+use crate::ssao::Ssao;
+use crate::texture::LutTexture;
+use crate::utils::{max_texture_samples, print_debug_infos};
 use crate::vao::Vao;
 use crate::vbo::Vbo;
 use crate::vertex::Vertex;
 use glad::gl;
-use glam::{Mat4, Vec2, Vec3};
+use glam::{Quat, Vec2, Vec3};
 use glutin::dpi::PhysicalPosition;
-use glutin::event::{ElementState, MouseButton};
+use glutin::event::{ElementState, MouseButton, VirtualKeyCode};
 use glutin::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
     Api, ContextBuilder, GlProfile, GlRequest,
 };
-use log::{error, info, trace, LevelFilter};
+use log::{error, info, trace, warn, LevelFilter};
 use simplelog::{ColorChoice, ConfigBuilder, TermLogger, TerminalMode};
 use std::ffi::{c_void, CStr};
 use std::os::raw::c_char;
 use std::path::Path;
 use std::ptr::null;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+mod archive;
+mod assets;
 mod camera;
+mod clustered;
+mod config;
+mod cooked;
+mod deferred;
+mod directional_light;
+mod ecs;
+mod environment;
+mod framebuffer;
+mod geometry;
 mod glad;
 mod ibo;
+mod light_buffer;
+mod material;
 mod mesh;
 mod model;
+mod oit;
+mod optimize;
 mod point_light;
+mod post_process;
+mod primitives;
+mod render_queue;
+mod scene;
+mod scene_file;
 mod shader;
+mod shadow;
+mod spot_light;
+mod ssao;
+mod ssbo;
 mod texture;
+mod thread_pool;
 mod utils;
 mod vao;
 mod vbo;
 mod vertex;
+mod vfs;
+mod watcher;
 
 extern "system" fn debug_callback(
     _source: u32,
@@ -57,6 +98,93 @@ extern "system" fn debug_callback(
     }
 }
 
+/// Time spent each frame uploading the assets decoded in the background.
+const UPLOAD_BUDGET: Duration = Duration::from_millis(4);
+
+/// Behavior of an entity rotating about an axis of its parent.
+struct Spin {
+    axis: Vec3,
+    /// Radians per second
+    speed: f32,
+}
+
+fn spin(world: &mut World, delta_time: Duration) {
+    world.for_each_mut::<Transform>(|entity, transform, world| {
+        if let Some(spin) = world.get::<Spin>(entity) {
+            let angle = spin.speed * delta_time.as_secs_f32();
+            transform.rotation = Quat::from_axis_angle(spin.axis, angle) * transform.rotation;
+        }
+    });
+}
+
+/// Fully saturated color of the given hue, between 0 and 1.
+fn hue_color(hue: f32) -> Vec3 {
+    let channel = |offset: f32| {
+        let distance = ((hue + offset).fract() * 6.0 - 3.0).abs();
+        (distance - 1.0).clamp(0.0, 1.0)
+    };
+    Vec3::new(channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0))
+}
+
+fn toggle_effect(post_process: &mut PostProcessStack, index: usize) {
+    if post_process.toggle(index).is_some() {
+        log_effects(post_process);
+    }
+}
+
+fn log_effects(post_process: &PostProcessStack) {
+    let chain: Vec<String> = post_process
+        .effects()
+        .iter()
+        .map(|effect| {
+            format!(
+                "{} [{}]",
+                effect.name,
+                if effect.enabled { "on" } else { "off" }
+            )
+        })
+        .collect();
+    info!("Post-processing: {}", chain.join(" -> "));
+}
+
+fn log_assets(assets: &Assets) {
+    let loaded = assets.loaded();
+    for asset in &loaded {
+        let memory = match asset.kind {
+            AssetKind::Material => "textures listed apart".to_owned(),
+            _ => format!("{} KiB", asset.memory / 1024),
+        };
+        info!(
+            "{:?} {}: {memory}, {} handle(s)",
+            asset.kind, asset.name, asset.handles
+        );
+    }
+    let total: usize = loaded.iter().map(|asset| asset.memory).sum();
+    info!("{} assets loaded, {} KiB", loaded.len(), total / 1024);
+}
+
+/// Mounts the directories and archives given as options or by default the
+/// working directory over an `assets.pak` archive next to the executable,
+/// if there is one.
+fn mount_files(config: &Config) {
+    if config.mounts.is_empty() {
+        let archive = std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join("assets.pak")));
+        if let Some(archive) = archive.filter(|archive| archive.is_file()) {
+            if let Err(err) = vfs::mount_archive(&archive) {
+                error!("Unable to mount {}: {err}", archive.display());
+            }
+        }
+        vfs::mount_directory(Path::new("."));
+    }
+    for mount in &config.mounts {
+        if let Err(err) = vfs::mount(mount) {
+            error!("Unable to mount {}: {err}", mount.display());
+        }
+    }
+}
+
 fn main() {
     TermLogger::init(
         LevelFilter::Debug,
@@ -69,16 +197,37 @@ fn main() {
     const WIDTH: u32 = 1280;
     const HEIGHT: u32 = 720;
 
+    let config = Config::from_args();
+    mount_files(&config);
+
     let el = EventLoop::new();
     let wb = WindowBuilder::new()
         .with_title("Rust OpenGL engine v0.0.1 (x64)")
         .with_visible(false)
         .with_inner_size(glutin::dpi::PhysicalSize::new(WIDTH, HEIGHT));
 
-    let windowed_context = ContextBuilder::new()
-        .with_gl(GlRequest::Specific(Api::OpenGl, (4, 6)))
-        .with_gl_profile(GlProfile::Core)
-        .build_windowed(wb, &el)
+    let context_builder = || {
+        ContextBuilder::new()
+            .with_gl(GlRequest::Specific(Api::OpenGl, (4, 6)))
+            .with_gl_profile(GlProfile::Core)
+    };
+
+    // The driver limit is unknown until a context exists, only round the
+    // request and fall back to a single sampled window if the requested
+    // pixel format is not available. The window gets checked once created.
+    let screen_samples = round_samples(config.screen_msaa_samples);
+    let windowed_context = context_builder()
+        .with_multisampling(screen_samples as u16)
+        .build_windowed(wb.clone(), &el)
+        .or_else(|err| {
+            if screen_samples == 0 {
+                return Err(err);
+            }
+            warn!(
+                "Could not create a {screen_samples}x multisampled window ({err}), disabling MSAA"
+            );
+            context_builder().build_windowed(wb, &el)
+        })
         .unwrap();
     let windowed_context = unsafe { windowed_context.make_current().unwrap() };
 
@@ -90,65 +239,131 @@ fn main() {
         gl::DebugMessageCallback(debug_callback, null());
         gl::ClearColor(0.2, 0.3, 0.8, 1.0);
         gl::Enable(gl::DEPTH_TEST);
+        gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+        // Lighting is computed in linear space, encode it when writing to the window
+        gl::Enable(gl::FRAMEBUFFER_SRGB);
     }
 
     print_debug_infos();
 
-    let vertices = vec![
-        Vertex {
-            position: Vec3::new(-2.0, 0.0, -2.0),
-            normals: Vec3::new(0.0, 1.0, 0.0),
-            color: Default::default(),
-            texture_coordinates: Vec2::new(0.0, 0.0),
-        },
-        Vertex {
-            position: Vec3::new(2.0, 0.0, -2.0),
-            normals: Vec3::new(0.0, 1.0, 0.0),
-            color: Default::default(),
-            texture_coordinates: Vec2::new(1.0, 0.0),
-        },
-        Vertex {
-            position: Vec3::new(2.0, 0.0, 2.0),
-            normals: Vec3::new(0.0, 1.0, 0.0),
-            color: Default::default(),
-            texture_coordinates: Vec2::new(1.0, 1.0),
-        },
-        Vertex {
-            position: Vec3::new(-2.0, 0.0, 2.0),
-            normals: Vec3::new(0.0, 1.0, 0.0),
-            color: Default::default(),
-            texture_coordinates: Vec2::new(0.0, 1.0),
-        },
-    ];
-
-    let indices = vec![0, 1, 2, 2, 3, 0];
-
-    let planks_diffuse = Texture::new(
-        Path::new("res/wood_floor/WoodFlooring044_COL_1K.jpg"),
-        TextureKind::DIFFUSE,
-    );
-    let planks_specular = Texture::new(
-        Path::new("res/wood_floor/WoodFlooring044_REFL_1K.jpg"),
-        TextureKind::SPECULAR,
-    );
-    let mesh = Mesh::new(vertices, indices, vec![planks_diffuse, planks_specular]);
+    // The G-buffer is not multisampled, the deferred path relies on FXAA
+    let msaa_samples = match config.render_path {
+        RenderPath::Forward => validate_samples(config.msaa_samples, max_texture_samples()),
+        RenderPath::Deferred => 0,
+    };
+    let mut max_window_samples = 0;
+    let mut window_samples = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_window_samples);
+        gl::GetIntegerv(gl::SAMPLES, &mut window_samples);
+    }
+    if screen_samples as i32 > max_window_samples {
+        warn!("{screen_samples}x window MSAA exceeds GL_MAX_SAMPLES ({max_window_samples}x)");
+    }
+    let window_samples = window_samples.max(0) as u32;
+    if window_samples != screen_samples {
+        warn!("Requested a {screen_samples}x multisampled window, got {window_samples}x");
+    }
+    info!("MSAA: {msaa_samples}x scene, {window_samples}x screen");
+    info!("Render path: {:?}", config.render_path);
 
-    let mut shader = Shader::new(
+    let mut assets = Assets::new();
+    let mut scene = scene_file::load(&config.scene, &mut assets, &config, WIDTH, HEIGHT)
+        .unwrap_or_else(|err| panic!("Unable to load {}: {err}", config.scene.display()));
+
+    let shader = assets.shader(
         Path::new("shaders/default.vert"),
         Path::new("shaders/default.frag"),
     );
 
-    shader.bind();
-    shader.set_uniform_1i("uTextureDiffuse", 0);
-    shader.set_uniform_1i("uTextureSpecular", 1);
+    let mut environment = Environment::from_equirectangular(&config.environment)
+        .unwrap_or_else(|err| panic!("Unable to load {}: {err}", config.environment.display()));
+
+    let mut deferred_renderer =
+        (config.render_path == RenderPath::Deferred).then(|| DeferredRenderer::new(WIDTH, HEIGHT));
+
+    let mut transparency = config.transparency;
+    let mut oit = WeightedBlendedOit::new(WIDTH, HEIGHT);
+
+    let mut ssao = Ssao::new(WIDTH, HEIGHT, config.ssao_samples);
+    ssao.radius = config.ssao_radius;
+    ssao.intensity = config.ssao_intensity;
 
     let mut camera = Camera::new(45.0, Vec3::new(0.0, 1.0, 1.0), WIDTH, HEIGHT);
 
-    let mut point_light = PointLight::new();
-    point_light.set_position(Vec3::new(0.0, 0.3, 0.0));
-    shader.set_uniform_vec3("uLightPosition", point_light.position);
-    shader.set_uniform_vec3("uLightColor", Vec3::new(1.0, 1.0, 1.0));
+    // Not part of the scene file, so they are left out when saving it
+    let light_ring = scene.find_node("light ring");
+    if let Some(light_ring) = light_ring {
+        scene.world.insert(
+            light_ring,
+            Spin {
+                axis: Vec3::Y,
+                speed: -0.3,
+            },
+        );
+    }
+    for i in 0..config.point_lights {
+        let angle = i as f32 / config.point_lights as f32 * std::f32::consts::TAU;
+        let radius = 1.0 + (i % 3) as f32 * 0.4;
+        let entity = scene.spawn(
+            light_ring,
+            Transform::from_translation(Vec3::new(
+                angle.cos() * radius,
+                0.15,
+                angle.sin() * radius,
+            )),
+        );
+        let mut light = PointLight::new();
+        light.color = hue_color(i as f32 / config.point_lights as f32);
+        light.intensity = 0.3;
+        light.range = 1.5;
+        scene.world.insert(entity, light);
+    }
+    let mut orbit_view = false;
 
+    let mut light_marker = PointLightMarker::new();
+    let mut light_buffer = LightBuffer::new(config.max_lights);
+    let mut clustered_lighting = ClusteredLighting::new(WIDTH, HEIGHT);
+
+    let mut scene_framebuffer = Framebuffer::new(WIDTH, HEIGHT)
+        .with_samples(msaa_samples)
+        .with_color_attachment(gl::RGBA16F)
+        .with_depth_attachment();
+    let mut resolve_framebuffer = (msaa_samples > 0).then(|| {
+        Framebuffer::new(WIDTH, HEIGHT)
+            .with_color_attachment(gl::RGBA16F)
+            .with_depth_attachment()
+    });
+
+    let mut post_process = PostProcessStack::new(WIDTH, HEIGHT);
+    post_process.push(
+        PostEffect::new("FXAA", Path::new("shaders/post/fxaa.frag"))
+            .with_uniform("uSpanMax", UniformValue::Float(8.0))
+            .with_uniform("uReduceMul", UniformValue::Float(1.0 / 8.0))
+            .with_uniform("uReduceMin", UniformValue::Float(1.0 / 128.0)),
+    );
+    post_process.push(
+        PostEffect::new(
+            "Color grading",
+            Path::new("shaders/post/color_grading.frag"),
+        )
+        .with_lut(LutTexture::from_strip(Path::new("res/luts/warm.png")))
+        .with_uniform("uIntensity", UniformValue::Float(1.0)),
+    );
+    post_process.push(
+        PostEffect::new("Vignette", Path::new("shaders/post/vignette.frag"))
+            .with_uniform("uIntensity", UniformValue::Float(0.6))
+            .with_uniform("uRadius", UniformValue::Float(0.75))
+            .with_uniform("uSoftness", UniformValue::Float(0.45))
+            .with_uniform("uCenter", UniformValue::Vec2(Vec2::splat(0.5)))
+            .with_uniform("uColor", UniformValue::Vec3(Vec3::ZERO)),
+    );
+    post_process.push(
+        PostEffect::new("Film grain", Path::new("shaders/post/film_grain.frag"))
+            .with_uniform("uIntensity", UniformValue::Float(0.06)),
+    );
+
+    let start_time = Instant::now();
     let mut fps_timer = Instant::now();
     let mut counter = 0;
 
@@ -169,6 +384,20 @@ fn main() {
                     windowed_context.resize(physical_size);
                     let window_size = windowed_context.window().inner_size();
                     camera.update_viewport(window_size.width, window_size.height);
+                    for (_, camera) in scene.world.query_mut::<Camera>() {
+                        camera.update_viewport(window_size.width, window_size.height);
+                    }
+                    scene_framebuffer.resize(window_size.width, window_size.height);
+                    if let Some(resolve_framebuffer) = &mut resolve_framebuffer {
+                        resolve_framebuffer.resize(window_size.width, window_size.height);
+                    }
+                    post_process.resize(window_size.width, window_size.height);
+                    clustered_lighting.resize(window_size.width, window_size.height);
+                    ssao.resize(window_size.width, window_size.height);
+                    if let Some(deferred_renderer) = &mut deferred_renderer {
+                        deferred_renderer.resize(window_size.width, window_size.height);
+                    }
+                    oit.resize(window_size.width, window_size.height);
                     unsafe {
                         gl::Viewport(0, 0, window_size.width as i32, window_size.height as i32);
                     }
@@ -180,6 +409,62 @@ fn main() {
                     if let Some(keycode) = input.virtual_keycode {
                         inputs[keycode as usize] = input.state == ElementState::Pressed;
                     }
+                    if input.state == ElementState::Pressed {
+                        match input.virtual_keycode {
+                            Some(VirtualKeyCode::F1) => toggle_effect(&mut post_process, 0),
+                            Some(VirtualKeyCode::F2) => toggle_effect(&mut post_process, 1),
+                            Some(VirtualKeyCode::F3) => toggle_effect(&mut post_process, 2),
+                            Some(VirtualKeyCode::F4) => toggle_effect(&mut post_process, 3),
+                            Some(VirtualKeyCode::F6) => {
+                                let sun = scene.world.query_mut::<DirectionalLight>().next();
+                                if let Some(shadow_map) =
+                                    sun.and_then(|(_, sun)| sun.shadow_map.as_mut())
+                                {
+                                    shadow_map.debug = !shadow_map.debug;
+                                }
+                            }
+                            Some(VirtualKeyCode::F7) => {
+                                clustered_lighting.debug_heatmap =
+                                    !clustered_lighting.debug_heatmap;
+                            }
+                            Some(VirtualKeyCode::F8) => match &mut deferred_renderer {
+                                Some(deferred_renderer) => {
+                                    deferred_renderer.view = deferred_renderer.view.next();
+                                    info!("G-buffer view: {:?}", deferred_renderer.view);
+                                }
+                                None => warn!("G-buffer views need --renderer deferred"),
+                            },
+                            Some(VirtualKeyCode::F9) => {
+                                info!("SSAO enabled: {}", ssao.toggle());
+                            }
+                            Some(VirtualKeyCode::F10) => {
+                                transparency = transparency.next();
+                                info!("Transparency: {transparency:?}");
+                            }
+                            Some(VirtualKeyCode::F11) => {
+                                orbit_view = !orbit_view;
+                                if scene.world.query::<Camera>().next().is_none() {
+                                    warn!("The scene has no camera");
+                                } else {
+                                    info!("Orbit camera: {orbit_view}");
+                                }
+                            }
+                            Some(VirtualKeyCode::F12) => {
+                                let path = config.scene.with_extension("saved.ron");
+                                match scene_file::save(&scene, &path) {
+                                    Ok(()) => info!("Scene saved to {}", path.display()),
+                                    Err(err) => error!("Unable to save {}: {err}", path.display()),
+                                }
+                            }
+                            Some(VirtualKeyCode::L) => log_assets(&assets),
+                            Some(VirtualKeyCode::F5) => {
+                                let last = post_process.effects().len().saturating_sub(1);
+                                post_process.move_effect(0, last);
+                                log_effects(&post_process);
+                            }
+                            _ => (),
+                        }
+                    }
                 }
                 WindowEvent::MouseInput { state, button, .. } => {
                     if button == MouseButton::Left && state == ElementState::Pressed {
@@ -198,18 +483,16 @@ fn main() {
                         mouse_captured = false;
                     }
                 }
-                WindowEvent::CursorMoved { position, .. } => {
-                    if mouse_captured {
-                        camera.update_orientation(position);
-                        let window_size = windowed_context.window().inner_size();
-                        windowed_context
-                            .window()
-                            .set_cursor_position(PhysicalPosition::new(
-                                window_size.width as f64 / 2.0,
-                                window_size.height as f64 / 2.0,
-                            ))
-                            .unwrap();
-                    }
+                WindowEvent::CursorMoved { position, .. } if mouse_captured => {
+                    camera.update_orientation(position);
+                    let window_size = windowed_context.window().inner_size();
+                    windowed_context
+                        .window()
+                        .set_cursor_position(PhysicalPosition::new(
+                            window_size.width as f64 / 2.0,
+                            window_size.height as f64 / 2.0,
+                        ))
+                        .unwrap();
                 }
                 _ => (),
             },
@@ -228,24 +511,110 @@ fn main() {
 
                 camera.update_position(&inputs, delta_time);
 
-                unsafe {
-                    gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                let time = start_time.elapsed().as_secs_f32();
+                let upload_deadline = Instant::now() + UPLOAD_BUDGET;
+                model::finish_loading(&mut scene, &mut assets, upload_deadline);
+                assets.reload_modified();
+                assets.finish_uploads(upload_deadline);
+
+                spin(&mut scene.world, delta_time);
+                scene.update();
+                let camera = &match scene.world.query::<Camera>().next() {
+                    Some((_, orbit_camera)) if orbit_view => orbit_camera.clone(),
+                    _ => camera.clone(),
+                };
+
+                scene.render_shadow_maps(camera);
+                // Draws the opaque and alpha tested meshes with their
+                // materials, for the depth only, G-buffer and forward passes
+                let draw_scene = |scene_shader: &Shader| scene.draw(scene_shader);
+
+                light_buffer.clear();
+                scene.push_lights(&mut light_buffer);
+                light_buffer.upload();
+                clustered_lighting.update(camera, light_buffer.light_count());
+
+                // Fill the depth and normals needed by SSAO before lighting
+                match &mut deferred_renderer {
+                    Some(deferred_renderer) => {
+                        deferred_renderer.geometry_pass(camera, draw_scene);
+                        ssao.render(camera, Some(deferred_renderer.gbuffer()));
+                    }
+                    None => {
+                        ssao.render_prepass(camera, draw_scene);
+                        ssao.render(camera, None);
+                    }
                 }
 
-                let model = Mat4::IDENTITY;
+                // The environment is a parameter as drawing the sky borrows it
+                // between the opaque and transparent passes
+                let bind_lighting = |lighting_shader: &Shader, environment: &Environment| {
+                    lighting_shader.set_uniform_mat4("uCameraView", camera.get_view_matrix());
+                    lighting_shader.set_uniform_vec3("uCameraPosition", camera.position);
+                    environment.bind(lighting_shader);
+                    scene.bind_shadow_maps(lighting_shader);
+                    clustered_lighting.bind(lighting_shader, camera);
+                    ssao.bind(lighting_shader);
+                };
 
+                match &mut deferred_renderer {
+                    Some(deferred_renderer) => {
+                        deferred_renderer.lighting_pass(&scene_framebuffer, camera, |shader| {
+                            bind_lighting(shader, &environment)
+                        });
+                    }
+                    None => {
+                        scene_framebuffer.bind();
+                        unsafe {
+                            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                        }
+
+                        shader.bind();
+                        shader.set_uniform_mat4("uCameraViewProjection", camera.get_matrix());
+                        bind_lighting(&shader, &environment);
+                        draw_scene(&shader);
+                        shader.unbind();
+                    }
+                }
+
+                environment.draw_skybox(camera);
+
+                for (_, light) in scene.world.query::<PointLight>() {
+                    light_marker.draw(camera, light);
+                }
+
+                let mut transparent_queue = TransparentQueue::new();
+                scene.queue_transparent(&mut transparent_queue);
+
+                if transparency == TransparencyMode::WeightedBlended {
+                    oit.render(&transparent_queue, &scene_framebuffer, camera, |shader| {
+                        bind_lighting(shader, &environment)
+                    });
+                }
                 shader.bind();
                 shader.set_uniform_mat4("uCameraViewProjection", camera.get_matrix());
-                shader.set_uniform_mat4("uModel", model);
-                shader.set_uniform_vec3("uCameraPosition", camera.position);
-                mesh.draw();
+                bind_lighting(&shader, &environment);
+                // The occlusion buffer only covers the opaque surfaces
+                shader.set_uniform_1i("uSsaoEnabled", 0);
+                match transparency {
+                    TransparencyMode::Sorted => transparent_queue.draw(&shader, camera),
+                    TransparencyMode::WeightedBlended => transparent_queue.draw_additive(&shader),
+                }
                 shader.unbind();
+                scene_framebuffer.unbind();
 
-                point_light.draw(&camera);
+                let post_process_input = match &resolve_framebuffer {
+                    Some(resolve_framebuffer) => {
+                        scene_framebuffer.resolve_into(resolve_framebuffer);
+                        resolve_framebuffer
+                    }
+                    None => &scene_framebuffer,
+                };
+                post_process.render(post_process_input, time);
 
                 windowed_context.swap_buffers().unwrap();
             }
-            Event::LoopDestroyed => return,
+            Event::LoopDestroyed => (),
             _ => (),
         };
     })
@@ -0,0 +1,278 @@
+use crate::assets::Assets;
+use crate::texture::{Texture, TextureKind};
+use crate::{gl, vfs, Shader};
+use glam::{Vec3, Vec4};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Scalar inputs of the metallic-roughness shading model, multiplied with
+/// the corresponding texture when the mesh has one.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaterialFactors {
+    /// Linear albedo and alpha
+    pub base_color: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// How much of the occlusion texture is applied, from 0 to 1
+    pub occlusion_strength: f32,
+    /// Linear emitted color
+    pub emissive: Vec3,
+}
+
+impl Default for MaterialFactors {
+    /// White rough dielectric, unlike glTF which defaults to a metal
+    fn default() -> Self {
+        MaterialFactors {
+            base_color: Vec4::ONE,
+            metallic: 0.0,
+            roughness: 0.8,
+            occlusion_strength: 1.0,
+            emissive: Vec3::ZERO,
+        }
+    }
+}
+
+impl MaterialFactors {
+    /// Sets the material uniforms of a scene shader, `maps` being the kinds
+    /// of textures bound along with the factors.
+    pub fn bind(&self, shader: &Shader, maps: &[TextureKind]) {
+        shader.set_uniform_vec4("uBaseColorFactor", self.base_color);
+        shader.set_uniform_1f("uMetallicFactor", self.metallic);
+        shader.set_uniform_1f("uRoughnessFactor", self.roughness);
+        shader.set_uniform_1f("uOcclusionStrength", self.occlusion_strength);
+        shader.set_uniform_vec3("uEmissiveFactor", self.emissive);
+
+        let map_samplers = [
+            (TextureKind::BaseColor, "uBaseColorMap", 1),
+            (TextureKind::MetallicRoughness, "uMetallicRoughnessMap", 2),
+            (TextureKind::Occlusion, "uOcclusionMap", 4),
+            (TextureKind::Emissive, "uEmissiveMap", 8),
+        ];
+        let mut map_flags = 0;
+        for (kind, sampler, flag) in map_samplers {
+            shader.set_uniform_1i(sampler, kind.unit() as i32);
+            if maps.contains(&kind) {
+                map_flags |= flag;
+            }
+        }
+        shader.set_uniform_1i("uMaterialMaps", map_flags);
+    }
+}
+
+/// Branch of the scene shaders used to shade a material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum ShaderVariant {
+    /// Metallic-roughness shading by the scene lights and environment
+    #[default]
+    Lit = 0,
+    /// Base color and emission as is, ignoring the lights
+    Unlit = 1,
+}
+
+/// How the fragments of a material are combined with what is behind them.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+pub enum BlendMode {
+    /// Replaces the color behind
+    #[default]
+    Opaque,
+    /// Opaque, but fragments with a lower base color alpha are discarded
+    AlphaTest(f32),
+    /// Mixes the color with the one behind by the base color alpha
+    AlphaBlend,
+    /// Adds the color weighted by the base color alpha, for glows and fire
+    Additive,
+    /// Like `AlphaBlend` for a base color already multiplied by its alpha,
+    /// emission being added as is
+    Premultiplied,
+}
+
+impl BlendMode {
+    /// Whether the material is drawn after the opaque meshes, sorted back to
+    /// front and without writing depth.
+    pub fn is_transparent(&self) -> bool {
+        !matches!(self, BlendMode::Opaque | BlendMode::AlphaTest(_))
+    }
+
+    /// Sets the blend function of a transparent mode, until `disable` is
+    /// called.
+    pub fn enable(&self) {
+        let (source, destination) = match self {
+            BlendMode::Opaque | BlendMode::AlphaTest(_) => return,
+            BlendMode::AlphaBlend => (gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Additive => (gl::SRC_ALPHA, gl::ONE),
+            BlendMode::Premultiplied => (gl::ONE, gl::ONE_MINUS_SRC_ALPHA),
+        };
+        // Transparent meshes are tested against the opaque ones but do not
+        // hide each other, their draw order does
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(source, destination);
+            gl::DepthMask(gl::FALSE);
+        }
+    }
+
+    pub fn disable(&self) {
+        if self.is_transparent() {
+            unsafe {
+                gl::Disable(gl::BLEND);
+                gl::DepthMask(gl::TRUE);
+            }
+        }
+    }
+}
+
+/// Everything needed to shade a mesh: the shader variant, the textures by
+/// slot and the scalar parameters.
+#[derive(Debug)]
+pub struct Material {
+    pub variant: ShaderVariant,
+    textures: Vec<Rc<Texture>>,
+    pub factors: MaterialFactors,
+    pub blend: BlendMode,
+    /// Converts the fragment alpha into a coverage mask instead of alpha
+    /// testing when drawing into a multisampled target, giving foliage
+    /// smooth cutout edges without sorting.
+    pub alpha_to_coverage: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new(vec![])
+    }
+}
+
+impl Material {
+    pub fn new(textures: Vec<Rc<Texture>>) -> Self {
+        Material {
+            variant: ShaderVariant::default(),
+            textures,
+            factors: MaterialFactors::default(),
+            blend: BlendMode::default(),
+            alpha_to_coverage: false,
+        }
+    }
+
+    pub fn with_factors(mut self, factors: MaterialFactors) -> Self {
+        self.factors = factors;
+        self
+    }
+
+    /// Loads a RON material file, texture paths being relative to the file.
+    /// The textures are decoded in the background by `assets`:
+    ///
+    /// ```ron
+    /// (
+    ///     shader: Lit,
+    ///     textures: { BaseColor: "planks.jpg" },
+    ///     parameters: (roughness: 0.6),
+    ///     blend: AlphaTest(0.5),
+    /// )
+    /// ```
+    pub fn load(path: &Path, assets: &mut Assets) -> Result<Self, MaterialError> {
+        let source = vfs::read_to_string(path).map_err(MaterialError::Io)?;
+        let file: MaterialFile = ron::from_str(&source).map_err(MaterialError::Parse)?;
+
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let textures = file
+            .textures
+            .into_iter()
+            .map(|(kind, texture_path)| assets.texture(&directory.join(texture_path), kind))
+            .collect();
+
+        Ok(Material {
+            variant: file.shader,
+            textures,
+            factors: file.parameters,
+            blend: file.blend,
+            alpha_to_coverage: file.alpha_to_coverage,
+        })
+    }
+
+    /// Binds the textures and sets the material uniforms of a scene shader,
+    /// until `unbind` is called.
+    pub fn bind(&self, shader: &Shader) {
+        let maps: Vec<_> = self.textures.iter().map(|texture| texture.kind()).collect();
+        self.factors.bind(shader, &maps);
+        shader.set_uniform_1i("uShaderVariant", self.variant as i32);
+        for texture in &self.textures {
+            texture.bind();
+        }
+
+        let alpha_cutoff = match self.blend {
+            // Alpha to coverage only works on a multisampled target,
+            // otherwise fall back to alpha testing
+            BlendMode::AlphaTest(_) if self.alpha_to_coverage && is_multisampled() => {
+                unsafe {
+                    gl::Enable(gl::SAMPLE_ALPHA_TO_COVERAGE);
+                }
+                0.0
+            }
+            BlendMode::AlphaTest(cutoff) => cutoff,
+            _ => 0.0,
+        };
+        shader.set_uniform_1f("uAlphaCutoff", alpha_cutoff);
+
+        shader.set_uniform_1i(
+            "uPremultipliedAlpha",
+            (self.blend == BlendMode::Premultiplied) as i32,
+        );
+    }
+
+    pub fn unbind(&self) {
+        for texture in &self.textures {
+            texture.unbind();
+        }
+        unsafe {
+            if self.alpha_to_coverage {
+                gl::Disable(gl::SAMPLE_ALPHA_TO_COVERAGE);
+            }
+        }
+    }
+}
+
+/// Whether the bound draw framebuffer has several samples per pixel.
+fn is_multisampled() -> bool {
+    let mut sample_buffers = 0;
+    unsafe {
+        gl::GetIntegerv(gl::SAMPLE_BUFFERS, &mut sample_buffers);
+    }
+    sample_buffers > 0
+}
+
+/// Content of a material file, see `Material::load`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialFile {
+    #[serde(default)]
+    shader: ShaderVariant,
+    #[serde(default)]
+    textures: HashMap<TextureKind, PathBuf>,
+    #[serde(default)]
+    parameters: MaterialFactors,
+    #[serde(default)]
+    blend: BlendMode,
+    #[serde(default)]
+    alpha_to_coverage: bool,
+}
+
+#[derive(Debug)]
+pub enum MaterialError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl Display for MaterialError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MaterialError::Io(err) => write!(f, "{err}"),
+            MaterialError::Parse(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for MaterialError {}
@@ -1,27 +1,62 @@
+use crate::cooked::CookedPart;
+use crate::geometry::{Aabb, BoundingSphere};
 use crate::gl::GLsizei;
-use crate::{gl, Ibo, Texture, Vao, Vbo, Vertex};
-use std::mem::size_of;
+use crate::material::{BlendMode, Material};
+use crate::{gl, Ibo, Shader, Vao, Vbo, Vertex};
+use glam::Vec3;
+use std::cell::Cell;
+use std::mem::{offset_of, size_of};
 use std::ptr::null;
+use std::rc::Rc;
 
 #[derive(Debug)]
 pub struct Mesh {
     vao: Vao,
     ibo: Ibo,
-    #[allow(dead_code)]
     vbo: Vbo,
-    textures: Vec<Texture>,
+    material: Rc<Material>,
+    /// Center of the bounding box of the vertices, in model space
+    center: Cell<Vec3>,
+    bounding_sphere: Cell<BoundingSphere>,
 }
 
 impl Mesh {
-    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture>) -> Self {
-        let vbo = Vbo::new(&vertices);
-        let ibo = Ibo::new(&indices);
+    pub fn new(vertices: &[Vertex], indices: &[u32], material: Rc<Material>) -> Self {
+        let (aabb, sphere) = bounds(vertices);
+        Mesh::from_buffers(
+            Vbo::new(vertices),
+            Ibo::new(indices),
+            aabb,
+            sphere,
+            material,
+        )
+    }
+
+    /// Mesh of a part of a cooked mesh file, whose buffers are uploaded as is.
+    pub fn from_cooked(part: &CookedPart, material: Rc<Material>) -> Self {
+        let vbo = Vbo::from_bytes(&part.vertices);
+        let ibo = Ibo::from_bytes(&part.indices);
+        let aabb = Aabb {
+            min: part.min,
+            max: part.max,
+        };
+        Mesh::from_buffers(vbo, ibo, aabb, aabb.bounding_sphere(), material)
+    }
 
+    fn from_buffers(
+        vbo: Vbo,
+        ibo: Ibo,
+        aabb: Aabb,
+        bounding_sphere: BoundingSphere,
+        material: Rc<Material>,
+    ) -> Self {
         let vao = Vao::new()
-            .with_layout(0, 3, gl::FLOAT, 0)
-            .with_layout(1, 3, gl::FLOAT, 3 * size_of::<f32>())
-            .with_layout(2, 3, gl::FLOAT, 6 * size_of::<f32>())
-            .with_layout(3, 2, gl::FLOAT, 9 * size_of::<f32>())
+            .with_layout(0, 3, gl::FLOAT, offset_of!(Vertex, position))
+            .with_layout(1, 3, gl::FLOAT, offset_of!(Vertex, normals))
+            .with_layout(2, 3, gl::FLOAT, offset_of!(Vertex, color))
+            .with_layout(3, 2, gl::FLOAT, offset_of!(Vertex, texture_coordinates))
+            // Tangent and bitangent sign
+            .with_layout(4, 4, gl::FLOAT, offset_of!(Vertex, tangent))
             .with_vbo(&vbo, size_of::<Vertex>() as _)
             .with_ibo(&ibo);
 
@@ -29,15 +64,52 @@ impl Mesh {
             vao,
             ibo,
             vbo,
-            textures,
+            material,
+            center: Cell::new(aabb.center()),
+            bounding_sphere: Cell::new(bounding_sphere),
         }
     }
 
-    pub fn draw(&self) {
+    /// Replaces the vertices and indices in place, for the users of the mesh
+    /// to draw the new ones.
+    pub fn update(&self, vertices: &[Vertex], indices: &[u32]) {
+        self.vbo.update(vertices);
+        self.ibo.update(indices);
+        let (aabb, sphere) = bounds(vertices);
+        self.center.set(aabb.center());
+        self.bounding_sphere.set(sphere);
+    }
+
+    /// Bytes used by the vertex and index buffers.
+    pub fn memory_size(&self) -> usize {
+        self.vbo.size() + self.ibo.count() as usize * size_of::<u32>()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.center.get()
+    }
+
+    /// Sphere containing the vertices, in model space.
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        self.bounding_sphere.get()
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.material.blend
+    }
+
+    /// Whether the material blends the mesh with what is behind it, in
+    /// which case it must be drawn after the opaque meshes by a
+    /// `TransparentQueue`.
+    pub fn is_transparent(&self) -> bool {
+        self.material.blend.is_transparent()
+    }
+
+    /// Draws the mesh with its material, `shader` being a scene shader with
+    /// the camera and `uModel` uniforms already set.
+    pub fn draw(&self, shader: &Shader) {
         self.vao.bind();
-        for texture in &self.textures {
-            texture.bind();
-        }
+        self.material.bind(shader);
         unsafe {
             gl::DrawElements(
                 gl::TRIANGLES,
@@ -46,9 +118,15 @@ impl Mesh {
                 null(),
             );
         }
-        for texture in &self.textures {
-            texture.unbind();
-        }
+        self.material.unbind();
         self.vao.unbind();
     }
 }
+
+fn bounds(vertices: &[Vertex]) -> (Aabb, BoundingSphere) {
+    let positions: Vec<Vec3> = vertices.iter().map(|vertex| vertex.position).collect();
+    (
+        Aabb::from_points(positions.iter().copied()),
+        BoundingSphere::from_points(&positions),
+    )
+}
@@ -0,0 +1,551 @@
+use crate::assets::Assets;
+use crate::cooked::{self, CookedPart};
+use crate::ecs::Entity;
+use crate::geometry::{self, MeshData};
+use crate::material::{BlendMode, Material, MaterialError, MaterialFactors, ShaderVariant};
+use crate::optimize::{self, OptimizeStats};
+use crate::scene::{MeshRenderer, Scene, Transform};
+use crate::texture::{Texture, TextureData, TextureKind};
+use crate::thread_pool::Task;
+use crate::{vfs, Mesh, Vertex};
+use glam::{Quat, Vec2, Vec3, Vec4};
+use gltf::image::Format;
+use gltf::material::AlphaMode;
+use gltf::mesh::Mode;
+use gltf::Semantic;
+use log::{debug, error, warn};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Contents of a glTF file, read and processed by a worker so that only
+/// uploads are left to the GL thread.
+pub struct Import {
+    document: gltf::Document,
+    /// Vertices of the supported primitives, by mesh and primitive index
+    primitives: HashMap<(usize, usize), MeshData>,
+    /// Texels of the images, by index
+    images: Vec<TextureData>,
+}
+
+/// Component of the root entity of a model whose file is being imported by
+/// a worker.
+pub struct PendingModel {
+    path: PathBuf,
+    import: Task<gltf::Result<Import>>,
+}
+
+/// Component of the root entity of a model, whose nodes are added again
+/// when its file changes.
+pub struct ModelRoot {
+    path: PathBuf,
+}
+
+/// Component of an entity drawing a cooked mesh file being read by a worker.
+pub struct PendingCookedMesh {
+    path: PathBuf,
+    /// Prefix of the names of the parts in `Assets`
+    name: String,
+    material: Rc<Material>,
+    parts: Task<io::Result<Vec<CookedPart>>>,
+}
+
+/// Spawns an entity of `scene`, child of `parent`, under which the scenes of
+/// a glTF file are added by `finish_loading` once imported in the
+/// background. Returns the new entity, showing a placeholder meanwhile.
+pub fn load(scene: &mut Scene, assets: &mut Assets, path: &Path, parent: Option<Entity>) -> Entity {
+    let root = scene.spawn(parent, Transform::default());
+    let meshes = vec![assets.placeholder_mesh()];
+    scene.world.insert(root, MeshRenderer { meshes });
+    let file = path.to_owned();
+    let import = assets.decode(move || import(&file));
+    assets.watch_model(path);
+    scene.world.insert(
+        root,
+        ModelRoot {
+            path: path.to_owned(),
+        },
+    );
+    scene.world.insert(
+        root,
+        PendingModel {
+            path: path.to_owned(),
+            import,
+        },
+    );
+    root
+}
+
+/// Draws on `entity` the parts of a mesh cooked by the `cook` binary with
+/// the material file at `material`, once read in the background by
+/// `finish_loading`. Shows a placeholder meanwhile.
+pub fn load_cooked(
+    scene: &mut Scene,
+    assets: &mut Assets,
+    path: &Path,
+    material: &Path,
+    entity: Entity,
+) -> Result<(), MaterialError> {
+    // Nodes drawing the same file with different materials need their own parts
+    let name = format!("{}+{}", path.display(), material.display());
+    let material = assets.material(material)?;
+    let meshes = vec![assets.placeholder_mesh()];
+    scene.world.insert(entity, MeshRenderer { meshes });
+    let file = path.to_owned();
+    let parts = assets.decode(move || cooked::read_mesh(&vfs::read(&file)?));
+    scene.world.insert(
+        entity,
+        PendingCookedMesh {
+            path: path.to_owned(),
+            name,
+            material,
+            parts,
+        },
+    );
+    Ok(())
+}
+
+/// Reads a glTF file and the buffers and images it references through
+/// `vfs`, decoding the images and building the vertices of the primitives,
+/// reordered to be drawn faster.
+pub fn import(path: &Path) -> gltf::Result<Import> {
+    let gltf::Gltf { document, mut blob } =
+        gltf::Gltf::from_slice(&vfs::read(path).map_err(gltf::Error::Io)?)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    // Relative URIs are read through `vfs`, embedded data, views and other
+    // schemes are left to the gltf crate
+    let buffers = document
+        .buffers()
+        .map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Uri(uri) if is_relative(uri) => {
+                vfs::read(&uri_path(directory, uri)?)
+                    .map(gltf::buffer::Data)
+                    .map_err(gltf::Error::Io)
+            }
+            source => gltf::buffer::Data::from_source_and_blob(source, None, &mut blob),
+        })
+        .collect::<gltf::Result<Vec<_>>>()?;
+    let images = document
+        .images()
+        .map(|image| match image.source() {
+            gltf::image::Source::Uri { uri, .. } if is_relative(uri) => {
+                let path = uri_path(directory, uri)?;
+                let bytes = vfs::read(&path).map_err(gltf::Error::Io)?;
+                // gltf uses another version of the image crate
+                let image = image::load_from_memory(&bytes)
+                    .map_err(|err| {
+                        gltf::Error::Io(io::Error::new(io::ErrorKind::InvalidData, err))
+                    })?
+                    .into_rgba8();
+                Ok(gltf::image::Data {
+                    format: Format::R8G8B8A8,
+                    width: image.width(),
+                    height: image.height(),
+                    pixels: image.into_raw(),
+                })
+            }
+            source => gltf::image::Data::from_source(source, Some(directory), &buffers),
+        })
+        .collect::<gltf::Result<Vec<_>>>()?;
+
+    let mut primitives = HashMap::new();
+    let mut stats = vec![];
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives().filter(is_supported) {
+            let mut data = read_primitive(&primitive, &buffers);
+            stats.push(optimize::optimize(&mut data));
+            primitives.insert((mesh.index(), primitive.index()), data);
+        }
+    }
+    let stats: OptimizeStats = stats.into_iter().sum();
+    debug!("Optimized {}: {stats}", path.display());
+    Ok(Import {
+        document,
+        primitives,
+        images: images.iter().map(image_data).collect(),
+    })
+}
+
+fn is_relative(uri: &str) -> bool {
+    !uri.contains(':')
+}
+
+/// Path of the file at a relative URI of a glTF file in `directory`,
+/// percent-decoded as the gltf crate does.
+fn uri_path(directory: &Path, uri: &str) -> gltf::Result<PathBuf> {
+    let uri = urlencoding::decode(uri)
+        .map_err(|err| gltf::Error::Io(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+    Ok(directory.join(&*uri))
+}
+
+/// Adds the nodes of the models imported and the cooked meshes read since
+/// the last call, until `deadline`. Errors are logged, the placeholder
+/// being removed. Models reloaded by `Assets` then replace their nodes.
+pub fn finish_loading(scene: &mut Scene, assets: &mut Assets, deadline: Instant) {
+    finish_cooked(scene, assets, deadline);
+    finish_reloads(scene, assets, deadline);
+
+    let pending: Vec<_> = scene
+        .world
+        .query::<PendingModel>()
+        .map(|(entity, _)| entity)
+        .collect();
+    for root in pending {
+        if Instant::now() >= deadline {
+            break;
+        }
+        let import = match scene
+            .world
+            .get::<PendingModel>(root)
+            .unwrap()
+            .import
+            .poll_result()
+        {
+            Some(import) => import,
+            None => continue,
+        };
+        let model = scene.world.remove::<PendingModel>(root).unwrap();
+        scene.world.remove::<MeshRenderer>(root);
+        match import {
+            Ok(import) => add_nodes(scene, assets, &model.path, root, &import),
+            Err(err) => error!("Unable to load {}: {err}", model.path.display()),
+        }
+    }
+}
+
+fn finish_reloads(scene: &mut Scene, assets: &mut Assets, deadline: Instant) {
+    while Instant::now() < deadline {
+        match assets.take_reloaded_model() {
+            Some((path, Ok(import))) => reload(scene, assets, &path, &import),
+            Some((path, Err(err))) => error!("Unable to reload {}: {err}", path.display()),
+            None => break,
+        }
+    }
+}
+
+fn finish_cooked(scene: &mut Scene, assets: &mut Assets, deadline: Instant) {
+    let pending: Vec<_> = scene
+        .world
+        .query::<PendingCookedMesh>()
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in pending {
+        if Instant::now() >= deadline {
+            break;
+        }
+        let parts = match scene
+            .world
+            .get::<PendingCookedMesh>(entity)
+            .unwrap()
+            .parts
+            .poll_result()
+        {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let cooked = scene.world.remove::<PendingCookedMesh>(entity).unwrap();
+        match parts {
+            Ok(parts) => {
+                let meshes = parts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, part)| {
+                        let name = format!("{}#part{}", cooked.name, i);
+                        let material = cooked.material.clone();
+                        assets.mesh(&name, |_| Mesh::from_cooked(part, material))
+                    })
+                    .collect();
+                scene.world.insert(entity, MeshRenderer { meshes });
+            }
+            Err(err) => {
+                error!("Unable to load {}: {err}", cooked.path.display());
+                scene.world.remove::<MeshRenderer>(entity);
+            }
+        }
+    }
+}
+
+/// Adds an entity per glTF node under `root`, keeping the hierarchy and
+/// local transforms of the file.
+///
+/// Meshes, materials and images are named after their index in the file in
+/// `assets`, so that nodes instancing a glTF mesh share its primitives and
+/// primitives sharing a glTF material share its textures.
+fn add_nodes(scene: &mut Scene, assets: &mut Assets, path: &Path, root: Entity, import: &Import) {
+    let gltf_scene = import
+        .document
+        .default_scene()
+        .or_else(|| import.document.scenes().next());
+    let mut nodes: Vec<_> = gltf_scene
+        .into_iter()
+        .flat_map(|scene| scene.nodes())
+        .map(|node| (node, root))
+        .collect();
+
+    while let Some((node, parent)) = nodes.pop() {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let entity = scene.spawn(
+            Some(parent),
+            Transform {
+                translation: Vec3::from(translation),
+                rotation: Quat::from_array(rotation),
+                scale: Vec3::from(scale),
+            },
+        );
+        nodes.extend(node.children().map(|child| (child, entity)));
+
+        let mesh = match node.mesh() {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        let meshes = mesh
+            .primitives()
+            .filter(is_supported)
+            .map(|primitive| {
+                let name = mesh_name(path, &mesh, &primitive);
+                let data = &import.primitives[&(mesh.index(), primitive.index())];
+                assets.mesh(&name, |assets| {
+                    load_primitive(&primitive, data, &import.images, path, assets)
+                })
+            })
+            .collect();
+        scene.world.insert(entity, MeshRenderer { meshes });
+    }
+}
+
+/// Replaces the data of the meshes and images of a model file still loaded
+/// in `assets` with the one of `import`, read again from `path`, then the
+/// nodes of the models loaded from it. Changes of the materials need a
+/// restart.
+fn reload(scene: &mut Scene, assets: &mut Assets, path: &Path, import: &Import) {
+    for mesh in import.document.meshes() {
+        for primitive in mesh.primitives().filter(is_supported) {
+            if let Some(loaded) = assets.loaded_mesh(&mesh_name(path, &mesh, &primitive)) {
+                let data = &import.primitives[&(mesh.index(), primitive.index())];
+                loaded.update(&data.vertices, &data.indices);
+            }
+        }
+    }
+    for (index, image) in import.images.iter().enumerate() {
+        for texture in assets.loaded_textures(Path::new(&image_name(path, index))) {
+            texture.upload(image);
+        }
+    }
+
+    let roots: Vec<_> = scene
+        .world
+        .query::<ModelRoot>()
+        .filter(|(root, model)| {
+            model.path == path && scene.world.get::<PendingModel>(*root).is_none()
+        })
+        .map(|(root, _)| root)
+        .collect();
+    for root in roots {
+        // Adding the new nodes first keeps the meshes they share with the
+        // old ones loaded
+        let old_nodes = scene.children(root);
+        add_nodes(scene, assets, path, root, import);
+        for node in old_nodes {
+            scene.despawn(node);
+        }
+    }
+}
+
+fn is_supported(primitive: &gltf::Primitive) -> bool {
+    primitive.mode() == Mode::Triangles && primitive.get(&Semantic::Positions).is_some()
+}
+
+fn mesh_name(path: &Path, mesh: &gltf::Mesh, primitive: &gltf::Primitive) -> String {
+    format!(
+        "{}#mesh{}/primitive{}",
+        path.display(),
+        mesh.index(),
+        primitive.index()
+    )
+}
+
+/// Model file of a mesh named by `mesh_name`, `None` for other meshes.
+pub fn mesh_file(name: &str) -> Option<PathBuf> {
+    let (path, primitive) = name.rsplit_once('#')?;
+    primitive.starts_with("mesh").then(|| PathBuf::from(path))
+}
+
+fn image_name(path: &Path, index: usize) -> String {
+    format!("{}#image{}", path.display(), index)
+}
+
+/// Uploads the vertices of a primitive with its material.
+fn load_primitive(
+    primitive: &gltf::Primitive,
+    data: &MeshData,
+    images: &[TextureData],
+    path: &Path,
+    assets: &mut Assets,
+) -> Mesh {
+    let material = primitive.material();
+    let material_name = format!("{}#material{:?}", path.display(), material.index());
+    let material: Rc<Material> = assets.material_with(&material_name, |assets| {
+        load_material(&material, images, path, assets)
+    });
+
+    Mesh::new(&data.vertices, &data.indices, material)
+}
+
+/// Reads the vertices of a primitive, computing the attributes the file
+/// leaves out: flat normals as glTF requires, then tangents.
+fn read_primitive(primitive: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> MeshData {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let positions = reader.read_positions().into_iter().flatten();
+    let mut normals = reader.read_normals();
+    let has_normals = normals.is_some();
+    let mut tex_coords = reader.read_tex_coords(0).map(|t| t.into_f32());
+    let mut tangents = reader.read_tangents();
+    let mut vertices: Vec<Vertex> = positions
+        .map(|position| Vertex {
+            position: Vec3::from(position),
+            normals: normals
+                .as_mut()
+                .and_then(Iterator::next)
+                .map_or(Vec3::Y, Vec3::from),
+            color: Vec3::ONE,
+            texture_coordinates: tex_coords
+                .as_mut()
+                .and_then(Iterator::next)
+                .map_or(Vec2::ZERO, Vec2::from),
+            ..Vertex::default()
+        })
+        .collect();
+    if let Some(tangents) = tangents.as_mut() {
+        for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+            vertex.tangent = Vec3::new(tangent[0], tangent[1], tangent[2]);
+            vertex.bitangent_sign = tangent[3];
+        }
+    }
+    let indices = reader
+        .read_indices()
+        .map(|indices| indices.into_u32().collect());
+    let is_indexed = indices.is_some();
+    let indices = indices.unwrap_or_else(|| (0..vertices.len() as u32).collect());
+
+    let mut data = MeshData::new(vertices, indices);
+    // Non-indexed primitives repeat the vertices shared by triangles
+    if !is_indexed {
+        geometry::weld(&mut data, 0.0);
+    }
+    if !has_normals {
+        geometry::flat_normals(&mut data);
+    } else if tangents.is_none() {
+        geometry::compute_tangents(&mut data.vertices, &data.indices);
+    }
+    data
+}
+
+fn load_material(
+    material: &gltf::Material,
+    images: &[TextureData],
+    path: &Path,
+    assets: &mut Assets,
+) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let mut textures = vec![];
+    let mut load_texture = |texture: Option<gltf::Texture>, kind| {
+        if let Some(texture) = texture {
+            let index = texture.source().index();
+            let name = image_name(path, index);
+            textures.push(assets.texture_with(&name, kind, || Texture::new(&images[index], kind)));
+        }
+    };
+    load_texture(
+        pbr.base_color_texture().map(|info| info.texture()),
+        TextureKind::BaseColor,
+    );
+    load_texture(
+        pbr.metallic_roughness_texture().map(|info| info.texture()),
+        TextureKind::MetallicRoughness,
+    );
+    load_texture(
+        material.occlusion_texture().map(|info| info.texture()),
+        TextureKind::Occlusion,
+    );
+    load_texture(
+        material.emissive_texture().map(|info| info.texture()),
+        TextureKind::Emissive,
+    );
+
+    let mut loaded = Material::new(textures).with_factors(MaterialFactors {
+        base_color: Vec4::from(pbr.base_color_factor()),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        occlusion_strength: material
+            .occlusion_texture()
+            .map_or(1.0, |info| info.strength()),
+        emissive: Vec3::from(material.emissive_factor()),
+    });
+    loaded.blend = match material.alpha_mode() {
+        AlphaMode::Opaque => BlendMode::Opaque,
+        AlphaMode::Mask => BlendMode::AlphaTest(material.alpha_cutoff().unwrap_or(0.5)),
+        AlphaMode::Blend => BlendMode::AlphaBlend,
+    };
+    if material.unlit() {
+        loaded.variant = ShaderVariant::Unlit;
+    }
+    loaded
+}
+
+/// Texels of a decoded glTF image as RGBA. glTF texture coordinates start
+/// from the top row, so unlike image files the image is not flipped.
+fn image_data(image: &gltf::image::Data) -> TextureData {
+    let texels = match image.format {
+        Format::R8G8B8A8 => image.pixels.clone(),
+        Format::R8G8B8 => image
+            .pixels
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        Format::R8G8 => image
+            .pixels
+            .chunks_exact(2)
+            .flat_map(|rg| [rg[0], rg[1], 0, 255])
+            .collect(),
+        Format::R8 => image.pixels.iter().flat_map(|&r| [r, r, r, 255]).collect(),
+        format => {
+            warn!("Unsupported glTF image format {format:?}, using a white texture");
+            vec![255; (image.width * image.height * 4) as usize]
+        }
+    };
+    TextureData::rgba(image.width, image.height, texels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mesh_files() {
+        assert_eq!(
+            mesh_file("res/a#b/scene.gltf#mesh2/primitive0"),
+            Some(PathBuf::from("res/a#b/scene.gltf"))
+        );
+        assert_eq!(mesh_file("res/a.cmesh+res/m.ron#part0"), None);
+        assert_eq!(mesh_file("placeholder"), None);
+    }
+
+    #[test]
+    fn uri_paths() {
+        let directory = Path::new("res/sword");
+        assert_eq!(
+            uri_path(directory, "textures/Base%20Color.png").unwrap(),
+            Path::new("res/sword/textures/Base Color.png")
+        );
+        assert_eq!(
+            uri_path(directory, "scene.bin").unwrap(),
+            Path::new("res/sword/scene.bin")
+        );
+        assert!(uri_path(directory, "%FF.png").is_err());
+        assert!(is_relative("Base%20Color.png"));
+        assert!(!is_relative("data:application/octet-stream;base64,AAAA"));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::framebuffer::Framebuffer;
+use crate::render_queue::TransparentQueue;
+use crate::{gl, Camera, Shader, Vao};
+use std::path::Path;
+
+const ACCUMULATION_ATTACHMENT: usize = 0;
+const REVEALAGE_ATTACHMENT: usize = 1;
+
+/// Weighted blended order-independent transparency: the transparent
+/// fragments are summed weighted by depth and alpha into an accumulation
+/// target, while a revealage target keeps the product of their 1 - alpha,
+/// then the average color is composited over the opaque scene.
+///
+/// Exact for a single layer and an approximation otherwise, but intersecting
+/// meshes need no sorting.
+pub struct WeightedBlendedOit {
+    /// Accumulation (RGBA16F), revealage (R8) and a copy of the scene depth
+    framebuffer: Framebuffer,
+    accumulate_shader: Shader,
+    composite_shader: Shader,
+    vao: Vao,
+}
+
+impl WeightedBlendedOit {
+    pub fn new(width: u32, height: u32) -> Self {
+        let composite_shader = Shader::new(
+            Path::new("shaders/post/fullscreen.vert"),
+            Path::new("shaders/oit/composite.frag"),
+        );
+        composite_shader.bind();
+        composite_shader.set_uniform_1i("uAccumulation", ACCUMULATION_ATTACHMENT as i32);
+        composite_shader.set_uniform_1i("uRevealage", REVEALAGE_ATTACHMENT as i32);
+        composite_shader.unbind();
+
+        WeightedBlendedOit {
+            framebuffer: Framebuffer::new(width, height)
+                .with_color_attachment(gl::RGBA16F)
+                .with_color_attachment(gl::R8)
+                .with_depth_attachment(),
+            accumulate_shader: Shader::new(
+                Path::new("shaders/default.vert"),
+                Path::new("shaders/oit/accumulate.frag"),
+            ),
+            composite_shader,
+            vao: Vao::new(),
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.framebuffer.resize(width, height);
+    }
+
+    /// Accumulates the alpha blended and premultiplied meshes of `queue`
+    /// against the depth of `target`, then composites them into `target`.
+    /// `bind_lighting` sets the light, shadow and cluster uniforms.
+    pub fn render(
+        &mut self,
+        queue: &TransparentQueue,
+        target: &Framebuffer,
+        camera: &Camera,
+        bind_lighting: impl FnOnce(&Shader),
+    ) {
+        target.copy_depth_into(&self.framebuffer);
+        self.framebuffer.bind();
+        let id = self.framebuffer.id;
+        unsafe {
+            gl::ClearNamedFramebufferfv(
+                id,
+                gl::COLOR,
+                ACCUMULATION_ATTACHMENT as i32,
+                [0.0; 4].as_ptr(),
+            );
+            gl::ClearNamedFramebufferfv(
+                id,
+                gl::COLOR,
+                REVEALAGE_ATTACHMENT as i32,
+                [1.0; 4].as_ptr(),
+            );
+
+            gl::Enable(gl::BLEND);
+            gl::BlendFunci(ACCUMULATION_ATTACHMENT as u32, gl::ONE, gl::ONE);
+            gl::BlendFunci(
+                REVEALAGE_ATTACHMENT as u32,
+                gl::ZERO,
+                gl::ONE_MINUS_SRC_COLOR,
+            );
+            gl::DepthMask(gl::FALSE);
+        }
+
+        self.accumulate_shader.bind();
+        self.accumulate_shader
+            .set_uniform_mat4("uCameraViewProjection", camera.get_matrix());
+        bind_lighting(&self.accumulate_shader);
+        // The occlusion buffer only covers the opaque surfaces
+        self.accumulate_shader.set_uniform_1i("uSsaoEnabled", 0);
+        queue.draw_order_independent(&self.accumulate_shader);
+        self.accumulate_shader.unbind();
+
+        target.bind();
+        self.framebuffer
+            .bind_color_texture(ACCUMULATION_ATTACHMENT, ACCUMULATION_ATTACHMENT as u32);
+        self.framebuffer
+            .bind_color_texture(REVEALAGE_ATTACHMENT, REVEALAGE_ATTACHMENT as u32);
+        self.composite_shader.bind();
+        self.vao.bind();
+        unsafe {
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            gl::Enable(gl::DEPTH_TEST);
+            gl::DepthMask(gl::TRUE);
+            gl::Disable(gl::BLEND);
+        }
+        self.vao.unbind();
+        self.composite_shader.unbind();
+    }
+}
@@ -0,0 +1,513 @@
+use crate::geometry::{self, MeshData};
+use crate::vertex::Vertex;
+use glam::Vec3;
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Sum;
+
+/// Entries of the FIFO post-transform cache simulated to measure index
+/// orders, a common size on current GPUs.
+pub const FIFO_CACHE_SIZE: usize = 16;
+/// Largest increase of the ACMR allowed when reordering for overdraw.
+pub const OVERDRAW_THRESHOLD: f32 = 1.05;
+
+/// Entries of the LRU cache modelled when ordering triangles for the cache.
+const LRU_CACHE_SIZE: usize = 32;
+/// Score of the vertices of the last triangle, lower than the next ones so
+/// that strips do not turn back.
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const CACHE_DECAY_POWER: f32 = 1.5;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// Average cache miss ratios of the indices of a mesh before and after
+/// `optimize`. Summing the stats of several meshes averages their ratios
+/// over all their triangles.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OptimizeStats {
+    pub triangles: usize,
+    pub acmr_before: f32,
+    pub acmr_after: f32,
+}
+
+impl Sum for OptimizeStats {
+    fn sum<I: Iterator<Item = Self>>(stats: I) -> Self {
+        let (triangles, misses_before, misses_after) =
+            stats.fold((0, 0.0, 0.0), |(triangles, before, after), stats| {
+                let count = stats.triangles as f32;
+                (
+                    triangles + stats.triangles,
+                    before + stats.acmr_before * count,
+                    after + stats.acmr_after * count,
+                )
+            });
+        let count = triangles.max(1) as f32;
+        OptimizeStats {
+            triangles,
+            acmr_before: misses_before / count,
+            acmr_after: misses_after / count,
+        }
+    }
+}
+
+impl fmt::Display for OptimizeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} triangles, ACMR {:.3} -> {:.3}",
+            self.triangles, self.acmr_before, self.acmr_after
+        )
+    }
+}
+
+/// Reorders the triangles of `mesh` for the vertex cache then to draw the
+/// outer ones first, and its vertices in the order they are used.
+pub fn optimize(mesh: &mut MeshData) -> OptimizeStats {
+    let acmr_before = acmr(&mesh.indices, FIFO_CACHE_SIZE);
+    optimize_vertex_cache(&mut mesh.indices, mesh.vertices.len());
+    optimize_overdraw(&mut mesh.indices, &mesh.vertices, OVERDRAW_THRESHOLD);
+    geometry::reorder_vertices(&mut mesh.vertices, &mut mesh.indices);
+    OptimizeStats {
+        triangles: mesh.indices.len() / 3,
+        acmr_before,
+        acmr_after: acmr(&mesh.indices, FIFO_CACHE_SIZE),
+    }
+}
+
+/// Average number of vertices transformed per triangle drawing `indices`
+/// with a FIFO cache of `cache_size` entries, between 0.5 for large regular
+/// grids and 3 without any reuse.
+pub fn acmr(indices: &[u32], cache_size: usize) -> f32 {
+    let triangles = indices.len() / 3;
+    if triangles == 0 {
+        return 0.0;
+    }
+    fifo_misses(indices, cache_size) as f32 / triangles as f32
+}
+
+/// Number of vertices transformed drawing `indices` with a FIFO cache.
+fn fifo_misses(indices: &[u32], cache_size: usize) -> usize {
+    let mut cache = FifoCache::new(cache_size);
+    indices.iter().filter(|&&index| cache.access(index)).count()
+}
+
+/// Post-transform cache that replaces its oldest entry on misses.
+struct FifoCache {
+    entries: Vec<u32>,
+    next: usize,
+}
+
+impl FifoCache {
+    fn new(size: usize) -> Self {
+        FifoCache {
+            entries: vec![u32::MAX; size],
+            next: 0,
+        }
+    }
+
+    /// Returns whether `index` missed, inserting it.
+    fn access(&mut self, index: u32) -> bool {
+        if self.entries.contains(&index) {
+            return false;
+        }
+        self.entries[self.next] = index;
+        self.next = (self.next + 1) % self.entries.len();
+        true
+    }
+
+    fn clear(&mut self) {
+        self.entries.fill(u32::MAX);
+        self.next = 0;
+    }
+}
+
+/// Reorders the triangles so that their vertices are still in the cache
+/// when used again (Tom Forsyth's linear-speed vertex cache optimisation):
+/// the next triangle is the one whose vertices score the most, for being
+/// recently used or having few triangles left.
+pub fn optimize_vertex_cache(indices: &mut [u32], vertex_count: usize) {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return;
+    }
+
+    // Triangles using each vertex, the first `remaining[v]` being the ones
+    // not emitted yet
+    let mut remaining = vec![0usize; vertex_count];
+    for &index in indices.iter() {
+        remaining[index as usize] += 1;
+    }
+    let mut offsets = Vec::with_capacity(vertex_count + 1);
+    offsets.push(0);
+    for &count in &remaining {
+        offsets.push(offsets.last().unwrap() + count);
+    }
+    let mut adjacency = vec![0usize; indices.len()];
+    let mut filled = offsets.clone();
+    for (triangle, vertices) in indices.chunks_exact(3).enumerate() {
+        for &index in vertices {
+            adjacency[filled[index as usize]] = triangle;
+            filled[index as usize] += 1;
+        }
+    }
+
+    let mut scores: Vec<f32> = remaining
+        .iter()
+        .map(|&count| vertex_score(None, count))
+        .collect();
+    let triangle_score = |triangle: usize, scores: &[f32]| -> f32 {
+        (0..3)
+            .map(|k| scores[indices[triangle * 3 + k] as usize])
+            .sum()
+    };
+
+    let mut emitted = vec![false; triangle_count];
+    let mut order = Vec::with_capacity(triangle_count);
+    let mut cache: Vec<u32> = Vec::with_capacity(LRU_CACHE_SIZE + 3);
+    let mut best = None;
+    let mut cursor = 0;
+    while order.len() < triangle_count {
+        // Starts again from the next triangle in the input order when none
+        // share a vertex with the cache
+        let triangle = match best {
+            Some(triangle) => triangle,
+            None => {
+                while emitted[cursor] {
+                    cursor += 1;
+                }
+                cursor
+            }
+        };
+        emitted[triangle] = true;
+        order.push(triangle);
+
+        let vertices = [0, 1, 2].map(|k| indices[triangle * 3 + k]);
+        for &vertex in &vertices {
+            let vertex = vertex as usize;
+            let used = &mut adjacency[offsets[vertex]..offsets[vertex] + remaining[vertex]];
+            let position = used.iter().position(|&t| t == triangle).unwrap();
+            used.swap(position, used.len() - 1);
+            remaining[vertex] -= 1;
+        }
+
+        // Moves the vertices of the triangle to the front of the cache
+        let mut new_cache = vertices.to_vec();
+        new_cache.extend(cache.iter().filter(|index| !vertices.contains(index)));
+        for &evicted in new_cache.iter().skip(LRU_CACHE_SIZE) {
+            scores[evicted as usize] = vertex_score(None, remaining[evicted as usize]);
+        }
+        new_cache.truncate(LRU_CACHE_SIZE);
+        cache = new_cache;
+        for (position, &vertex) in cache.iter().enumerate() {
+            scores[vertex as usize] = vertex_score(Some(position), remaining[vertex as usize]);
+        }
+
+        // Only triangles using a cached vertex can score more than the others
+        best = None;
+        let mut best_score = f32::MIN;
+        for &vertex in &cache {
+            let vertex = vertex as usize;
+            for &other in &adjacency[offsets[vertex]..offsets[vertex] + remaining[vertex]] {
+                let score = triangle_score(other, &scores);
+                if score > best_score {
+                    best = Some(other);
+                    best_score = score;
+                }
+            }
+        }
+    }
+
+    let reordered: Vec<u32> = order
+        .iter()
+        .flat_map(|&triangle| [0, 1, 2].map(|k| indices[triangle * 3 + k]))
+        .collect();
+    indices[..reordered.len()].copy_from_slice(&reordered);
+}
+
+/// Score of a vertex at `cache_position` in the LRU cache used by
+/// `remaining` triangles not emitted yet.
+fn vertex_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        None => 0.0,
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scale = 1.0 / (LRU_CACHE_SIZE - 3) as f32;
+            (1.0 - (position - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+        }
+    };
+    // Vertices with few triangles left are finished first to avoid leaving
+    // lone triangles behind
+    cache_score + VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+/// Reorders the clusters of triangles drawn in a row so that those facing
+/// away from the center of the mesh, likely to hide others, come first
+/// (Sander et al.'s Tipsify overdraw pass). `indices` should already be
+/// ordered for the vertex cache, clusters being cut where they can be
+/// without raising the ACMR more than `threshold` times.
+pub fn optimize_overdraw(indices: &mut [u32], vertices: &[Vertex], threshold: f32) {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return;
+    }
+    let clusters = clusters(indices, threshold);
+
+    let position = |index: u32| vertices[index as usize].position;
+    // Area weighted centroid and normal of each cluster
+    let mut areas = Vec::with_capacity(clusters.len());
+    let mut centroids = Vec::with_capacity(clusters.len());
+    let mut normals = Vec::with_capacity(clusters.len());
+    for (i, &start) in clusters.iter().enumerate() {
+        let end = clusters.get(i + 1).copied().unwrap_or(triangle_count);
+        let mut area = 0.0;
+        let mut centroid = Vec3::ZERO;
+        let mut normal = Vec3::ZERO;
+        for triangle in indices[start * 3..end * 3].chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| position(triangle[k]));
+            let cross = (b - a).cross(c - a);
+            let triangle_area = cross.length() / 2.0;
+            area += triangle_area;
+            centroid += (a + b + c) / 3.0 * triangle_area;
+            normal += cross;
+        }
+        centroids.push(if area > 0.0 {
+            centroid / area
+        } else {
+            centroid
+        });
+        areas.push(area);
+        normals.push(normal.normalize_or_zero());
+    }
+    let total_area: f32 = areas.iter().sum();
+    let mesh_centroid = if total_area > 0.0 {
+        centroids
+            .iter()
+            .zip(&areas)
+            .fold(Vec3::ZERO, |sum, (&centroid, &area)| sum + centroid * area)
+            / total_area
+    } else {
+        Vec3::ZERO
+    };
+
+    let keys: Vec<f32> = centroids
+        .iter()
+        .zip(&normals)
+        .map(|(&centroid, &normal)| (centroid - mesh_centroid).dot(normal))
+        .collect();
+    let mut order: Vec<usize> = (0..clusters.len()).collect();
+    order.sort_by(|&a, &b| keys[b].partial_cmp(&keys[a]).unwrap_or(Ordering::Equal));
+
+    let reordered: Vec<u32> = order
+        .iter()
+        .flat_map(|&cluster| {
+            let start = clusters[cluster];
+            let end = clusters.get(cluster + 1).copied().unwrap_or(triangle_count);
+            indices[start * 3..end * 3].iter().copied()
+        })
+        .collect();
+    indices.copy_from_slice(&reordered);
+}
+
+/// First triangles of the clusters the overdraw pass can move around: the
+/// cache is cold where all vertices of a triangle miss, and runs between
+/// those are cut again as soon as their own ACMR is within `threshold`
+/// times the ACMR of the run.
+fn clusters(indices: &[u32], threshold: f32) -> Vec<usize> {
+    let triangle_count = indices.len() / 3;
+    let mut cache = FifoCache::new(FIFO_CACHE_SIZE);
+    let mut hard = Vec::new();
+    for (triangle, vertices) in indices.chunks_exact(3).enumerate() {
+        let misses = vertices
+            .iter()
+            .filter(|&&index| cache.access(index))
+            .count();
+        if misses == 3 || triangle == 0 {
+            hard.push(triangle);
+        }
+    }
+
+    let mut clusters = Vec::new();
+    for (i, &start) in hard.iter().enumerate() {
+        let end = hard.get(i + 1).copied().unwrap_or(triangle_count);
+        let run = &indices[start * 3..end * 3];
+        let limit = fifo_misses(run, FIFO_CACHE_SIZE) as f32 / (end - start) as f32 * threshold;
+
+        // Each cluster starts with a cold cache as it may follow any other
+        cache.clear();
+        clusters.push(start);
+        let mut cluster_start = start;
+        let mut misses = 0;
+        for triangle in start..end {
+            misses += indices[triangle * 3..triangle * 3 + 3]
+                .iter()
+                .filter(|&&index| cache.access(index))
+                .count();
+            let cluster_acmr = misses as f32 / (triangle + 1 - cluster_start) as f32;
+            if cluster_acmr <= limit && triangle + 1 < end {
+                cache.clear();
+                clusters.push(triangle + 1);
+                cluster_start = triangle + 1;
+                misses = 0;
+            }
+        }
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Grid of `size` by `size` quads in the XY plane, facing +Z.
+    fn grid(size: u32) -> MeshData {
+        let vertices = (0..=size)
+            .flat_map(|y| {
+                (0..=size).map(move |x| Vertex {
+                    position: Vec3::new(x as f32, y as f32, 0.0),
+                    ..Vertex::default()
+                })
+            })
+            .collect();
+        let indices = (0..size)
+            .flat_map(|y| {
+                (0..size).flat_map(move |x| {
+                    let corner = y * (size + 1) + x;
+                    let above = corner + size + 1;
+                    [corner, corner + 1, above + 1, corner, above + 1, above]
+                })
+            })
+            .collect();
+        MeshData::new(vertices, indices)
+    }
+
+    /// Shuffles the triangles in a reproducible order.
+    fn shuffle(indices: &mut [u32]) {
+        let mut triangles: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+        let mut state = 12345u32;
+        for i in (1..triangles.len()).rev() {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            triangles.swap(i, (state >> 8) as usize % (i + 1));
+        }
+        indices.copy_from_slice(&triangles.concat());
+    }
+
+    /// Triangles of a mesh as positions, sorted to compare meshes whose
+    /// triangles and vertices were reordered.
+    fn triangles(mesh: &MeshData) -> Vec<[[u32; 3]; 3]> {
+        let mut triangles: Vec<_> = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                [0, 1, 2].map(|k| {
+                    let position = mesh.vertices[triangle[k] as usize].position;
+                    position.to_array().map(f32::to_bits)
+                })
+            })
+            .collect();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn acmr_counts_misses() {
+        assert_eq!(acmr(&[], FIFO_CACHE_SIZE), 0.0);
+        assert_eq!(acmr(&[0, 1, 2, 3, 4, 5], FIFO_CACHE_SIZE), 3.0);
+        assert_eq!(acmr(&[0, 1, 2, 0, 2, 3], FIFO_CACHE_SIZE), 2.0);
+        // With 3 entries, 3 evicts 0 which then evicts 1
+        assert_eq!(acmr(&[0, 1, 2, 2, 1, 3, 0, 3, 1], 3), 2.0);
+    }
+
+    #[test]
+    fn vertex_cache_keeps_triangles() {
+        let mut mesh = grid(20);
+        shuffle(&mut mesh.indices);
+        let mut optimized = mesh.clone();
+        optimize_vertex_cache(&mut optimized.indices, optimized.vertices.len());
+        assert_eq!(triangles(&optimized), triangles(&mesh));
+    }
+
+    #[test]
+    fn vertex_cache_lowers_acmr() {
+        let mut mesh = grid(32);
+        shuffle(&mut mesh.indices);
+        let before = acmr(&mesh.indices, FIFO_CACHE_SIZE);
+        optimize_vertex_cache(&mut mesh.indices, mesh.vertices.len());
+        let after = acmr(&mesh.indices, FIFO_CACHE_SIZE);
+        assert!(before > 2.0, "{before}");
+        assert!(after < 0.8, "{after}");
+    }
+
+    #[test]
+    fn overdraw_draws_front_clusters_first() {
+        let quad = |z: f32| {
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].map(|(x, y)| Vertex {
+                position: Vec3::new(x, y, z),
+                ..Vertex::default()
+            })
+        };
+        let vertices: Vec<Vertex> = quad(-1.0).into_iter().chain(quad(1.0)).collect();
+        let mut indices = [0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7];
+        optimize_overdraw(&mut indices, &vertices, OVERDRAW_THRESHOLD);
+        assert_eq!(indices, [4, 5, 6, 4, 6, 7, 0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn overdraw_keeps_acmr_close() {
+        let mut mesh = grid(32);
+        shuffle(&mut mesh.indices);
+        optimize_vertex_cache(&mut mesh.indices, mesh.vertices.len());
+        let cache_optimized = mesh.clone();
+        optimize_overdraw(&mut mesh.indices, &mesh.vertices, OVERDRAW_THRESHOLD);
+        assert_eq!(triangles(&mesh), triangles(&cache_optimized));
+        let before = acmr(&cache_optimized.indices, FIFO_CACHE_SIZE);
+        let after = acmr(&mesh.indices, FIFO_CACHE_SIZE);
+        assert!(
+            after <= before * OVERDRAW_THRESHOLD + 0.05,
+            "{before} {after}"
+        );
+    }
+
+    #[test]
+    fn optimize_reports_and_orders_vertices() {
+        let mut mesh = grid(16);
+        shuffle(&mut mesh.indices);
+        let original = mesh.clone();
+        let stats = optimize(&mut mesh);
+        assert_eq!(stats.acmr_before, acmr(&original.indices, FIFO_CACHE_SIZE));
+        assert_eq!(stats.acmr_after, acmr(&mesh.indices, FIFO_CACHE_SIZE));
+        assert!(stats.acmr_after < stats.acmr_before);
+        assert_eq!(triangles(&mesh), triangles(&original));
+
+        // Vertices are fetched in the order they are stored
+        let mut next = 0;
+        for &index in &mesh.indices {
+            assert!(index <= next);
+            if index == next {
+                next += 1;
+            }
+        }
+        assert_eq!(next as usize, mesh.vertices.len());
+        assert_eq!(stats.triangles, 16 * 16 * 2);
+    }
+
+    #[test]
+    fn stats_sum_over_triangles() {
+        let stats = |triangles, acmr_before, acmr_after| OptimizeStats {
+            triangles,
+            acmr_before,
+            acmr_after,
+        };
+        let total: OptimizeStats = [stats(10, 3.0, 1.0), stats(30, 1.0, 0.6)].into_iter().sum();
+        assert_eq!(total, stats(40, 1.5, 0.7));
+        assert_eq!(
+            std::iter::empty().sum::<OptimizeStats>(),
+            OptimizeStats::default()
+        );
+    }
+}
@@ -1,83 +1,88 @@
-use crate::{Camera, Mesh, Shader, Vertex};
+use crate::geometry::MeshData;
+use crate::material::Material;
+use crate::shadow::CubeShadowMap;
+use crate::{primitives, Camera, Mesh, Shader};
 use glam::{Mat4, Vec3};
 use std::path::Path;
+use std::rc::Rc;
 
+/// Light emitted in every direction from a point, fading out over its range.
 pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Distance after which the light has no effect
+    pub range: f32,
+    pub shadow_map: Option<CubeShadowMap>,
+}
+
+impl PointLight {
+    pub fn new() -> Self {
+        PointLight {
+            position: Vec3::default(),
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: 10.0,
+            shadow_map: None,
+        }
+    }
+
+    pub fn with_shadow_map(mut self, shadow_map: CubeShadowMap) -> Self {
+        self.shadow_map = Some(shadow_map);
+        self
+    }
+
+    pub fn set_position(&mut self, new_position: Vec3) {
+        self.position = new_position;
+    }
+
+    /// Updates the shadow map, if any, from the current light position.
+    pub fn render_shadow_map(&mut self, draw_casters: impl FnMut(&Shader)) {
+        if let Some(shadow_map) = &mut self.shadow_map {
+            shadow_map.render(self.position, draw_casters);
+        }
+    }
+
+    /// Sets the shadow uniforms of a lighting shader, binding the shadow map to
+    /// `unit` if the light casts shadows.
+    pub fn bind_shadow_map(&self, shader: &Shader, unit: u32) {
+        if let Some(shadow_map) = &self.shadow_map {
+            shadow_map.bind(shader, unit);
+        }
+    }
+}
+
+/// Small cube drawn at the position of point lights, in their color.
+pub struct PointLightMarker {
     mesh: Mesh,
     shader: Shader,
-    pub position: Vec3,
     scale: Vec3,
 }
 
-impl PointLight {
+impl PointLightMarker {
     pub fn new() -> Self {
-        let vertices = vec![
-            Vertex {
-                position: Vec3::new(-0.5, -0.5, -0.5),
-                ..Vertex::default()
-            },
-            Vertex {
-                position: Vec3::new(0.5, -0.5, -0.5),
-                ..Vertex::default()
-            },
-            Vertex {
-                position: Vec3::new(0.5, -0.5, 0.5),
-                ..Vertex::default()
-            },
-            Vertex {
-                position: Vec3::new(-0.5, -0.5, 0.5),
-                ..Vertex::default()
-            },
-            Vertex {
-                position: Vec3::new(-0.5, 0.5, -0.5),
-                ..Vertex::default()
-            },
-            Vertex {
-                position: Vec3::new(0.5, 0.5, -0.5),
-                ..Vertex::default()
-            },
-            Vertex {
-                position: Vec3::new(0.5, 0.5, 0.5),
-                ..Vertex::default()
-            },
-            Vertex {
-                position: Vec3::new(-0.5, 0.5, 0.5),
-                ..Vertex::default()
-            },
-        ];
-        let indices = vec![
-            0, 1, 2, 2, 3, 0, // Face 1
-            0, 1, 5, 5, 4, 0, // Face 2
-            1, 2, 6, 6, 5, 1, // Face 3
-            2, 3, 7, 7, 6, 2, // Face 4
-            3, 0, 4, 4, 7, 3, // Face 5
-            4, 5, 6, 6, 7, 4, // Face 6
-        ];
-        let mesh = Mesh::new(vertices, indices, vec![]);
+        let MeshData { vertices, indices } = primitives::cube(1.0, 1);
+        let mesh = Mesh::new(&vertices, &indices, Rc::new(Material::default()));
 
         let shader = Shader::new(
             Path::new("shaders/light.vert"),
             Path::new("shaders/light.frag"),
         );
 
-        PointLight {
+        PointLightMarker {
             mesh,
             shader,
-            position: Vec3::default(),
-            scale: Vec3::splat(0.2),
+            scale: Vec3::splat(0.1),
         }
     }
 
-    pub fn draw(&mut self, camera: &Camera) {
-        let model = Mat4::from_translation(self.position) * Mat4::from_scale(self.scale);
+    pub fn draw(&mut self, camera: &Camera, light: &PointLight) {
+        let model = Mat4::from_translation(light.position) * Mat4::from_scale(self.scale);
         let mvp = camera.get_matrix() * model;
 
         self.shader.bind();
         self.shader.set_uniform_mat4("uMVP", mvp);
-        self.mesh.draw();
-    }
-
-    pub fn set_position(&mut self, new_position: Vec3) {
-        self.position = new_position;
+        self.shader.set_uniform_vec3("uColor", light.color);
+        self.mesh.draw(&self.shader);
     }
 }
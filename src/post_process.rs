@@ -0,0 +1,185 @@
+use crate::framebuffer::Framebuffer;
+use crate::texture::LutTexture;
+use crate::{gl, Shader, Vao};
+use glam::{Vec2, Vec3};
+use std::path::Path;
+
+const SCREEN_TEXTURE_UNIT: u32 = 0;
+const LUT_TEXTURE_UNIT: u32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub enum UniformValue {
+    Float(f32),
+    Vec2(Vec2),
+    Vec3(Vec3),
+}
+
+/// Fullscreen fragment shader applied to the output of the previous effect.
+///
+/// Every effect receives the previous image in `uScreenTexture`, the size of
+/// a texel in `uTexelSize` and the elapsed time in seconds in `uTime`.
+pub struct PostEffect {
+    pub name: String,
+    pub enabled: bool,
+    shader: Shader,
+    uniforms: Vec<(String, UniformValue)>,
+    lut: Option<LutTexture>,
+}
+
+impl PostEffect {
+    pub fn new(name: &str, fragment_file: &Path) -> Self {
+        let shader = Shader::new(Path::new("shaders/post/fullscreen.vert"), fragment_file);
+        PostEffect {
+            name: name.to_string(),
+            enabled: true,
+            shader,
+            uniforms: vec![],
+            lut: None,
+        }
+    }
+
+    pub fn with_uniform(mut self, name: &str, value: UniformValue) -> Self {
+        self.uniforms.push((name.to_string(), value));
+        self
+    }
+
+    /// Binds a color lookup table to the `uLut` sampler, its size being set
+    /// to `uLutSize`.
+    pub fn with_lut(mut self, lut: LutTexture) -> Self {
+        let size = UniformValue::Float(lut.size() as f32);
+        self.lut = Some(lut);
+        self.with_uniform("uLutSize", size)
+    }
+
+    fn apply(&mut self, input: &Framebuffer, time: f32) {
+        let texel_size = Vec2::new(1.0 / input.width() as f32, 1.0 / input.height() as f32);
+
+        self.shader.bind();
+        input.bind_color_texture(0, SCREEN_TEXTURE_UNIT);
+        self.shader
+            .set_uniform_1i("uScreenTexture", SCREEN_TEXTURE_UNIT as i32);
+        self.shader.set_uniform_vec2("uTexelSize", texel_size);
+        self.shader.set_uniform_1f("uTime", time);
+        if let Some(lut) = &self.lut {
+            lut.bind(LUT_TEXTURE_UNIT);
+            self.shader.set_uniform_1i("uLut", LUT_TEXTURE_UNIT as i32);
+        }
+        for (name, value) in &self.uniforms {
+            match *value {
+                UniformValue::Float(v) => self.shader.set_uniform_1f(name, v),
+                UniformValue::Vec2(v) => self.shader.set_uniform_vec2(name, v),
+                UniformValue::Vec3(v) => self.shader.set_uniform_vec3(name, v),
+            }
+        }
+
+        unsafe {
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+        self.shader.unbind();
+    }
+}
+
+/// Ordered chain of post-processing effects ping-ponged between two
+/// framebuffers, the last enabled effect writing to the screen.
+///
+/// The image is drawn rather than blitted to the screen so that the default
+/// framebuffer can be multisampled.
+pub struct PostProcessStack {
+    effects: Vec<PostEffect>,
+    copy: PostEffect,
+    targets: [Framebuffer; 2],
+    vao: Vao,
+    width: u32,
+    height: u32,
+}
+
+impl PostProcessStack {
+    pub fn new(width: u32, height: u32) -> Self {
+        let create_target = || Framebuffer::new(width, height).with_color_attachment(gl::RGBA16F);
+        PostProcessStack {
+            effects: vec![],
+            copy: PostEffect::new("Copy", Path::new("shaders/post/copy.frag")),
+            targets: [create_target(), create_target()],
+            vao: Vao::new(),
+            width,
+            height,
+        }
+    }
+
+    pub fn push(&mut self, effect: PostEffect) {
+        self.effects.push(effect);
+    }
+
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    /// Flips the enabled state of the effect at `index` and returns the new
+    /// state, or `None` if there is no such effect.
+    pub fn toggle(&mut self, index: usize) -> Option<bool> {
+        let effect = self.effects.get_mut(index)?;
+        effect.enabled = !effect.enabled;
+        Some(effect.enabled)
+    }
+
+    /// Moves the effect at `from` so that it ends up at position `to` in the
+    /// chain, shifting the effects in between.
+    pub fn move_effect(&mut self, from: usize, to: usize) {
+        if from >= self.effects.len() || to >= self.effects.len() {
+            return;
+        }
+        let effect = self.effects.remove(from);
+        self.effects.insert(to, effect);
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        for target in &mut self.targets {
+            target.resize(width, height);
+        }
+    }
+
+    /// Runs the enabled effects over the first color attachment of `scene`
+    /// and presents the result to the default framebuffer.
+    pub fn render(&mut self, scene: &Framebuffer, time: f32) {
+        let enabled: Vec<usize> = (0..self.effects.len())
+            .filter(|&i| self.effects[i].enabled)
+            .collect();
+
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+        }
+        self.vao.bind();
+
+        if enabled.is_empty() {
+            self.bind_screen();
+            self.copy.apply(scene, time);
+        }
+
+        let mut input = scene;
+        for (pass, &index) in enabled.iter().enumerate() {
+            let target = &self.targets[pass % 2];
+            if pass == enabled.len() - 1 {
+                self.bind_screen();
+            } else {
+                target.bind();
+            }
+
+            self.effects[index].apply(input, time);
+            input = target;
+        }
+
+        self.vao.unbind();
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+
+    fn bind_screen(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, self.width as i32, self.height as i32);
+        }
+    }
+}
@@ -0,0 +1,626 @@
+//! Meshes generated from a few parameters, centered on the origin. Their
+//! triangles are counter-clockwise seen from outside and their vertices
+//! are white, with tangents following the texture coordinates.
+
+use crate::geometry::{self, MeshData};
+use crate::vertex::Vertex;
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// Primitive with its parameters, as placed in scene files.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Plane {
+        size: f32,
+        subdivisions: u32,
+    },
+    Cube {
+        size: f32,
+        subdivisions: u32,
+    },
+    UvSphere {
+        radius: f32,
+        sectors: u32,
+        stacks: u32,
+    },
+    Icosphere {
+        radius: f32,
+        subdivisions: u32,
+    },
+    Cylinder {
+        radius: f32,
+        height: f32,
+        sectors: u32,
+        stacks: u32,
+    },
+    Cone {
+        radius: f32,
+        height: f32,
+        sectors: u32,
+        stacks: u32,
+    },
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+        sectors: u32,
+        sides: u32,
+    },
+    Capsule {
+        radius: f32,
+        height: f32,
+        sectors: u32,
+        rings: u32,
+    },
+}
+
+impl Shape {
+    pub fn mesh(&self) -> MeshData {
+        match *self {
+            Shape::Plane { size, subdivisions } => plane(size, subdivisions),
+            Shape::Cube { size, subdivisions } => cube(size, subdivisions),
+            Shape::UvSphere {
+                radius,
+                sectors,
+                stacks,
+            } => uv_sphere(radius, sectors, stacks),
+            Shape::Icosphere {
+                radius,
+                subdivisions,
+            } => icosphere(radius, subdivisions),
+            Shape::Cylinder {
+                radius,
+                height,
+                sectors,
+                stacks,
+            } => cylinder(radius, height, sectors, stacks),
+            Shape::Cone {
+                radius,
+                height,
+                sectors,
+                stacks,
+            } => cone(radius, height, sectors, stacks),
+            Shape::Torus {
+                major_radius,
+                minor_radius,
+                sectors,
+                sides,
+            } => torus(major_radius, minor_radius, sectors, sides),
+            Shape::Capsule {
+                radius,
+                height,
+                sectors,
+                rings,
+            } => capsule(radius, height, sectors, rings),
+        }
+    }
+}
+
+/// Square of side `size` on the XZ plane facing +Y, split in
+/// `subdivisions` squares per side. Texture v increases toward -Z.
+pub fn plane(size: f32, subdivisions: u32) -> MeshData {
+    let half = size / 2.0;
+    let mut mesh = MeshData::default();
+    add_grid(
+        &mut mesh,
+        Vec3::new(-half, 0.0, half),
+        Vec3::X * size,
+        -Vec3::Z * size,
+        subdivisions,
+    );
+    finish(mesh)
+}
+
+/// Axis aligned cube of side `size`, each face split in `subdivisions`
+/// squares per side and mapped to the whole texture.
+pub fn cube(size: f32, subdivisions: u32) -> MeshData {
+    let mut mesh = MeshData::default();
+    for normal in [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z] {
+        // Axes spanning the face counter-clockwise
+        let u = Vec3::new(normal.y, normal.z, normal.x);
+        let v = normal.cross(u);
+        let origin = (normal - u - v) * size / 2.0;
+        add_grid(&mut mesh, origin, u * size, v * size, subdivisions);
+    }
+    finish(mesh)
+}
+
+/// Sphere split in `sectors` meridians and `stacks` parallels, texture u
+/// going around the Y axis from +Z toward +X and v from the bottom pole.
+pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> MeshData {
+    let stacks = stacks.max(2);
+    let profile: Vec<_> = (0..=stacks)
+        .map(|i| {
+            let t = i as f32 / stacks as f32;
+            sphere_point(radius, 0.0, t * PI, 1.0 - t, i == stacks)
+        })
+        .collect();
+    let mut mesh = MeshData::default();
+    add_lathe(&mut mesh, &profile, sectors);
+    finish(mesh)
+}
+
+/// Sphere made by splitting each triangle of an icosahedron in 4,
+/// `subdivisions` times, for triangles of even sizes. Texture coordinates
+/// are the ones of `uv_sphere`.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut directions: Vec<Vec3> = [
+        (-1.0, t, 0.0),
+        (1.0, t, 0.0),
+        (-1.0, -t, 0.0),
+        (1.0, -t, 0.0),
+        (0.0, -1.0, t),
+        (0.0, 1.0, t),
+        (0.0, -1.0, -t),
+        (0.0, 1.0, -t),
+        (t, 0.0, -1.0),
+        (t, 0.0, 1.0),
+        (-t, 0.0, -1.0),
+        (-t, 0.0, 1.0),
+    ]
+    .into_iter()
+    .map(|(x, y, z)| Vec3::new(x, y, z).normalize())
+    .collect();
+    let mut triangles = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Edges are split once for the two triangles sharing them
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let direction = (directions[a as usize] + directions[b as usize]).normalize();
+                directions.push(direction);
+                directions.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .into_iter()
+            .flat_map(|[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let mut mesh = MeshData::default();
+    // Vertex of each direction and u, those on the seam or at the poles
+    // having several
+    let mut vertices = HashMap::new();
+    for triangle in triangles {
+        let corners = triangle.map(|i| directions[i as usize]);
+        // The poles are on all meridians, each triangle uses its own u
+        let pole = corners.map(|direction| direction.x == 0.0 && direction.z == 0.0);
+        let mut u = corners.map(|direction| (direction.x.atan2(direction.z) / TAU).rem_euclid(1.0));
+        let around = (0..3).filter(|&k| !pole[k]).map(|k| u[k]);
+        let range = around.clone().fold(f32::MIN, f32::max) - around.fold(f32::MAX, f32::min);
+        // Triangles crossing the seam at u = 0 continue past 1 instead
+        if range > 0.5 {
+            for u in &mut u {
+                if *u < 0.5 {
+                    *u += 1.0;
+                }
+            }
+        }
+        for k in 0..3 {
+            if pole[k] {
+                u[k] = (u[(k + 1) % 3] + u[(k + 2) % 3]) / 2.0;
+            }
+        }
+        for k in 0..3 {
+            let index = *vertices
+                .entry((triangle[k], u[k].to_bits()))
+                .or_insert_with(|| {
+                    let direction = corners[k];
+                    let v = 0.5 + direction.y.asin() / PI;
+                    mesh.vertices
+                        .push(vertex(direction * radius, direction, Vec2::new(u[k], v)));
+                    mesh.vertices.len() as u32 - 1
+                });
+            mesh.indices.push(index);
+        }
+    }
+    finish(mesh)
+}
+
+/// Cylinder along Y of height `height`, its side split in `sectors` and
+/// `stacks` and closed by discs.
+pub fn cylinder(radius: f32, height: f32, sectors: u32, stacks: u32) -> MeshData {
+    let stacks = stacks.max(1);
+    let profile: Vec<_> = (0..=stacks)
+        .map(|i| {
+            let t = i as f32 / stacks as f32;
+            ProfilePoint {
+                radius,
+                y: height * (0.5 - t),
+                normal: Vec2::X,
+                v: 1.0 - t,
+            }
+        })
+        .collect();
+    let mut mesh = MeshData::default();
+    add_lathe(&mut mesh, &profile, sectors);
+    add_disc(&mut mesh, radius, height / 2.0, sectors, true);
+    add_disc(&mut mesh, radius, -height / 2.0, sectors, false);
+    finish(mesh)
+}
+
+/// Cone along Y of height `height` pointing up, its side split in `sectors`
+/// and `stacks` and closed by a disc.
+pub fn cone(radius: f32, height: f32, sectors: u32, stacks: u32) -> MeshData {
+    let stacks = stacks.max(1);
+    let normal = Vec2::new(height, radius).normalize();
+    let profile: Vec<_> = (0..=stacks)
+        .map(|i| {
+            let t = i as f32 / stacks as f32;
+            ProfilePoint {
+                radius: radius * t,
+                y: height * (0.5 - t),
+                normal,
+                v: 1.0 - t,
+            }
+        })
+        .collect();
+    let mut mesh = MeshData::default();
+    add_lathe(&mut mesh, &profile, sectors);
+    add_disc(&mut mesh, radius, -height / 2.0, sectors, false);
+    finish(mesh)
+}
+
+/// Torus around Y with a tube of radius `minor_radius` whose center is at
+/// `major_radius` from the axis, split in `sectors` around the axis and
+/// `sides` around the tube.
+pub fn torus(major_radius: f32, minor_radius: f32, sectors: u32, sides: u32) -> MeshData {
+    let sides = sides.max(3);
+    // Around the tube from the outside, going down first
+    let profile: Vec<_> = (0..=sides)
+        .map(|i| {
+            let t = i as f32 / sides as f32;
+            let (sin, cos) = (t * TAU).sin_cos();
+            ProfilePoint {
+                radius: major_radius + minor_radius * cos,
+                y: -minor_radius * sin,
+                normal: Vec2::new(cos, -sin),
+                v: 1.0 - t,
+            }
+        })
+        .collect();
+    let mut mesh = MeshData::default();
+    add_lathe(&mut mesh, &profile, sectors);
+    finish(mesh)
+}
+
+/// Cylinder along Y ended by hemispheres, `height` including them, split in
+/// `sectors` around the axis and `rings` parallels per hemisphere.
+pub fn capsule(radius: f32, height: f32, sectors: u32, rings: u32) -> MeshData {
+    let rings = rings.max(1);
+    let half_length = (height / 2.0 - radius).max(0.0);
+    let total_height = 2.0 * (half_length + radius);
+    let v = |y: f32| 0.5 + y / total_height;
+
+    let mut profile = vec![];
+    for i in 0..=rings {
+        let polar = i as f32 / rings as f32 * FRAC_PI_2;
+        let point = sphere_point(radius, half_length, polar, 0.0, false);
+        profile.push(ProfilePoint {
+            v: v(point.y),
+            ..point
+        });
+    }
+    // Without a cylinder between the hemispheres their equators are merged
+    let first = if half_length > 0.0 { 0 } else { 1 };
+    for i in first..=rings {
+        let polar = FRAC_PI_2 + i as f32 / rings as f32 * FRAC_PI_2;
+        let point = sphere_point(radius, -half_length, polar, 0.0, i == rings);
+        profile.push(ProfilePoint {
+            v: v(point.y),
+            ..point
+        });
+    }
+    let mut mesh = MeshData::default();
+    add_lathe(&mut mesh, &profile, sectors);
+    finish(mesh)
+}
+
+/// Point of the outline of a surface of revolution around Y.
+struct ProfilePoint {
+    /// Distance from the Y axis
+    radius: f32,
+    y: f32,
+    /// Normal in the plane of the outline, along the radius and Y
+    normal: Vec2,
+    /// Texture v of the vertices on this ring
+    v: f32,
+}
+
+/// Point of a sphere centered at `center_y` on the Y axis, at `polar`
+/// radians from its top. Its radius is forced to 0 at the bottom pole, where
+/// the sine is not exactly 0.
+fn sphere_point(radius: f32, center_y: f32, polar: f32, v: f32, bottom_pole: bool) -> ProfilePoint {
+    let (sin, cos) = polar.sin_cos();
+    ProfilePoint {
+        radius: if bottom_pole { 0.0 } else { radius * sin },
+        y: center_y + radius * cos,
+        normal: Vec2::new(sin, cos),
+        v,
+    }
+}
+
+fn vertex(position: Vec3, normal: Vec3, texture_coordinates: Vec2) -> Vertex {
+    Vertex {
+        position,
+        normals: normal,
+        color: Vec3::ONE,
+        texture_coordinates,
+        ..Vertex::default()
+    }
+}
+
+/// Adds the parallelogram spanned by `u` and `v` from `origin`, facing
+/// `u.cross(v)`, texture coordinates going from 0 to 1 along them.
+fn add_grid(mesh: &mut MeshData, origin: Vec3, u: Vec3, v: Vec3, subdivisions: u32) {
+    let subdivisions = subdivisions.max(1);
+    let normal = u.cross(v).normalize();
+    let first = mesh.vertices.len() as u32;
+    for j in 0..=subdivisions {
+        for i in 0..=subdivisions {
+            let uv = Vec2::new(i as f32, j as f32) / subdivisions as f32;
+            mesh.vertices
+                .push(vertex(origin + u * uv.x + v * uv.y, normal, uv));
+        }
+    }
+    let row = subdivisions + 1;
+    for j in 0..subdivisions {
+        for i in 0..subdivisions {
+            let corner = first + j * row + i;
+            let opposite = corner + row + 1;
+            mesh.indices
+                .extend([corner, corner + 1, opposite, opposite, corner + row, corner]);
+        }
+    }
+}
+
+/// Adds the surface swept by revolving `profile`, from its top, around the
+/// Y axis in `sectors` steps from +Z toward +X, texture u following them.
+fn add_lathe(mesh: &mut MeshData, profile: &[ProfilePoint], sectors: u32) {
+    let sectors = sectors.max(3);
+    let first = mesh.vertices.len() as u32;
+    for point in profile {
+        for j in 0..=sectors {
+            let u = j as f32 / sectors as f32;
+            let (sin, cos) = (u * TAU).sin_cos();
+            let direction = Vec3::new(sin, 0.0, cos);
+            mesh.vertices.push(vertex(
+                direction * point.radius + Vec3::Y * point.y,
+                (direction * point.normal.x + Vec3::Y * point.normal.y).normalize(),
+                Vec2::new(u, point.v),
+            ));
+        }
+    }
+    let row = sectors + 1;
+    for (i, pair) in profile.windows(2).enumerate() {
+        for j in 0..sectors {
+            let upper = first + i as u32 * row + j;
+            let lower = upper + row;
+            // Rings shrunk to a point only get the triangles of the other one
+            if pair[1].radius != 0.0 {
+                mesh.indices.extend([lower, lower + 1, upper + 1]);
+            }
+            if pair[0].radius != 0.0 {
+                mesh.indices.extend([lower, upper + 1, upper]);
+            }
+        }
+    }
+}
+
+/// Adds a disc on the XZ plane at `y`, facing +Y if `up` and -Y otherwise,
+/// the texture being mapped on its bounding square.
+fn add_disc(mesh: &mut MeshData, radius: f32, y: f32, sectors: u32, up: bool) {
+    let sectors = sectors.max(3);
+    let (normal, v_sign) = if up { (Vec3::Y, -1.0) } else { (-Vec3::Y, 1.0) };
+    let center = mesh.vertices.len() as u32;
+    mesh.vertices
+        .push(vertex(Vec3::Y * y, normal, Vec2::splat(0.5)));
+    for j in 0..sectors {
+        let (sin, cos) = (j as f32 / sectors as f32 * TAU).sin_cos();
+        let uv = Vec2::new(0.5 + sin / 2.0, 0.5 + v_sign * cos / 2.0);
+        mesh.vertices
+            .push(vertex(Vec3::new(sin * radius, y, cos * radius), normal, uv));
+    }
+    for j in 0..sectors {
+        let current = center + 1 + j;
+        let next = center + 1 + (j + 1) % sectors;
+        if up {
+            mesh.indices.extend([center, current, next]);
+        } else {
+            mesh.indices.extend([center, next, current]);
+        }
+    }
+}
+
+fn finish(mut mesh: MeshData) -> MeshData {
+    geometry::compute_tangents(&mut mesh.vertices, &mesh.indices);
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Volume enclosed by a closed mesh, negative if its triangles face
+    /// inwards.
+    fn volume(mesh: &MeshData) -> f32 {
+        mesh.indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].position);
+                a.dot(b.cross(c)) / 6.0
+            })
+            .sum()
+    }
+
+    /// Checks the indices, the unit normals and tangents and that the
+    /// triangles face the side of their vertex normals.
+    fn check(mesh: &MeshData) {
+        assert!(!mesh.indices.is_empty());
+        assert_eq!(mesh.indices.len() % 3, 0);
+        for vertex in &mesh.vertices {
+            assert!((vertex.normals.length() - 1.0).abs() < 1e-4, "{vertex:?}");
+            assert!((vertex.tangent.length() - 1.0).abs() < 1e-4, "{vertex:?}");
+            assert!(
+                vertex.tangent.dot(vertex.normals).abs() < 1e-4,
+                "{vertex:?}"
+            );
+            assert!(vertex.bitangent_sign.abs() == 1.0);
+        }
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize]);
+            let face_normal = (b.position - a.position).cross(c.position - a.position);
+            assert!(
+                face_normal.length() > 1e-6,
+                "degenerate triangle {triangle:?}"
+            );
+            for vertex in [a, b, c] {
+                assert!(face_normal.dot(vertex.normals) > 0.0, "{triangle:?}");
+            }
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn plane_is_a_grid_facing_up() {
+        let mesh = plane(2.0, 4);
+        check(&mesh);
+        assert_eq!(mesh.vertices.len(), 25);
+        assert_eq!(mesh.indices.len(), 4 * 4 * 6);
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.normals, Vec3::Y);
+            assert_eq!(vertex.position.y, 0.0);
+            assert!(vertex.position.x.abs() <= 1.0 && vertex.position.z.abs() <= 1.0);
+            // Texture v increases toward -Z
+            assert!((vertex.texture_coordinates.y - (1.0 - vertex.position.z) / 2.0).abs() < 1e-6);
+            assert_eq!(vertex.tangent, Vec3::X);
+        }
+    }
+
+    #[test]
+    fn cube_encloses_its_volume() {
+        let mesh = cube(2.0, 1);
+        check(&mesh);
+        assert_eq!(mesh.vertices.len(), 24);
+        assert_eq!(mesh.indices.len(), 36);
+        assert_close(volume(&mesh), 8.0, 1e-5);
+        assert_close(volume(&cube(2.0, 3)), 8.0, 1e-5);
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.position.abs().max_element(), 1.0);
+        }
+    }
+
+    #[test]
+    fn spheres_are_round() {
+        for mesh in [uv_sphere(1.5, 32, 16), icosphere(1.5, 3)] {
+            check(&mesh);
+            for vertex in &mesh.vertices {
+                assert!((vertex.position.length() - 1.5).abs() < 1e-5);
+                assert!((vertex.normals - vertex.position / 1.5).length() < 1e-5);
+            }
+            assert_close(volume(&mesh), 4.0 / 3.0 * PI * 1.5f32.powi(3), 0.03);
+        }
+    }
+
+    #[test]
+    fn uv_sphere_has_a_triangle_per_pole_sector() {
+        let mesh = uv_sphere(1.0, 8, 4);
+        assert_eq!(mesh.vertices.len(), 9 * 5);
+        // Two triangles per quad, one per sector at the poles
+        assert_eq!(mesh.indices.len() / 3, 8 * 2 * 2 + 8 * 2);
+    }
+
+    #[test]
+    fn icosphere_splits_triangles_in_four() {
+        for subdivisions in 0..4 {
+            let mesh = icosphere(1.0, subdivisions);
+            assert_eq!(mesh.indices.len() / 3, 20 * 4usize.pow(subdivisions));
+        }
+    }
+
+    #[test]
+    fn icosphere_texture_coordinates_do_not_wrap_within_a_triangle() {
+        let mesh = icosphere(1.0, 2);
+        for triangle in mesh.indices.chunks_exact(3) {
+            let u = [0, 1, 2].map(|k| mesh.vertices[triangle[k] as usize].texture_coordinates.x);
+            let range = u.iter().copied().fold(f32::MIN, f32::max)
+                - u.iter().copied().fold(f32::MAX, f32::min);
+            assert!(range < 0.5, "{u:?}");
+        }
+    }
+
+    #[test]
+    fn cylinder_and_cone_enclose_their_volume() {
+        // Volume of the prism and pyramid on a polygon of 64 sides
+        let base = 32.0 * (TAU / 64.0).sin() * 0.5f32.powi(2);
+        let mesh = cylinder(0.5, 2.0, 64, 3);
+        check(&mesh);
+        assert_close(volume(&mesh), base * 2.0, 1e-4);
+        let mesh = cone(0.5, 2.0, 64, 3);
+        check(&mesh);
+        assert_close(volume(&mesh), base * 2.0 / 3.0, 1e-4);
+        for vertex in &mesh.vertices {
+            assert!(vertex.position.y.abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn torus_encloses_its_volume() {
+        let mesh = torus(1.0, 0.25, 64, 32);
+        check(&mesh);
+        assert_close(volume(&mesh), 2.0 * PI * PI * 0.25f32.powi(2), 0.01);
+    }
+
+    #[test]
+    fn capsule_fits_its_height() {
+        let mesh = capsule(0.5, 3.0, 32, 8);
+        check(&mesh);
+        let sphere = 4.0 / 3.0 * PI * 0.5f32.powi(3);
+        let cylinder = PI * 0.5f32.powi(2) * 2.0;
+        assert_close(volume(&mesh), sphere + cylinder, 0.02);
+        let top = mesh
+            .vertices
+            .iter()
+            .map(|v| v.position.y)
+            .fold(f32::MIN, f32::max);
+        assert!((top - 1.5).abs() < 1e-6);
+        // A capsule as high as it is wide is a sphere
+        let mesh = capsule(0.5, 1.0, 32, 8);
+        check(&mesh);
+        assert_close(volume(&mesh), sphere, 0.02);
+    }
+}
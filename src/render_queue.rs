@@ -0,0 +1,71 @@
+use crate::material::BlendMode;
+use crate::{Camera, Mesh, Shader};
+use glam::Mat4;
+
+/// Transparent meshes of a frame, drawn after the opaque ones from the
+/// farthest to the closest so that each blends over what is behind it.
+#[derive(Default)]
+pub struct TransparentQueue<'a> {
+    draws: Vec<(&'a Mesh, Mat4)>,
+}
+
+impl<'a> TransparentQueue<'a> {
+    pub fn new() -> Self {
+        TransparentQueue::default()
+    }
+
+    pub fn push(&mut self, mesh: &'a Mesh, model: Mat4) {
+        self.draws.push((mesh, model));
+    }
+
+    /// Sorts the meshes by the distance from the camera to their center and
+    /// draws them, `shader` having the camera and lighting uniforms set.
+    pub fn draw(&mut self, shader: &Shader, camera: &Camera) {
+        let distance = |(mesh, model): &(&Mesh, Mat4)| {
+            model
+                .transform_point3(mesh.center())
+                .distance_squared(camera.position)
+        };
+        self.draws
+            .sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+
+        for (mesh, model) in &self.draws {
+            draw_blended(shader, mesh, *model);
+        }
+    }
+
+    /// Draws the additive meshes, whose result does not depend on the order.
+    pub fn draw_additive(&self, shader: &Shader) {
+        for (mesh, model) in self.draws_with(|mode| mode == BlendMode::Additive) {
+            draw_blended(shader, mesh, model);
+        }
+    }
+
+    /// Draws the alpha blended and premultiplied meshes in any order,
+    /// keeping the blend state set by the caller.
+    pub fn draw_order_independent(&self, shader: &Shader) {
+        let is_order_dependent =
+            |mode| matches!(mode, BlendMode::AlphaBlend | BlendMode::Premultiplied);
+        for (mesh, model) in self.draws_with(is_order_dependent) {
+            shader.set_uniform_mat4("uModel", model);
+            mesh.draw(shader);
+        }
+    }
+
+    fn draws_with(
+        &self,
+        predicate: fn(BlendMode) -> bool,
+    ) -> impl Iterator<Item = (&'a Mesh, Mat4)> + '_ {
+        self.draws
+            .iter()
+            .copied()
+            .filter(move |(mesh, _)| predicate(mesh.blend_mode()))
+    }
+}
+
+fn draw_blended(shader: &Shader, mesh: &Mesh, model: Mat4) {
+    shader.set_uniform_mat4("uModel", model);
+    mesh.blend_mode().enable();
+    mesh.draw(shader);
+    mesh.blend_mode().disable();
+}
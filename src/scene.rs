@@ -0,0 +1,557 @@
+use crate::directional_light::DirectionalLight;
+use crate::ecs::{Entity, World};
+use crate::geometry::BoundingSphere;
+use crate::light_buffer::LightBuffer;
+use crate::point_light::PointLight;
+use crate::render_queue::TransparentQueue;
+use crate::scene_file::MeshSource;
+use crate::spot_light::SpotLight;
+use crate::texture::TextureKind;
+use crate::{Camera, Mesh, Shader};
+use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// Texture units of the shadow maps in the lighting shaders
+const POINT_SHADOW_UNIT: u32 = 2;
+const CASCADE_SHADOW_UNIT: u32 = 3;
+const SPOT_SHADOW_UNIT: u32 = 4;
+
+/// Index of a node in its `SceneGraph`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// Placement of a node relative to its parent, scaled then rotated then
+/// translated.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vec3) -> Self {
+        Transform {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+struct Node {
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    transform: Transform,
+    /// Cached product of the transforms from the root down to this node
+    world: Mat4,
+    /// Whether `world` is outdated, always set on the descendants of a dirty
+    /// node
+    dirty: bool,
+}
+
+/// Hierarchy of nodes placing entities relative to their parent.
+///
+/// World matrices are only recomputed by `update` for the nodes whose
+/// transform, or the one of an ancestor, changed since the last update.
+#[derive(Default)]
+pub struct SceneGraph {
+    /// Removed nodes stay until their slot is reused
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+    /// Slots of the removed nodes
+    free: Vec<NodeId>,
+}
+
+impl SceneGraph {
+    pub fn add_node(&mut self, parent: Option<NodeId>, transform: Transform) -> NodeId {
+        let node = Node {
+            parent,
+            children: vec![],
+            transform,
+            world: Mat4::IDENTITY,
+            dirty: true,
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id.0] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                NodeId(self.nodes.len() - 1)
+            }
+        };
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+
+    /// Removes `node` and its descendants, returning them.
+    pub fn remove_node(&mut self, node: NodeId) -> Vec<NodeId> {
+        let siblings = match self.nodes[node.0].parent {
+            Some(parent) => &mut self.nodes[parent.0].children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != node);
+
+        let mut removed = vec![node];
+        let mut i = 0;
+        while let Some(&node) = removed.get(i) {
+            removed.extend_from_slice(&self.nodes[node.0].children);
+            i += 1;
+        }
+        self.free.extend_from_slice(&removed);
+        removed
+    }
+
+    /// Changes the transform of `node`, its world matrix and the ones of its
+    /// descendants being recomputed by the next `update` if it differs.
+    pub fn set_transform(&mut self, node: NodeId, transform: Transform) {
+        if self.nodes[node.0].transform != transform {
+            self.nodes[node.0].transform = transform;
+            self.mark_dirty(node);
+        }
+    }
+
+    pub fn children(&self, node: NodeId) -> &[NodeId] {
+        &self.nodes[node.0].children
+    }
+
+    /// Nodes without a parent, in the order they were added.
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// World matrix of `node` as of the last `update`.
+    pub fn world_matrix(&self, node: NodeId) -> Mat4 {
+        self.nodes[node.0].world
+    }
+
+    /// Recomputes the outdated world matrices.
+    pub fn update(&mut self) {
+        // Parents are visited before their children
+        let mut stack = self.roots.clone();
+        while let Some(id) = stack.pop() {
+            if self.nodes[id.0].dirty {
+                let parent_world = self.nodes[id.0]
+                    .parent
+                    .map_or(Mat4::IDENTITY, |parent| self.nodes[parent.0].world);
+                let node = &mut self.nodes[id.0];
+                node.world = parent_world * node.transform.matrix();
+                node.dirty = false;
+            }
+            stack.extend_from_slice(&self.nodes[id.0].children);
+        }
+    }
+
+    fn mark_dirty(&mut self, node: NodeId) {
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            let node = &mut self.nodes[node.0];
+            // Its descendants are already dirty
+            if node.dirty {
+                continue;
+            }
+            node.dirty = true;
+            stack.extend_from_slice(&node.children);
+        }
+    }
+}
+
+/// Meshes drawn with the world matrix of their entity. A mesh can be shared
+/// by several entities.
+pub struct MeshRenderer {
+    pub meshes: Vec<Rc<Mesh>>,
+}
+
+/// Component of the entities added to a `Scene` with the assets they were
+/// created from, which is what a scene file stores. The entities of a
+/// loaded model do not have one, loading the model again recreates them.
+pub struct SceneNode {
+    /// Empty for anonymous nodes
+    pub name: String,
+    pub mesh: Option<MeshSource>,
+    /// Texture projected by the spot light of the node
+    pub cookie: Option<PathBuf>,
+}
+
+/// Entities placed by the nodes of a scene graph.
+///
+/// Every entity of the scene has a `NodeId` and a `Transform` component, the
+/// transform being copied to the graph by `update`. `MeshRenderer`,
+/// `PointLight`, `SpotLight`, `DirectionalLight` and `Camera` components are
+/// then placed at the origin of their node, spot and directional lights
+/// pointing to its -Z axis and cameras looking along it.
+#[derive(Default)]
+pub struct Scene {
+    pub graph: SceneGraph,
+    pub world: World,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Scene::default()
+    }
+
+    /// Adds an entity with a node child of the one of `parent`.
+    pub fn spawn(&mut self, parent: Option<Entity>, transform: Transform) -> Entity {
+        let parent = parent.and_then(|parent| self.world.get::<NodeId>(parent).copied());
+        let node = self.graph.add_node(parent, transform);
+        let entity = self.world.spawn();
+        self.world.insert(entity, node);
+        self.world.insert(entity, transform);
+        entity
+    }
+
+    /// Removes `entity` and the entities of the descendants of its node.
+    pub fn despawn(&mut self, entity: Entity) {
+        if let Some(&node) = self.world.get::<NodeId>(entity) {
+            let removed: HashSet<_> = self.graph.remove_node(node).into_iter().collect();
+            for entity in self.entities(&removed) {
+                self.world.despawn(entity);
+            }
+        }
+        self.world.despawn(entity);
+    }
+
+    /// Entities whose node is a child of the one of `entity`.
+    pub fn children(&self, entity: Entity) -> Vec<Entity> {
+        match self.world.get::<NodeId>(entity) {
+            Some(&node) => self.entities(&self.graph.children(node).iter().copied().collect()),
+            None => vec![],
+        }
+    }
+
+    fn entities(&self, nodes: &HashSet<NodeId>) -> Vec<Entity> {
+        self.world
+            .query::<NodeId>()
+            .filter(|(_, node)| nodes.contains(node))
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    /// Adds an entity saved with the scene.
+    pub fn add_node(&mut self, parent: Option<Entity>, name: &str, transform: Transform) -> Entity {
+        let entity = self.spawn(parent, transform);
+        self.world.insert(
+            entity,
+            SceneNode {
+                name: name.to_owned(),
+                mesh: None,
+                cookie: None,
+            },
+        );
+        entity
+    }
+
+    /// First node of the scene with the given name.
+    pub fn find_node(&self, name: &str) -> Option<Entity> {
+        self.world
+            .query::<SceneNode>()
+            .find(|(_, node)| node.name == name)
+            .map(|(entity, _)| entity)
+    }
+
+    /// Updates the world matrices from the transforms, then moves the lights
+    /// and cameras to their node.
+    pub fn update(&mut self) {
+        for (_, &node, transform) in self.world.query2::<NodeId, Transform>() {
+            self.graph.set_transform(node, *transform);
+        }
+        self.graph.update();
+
+        let graph = &self.graph;
+        let world_matrix = |entity, world: &World| {
+            world
+                .get::<NodeId>(entity)
+                .map_or(Mat4::IDENTITY, |&node| graph.world_matrix(node))
+        };
+        self.world
+            .for_each_mut::<PointLight>(|entity, light, world| {
+                light.set_position(world_matrix(entity, world).w_axis.truncate())
+            });
+        self.world
+            .for_each_mut::<SpotLight>(|entity, light, world| {
+                light.set_world_transform(world_matrix(entity, world))
+            });
+        self.world
+            .for_each_mut::<DirectionalLight>(|entity, light, world| {
+                light.set_world_transform(world_matrix(entity, world))
+            });
+        self.world.for_each_mut::<Camera>(|entity, camera, world| {
+            camera.set_world_transform(world_matrix(entity, world))
+        });
+    }
+
+    /// Draws the opaque meshes with their material, `shader` having the
+    /// camera uniforms set.
+    pub fn draw(&self, shader: &Shader) {
+        draw_opaque(&self.graph, &self.world, shader, None);
+    }
+
+    /// Adds the transparent meshes to the queue drawn after the opaque ones.
+    pub fn queue_transparent<'a>(&'a self, queue: &mut TransparentQueue<'a>) {
+        for (_, renderer, &node) in self.world.query2::<MeshRenderer, NodeId>() {
+            let model = self.graph.world_matrix(node);
+            for mesh in renderer.meshes.iter().filter(|mesh| mesh.is_transparent()) {
+                queue.push(mesh, model);
+            }
+        }
+    }
+
+    /// Renders the shadow maps of the lights having one, `camera` being the
+    /// one the cascades of directional lights are fitted to.
+    /// Only the shadow maps sampled by the lighting shaders are rendered, the
+    /// one of the first light of each kind having one.
+    pub fn render_shadow_maps(&mut self, camera: &Camera) {
+        let graph = &self.graph;
+        let mut rendered = false;
+        self.world.for_each_mut::<PointLight>(|_, light, world| {
+            if rendered || light.shadow_map.is_none() {
+                return;
+            }
+            rendered = true;
+            // Meshes out of range cast no shadow
+            let reach = BoundingSphere {
+                center: light.position,
+                radius: light.range,
+            };
+            light.render_shadow_map(|shader| draw_opaque(graph, world, shader, Some(reach)))
+        });
+        let mut rendered = false;
+        self.world
+            .for_each_mut::<DirectionalLight>(|_, light, world| {
+                if rendered || light.shadow_map.is_none() {
+                    return;
+                }
+                rendered = true;
+                light.render_shadow_map(camera, |shader| draw_opaque(graph, world, shader, None))
+            });
+        let mut rendered = false;
+        self.world.for_each_mut::<SpotLight>(|_, light, world| {
+            if rendered || light.shadow_map.is_none() {
+                return;
+            }
+            rendered = true;
+            light.render_shadow_map(|shader| draw_opaque(graph, world, shader, None))
+        });
+    }
+
+    pub fn push_lights(&self, light_buffer: &mut LightBuffer) {
+        for (_, light) in self.world.query::<PointLight>() {
+            light_buffer.push_point(light);
+        }
+        for (_, light) in self.world.query::<DirectionalLight>() {
+            light_buffer.push_directional(light);
+        }
+        for (_, light) in self.world.query::<SpotLight>() {
+            light_buffer.push_spot(light);
+        }
+    }
+
+    /// Binds the shadow maps of a lighting shader, which only uses those of
+    /// the first light of each kind casting shadows, as `LightBuffer` does.
+    pub fn bind_shadow_maps(&self, shader: &Shader) {
+        // Samplers of different types cannot share a unit, even unused ones
+        for (name, unit) in shadow_samplers() {
+            shader.set_uniform_1i(name, unit as i32);
+        }
+        if let Some((_, light)) = self
+            .world
+            .query::<PointLight>()
+            .find(|(_, light)| light.shadow_map.is_some())
+        {
+            light.bind_shadow_map(shader, POINT_SHADOW_UNIT);
+        }
+        match self
+            .world
+            .query::<DirectionalLight>()
+            .find(|(_, light)| light.shadow_map.is_some())
+        {
+            Some((_, light)) => light.bind_shadow_map(shader, CASCADE_SHADOW_UNIT),
+            None => shader.set_uniform_1i("uCascadeCount", 0),
+        }
+        if let Some((_, light)) = self
+            .world
+            .query::<SpotLight>()
+            .find(|(_, light)| light.shadow_map.is_some() || light.has_cookie())
+        {
+            light.bind_shadow_map(shader, SPOT_SHADOW_UNIT);
+        }
+    }
+}
+
+/// Samplers of the lighting shaders set by `Scene::bind_shadow_maps`, with
+/// their texture unit.
+fn shadow_samplers() -> [(&'static str, u32); 4] {
+    [
+        ("uShadowMap", POINT_SHADOW_UNIT),
+        ("uCascadeShadowMap", CASCADE_SHADOW_UNIT),
+        ("uSpotShadowMap", SPOT_SHADOW_UNIT),
+        ("uSpotLightCookie", TextureKind::Cookie.unit()),
+    ]
+}
+
+/// Draws the opaque meshes, only those whose bounding sphere intersects
+/// `reach` if given.
+fn draw_opaque(graph: &SceneGraph, world: &World, shader: &Shader, reach: Option<BoundingSphere>) {
+    for (_, renderer, &node) in world.query2::<MeshRenderer, NodeId>() {
+        let model = graph.world_matrix(node);
+        for mesh in renderer.meshes.iter().filter(|mesh| !mesh.is_transparent()) {
+            let in_reach = reach
+                .is_none_or(|reach| mesh.bounding_sphere().transformed(model).intersects(&reach));
+            if in_reach {
+                shader.set_uniform_mat4("uModel", model);
+                mesh.draw(shader);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_dirty(graph: &SceneGraph, node: NodeId) -> bool {
+        graph.nodes[node.0].dirty
+    }
+
+    #[test]
+    fn children_follow_their_parent() {
+        let mut graph = SceneGraph::default();
+        let parent = graph.add_node(None, Transform::from_translation(Vec3::X));
+        let child = graph.add_node(Some(parent), Transform::from_translation(Vec3::Y));
+        graph.update();
+        assert_eq!(
+            graph.world_matrix(child),
+            Mat4::from_translation(Vec3::X + Vec3::Y)
+        );
+
+        graph.set_transform(
+            parent,
+            Transform {
+                translation: Vec3::Z,
+                scale: Vec3::splat(2.0),
+                ..Default::default()
+            },
+        );
+        assert!(is_dirty(&graph, child));
+        // World matrices are only computed by `update`
+        assert_eq!(
+            graph.world_matrix(child),
+            Mat4::from_translation(Vec3::X + Vec3::Y)
+        );
+        graph.update();
+        assert_eq!(
+            graph.world_matrix(child),
+            Mat4::from_translation(Vec3::Z + Vec3::Y * 2.0) * Mat4::from_scale(Vec3::splat(2.0))
+        );
+        assert!(!is_dirty(&graph, parent) && !is_dirty(&graph, child));
+    }
+
+    #[test]
+    fn equal_transforms_do_not_dirty() {
+        let mut graph = SceneGraph::default();
+        let transform = Transform::from_translation(Vec3::X);
+        let parent = graph.add_node(None, transform);
+        let child = graph.add_node(Some(parent), Transform::default());
+        graph.update();
+
+        graph.set_transform(parent, transform);
+        assert!(!is_dirty(&graph, parent));
+        assert!(!is_dirty(&graph, child));
+
+        // Only the changed node and its descendants are outdated
+        graph.set_transform(child, Transform::from_translation(Vec3::Y));
+        assert!(!is_dirty(&graph, parent));
+        assert!(is_dirty(&graph, child));
+    }
+
+    #[test]
+    fn children_of_clean_parents_are_computed() {
+        let mut graph = SceneGraph::default();
+        let parent = graph.add_node(None, Transform::from_translation(Vec3::X));
+        graph.update();
+
+        let child = graph.add_node(Some(parent), Transform::from_translation(Vec3::Y));
+        assert!(!is_dirty(&graph, parent));
+        graph.update();
+        assert_eq!(
+            graph.world_matrix(child),
+            Mat4::from_translation(Vec3::X + Vec3::Y)
+        );
+        assert_eq!(graph.children(parent), [child]);
+    }
+
+    #[test]
+    fn shadow_samplers_have_their_own_units() {
+        let material_units = [
+            TextureKind::BaseColor,
+            TextureKind::MetallicRoughness,
+            TextureKind::Occlusion,
+            TextureKind::Emissive,
+        ]
+        .map(|kind| kind.unit());
+        let units: HashSet<_> = shadow_samplers().iter().map(|&(_, unit)| unit).collect();
+        assert_eq!(units.len(), shadow_samplers().len());
+        assert!(units.iter().all(|unit| !material_units.contains(unit)));
+    }
+
+    #[test]
+    fn removed_slots_are_reused() {
+        let mut graph = SceneGraph::default();
+        let root = graph.add_node(None, Transform::from_translation(Vec3::X));
+        let parent = graph.add_node(Some(root), Transform::default());
+        let child = graph.add_node(Some(parent), Transform::default());
+        graph.update();
+
+        let mut removed = graph.remove_node(parent);
+        removed.sort_by_key(|node| node.0);
+        assert_eq!(removed, [parent, child]);
+        assert!(graph.children(root).is_empty());
+
+        // A reused slot is placed under its new parent
+        let reused = graph.add_node(Some(root), Transform::from_translation(Vec3::Y));
+        assert!(removed.contains(&reused));
+        graph.update();
+        assert_eq!(
+            graph.world_matrix(reused),
+            Mat4::from_translation(Vec3::X + Vec3::Y)
+        );
+    }
+
+    #[test]
+    fn despawn_removes_descendants() {
+        let mut scene = Scene::new();
+        let root = scene.spawn(None, Transform::default());
+        let parent = scene.spawn(Some(root), Transform::default());
+        let child = scene.spawn(Some(parent), Transform::default());
+        let sibling = scene.spawn(Some(root), Transform::default());
+        assert_eq!(scene.children(root), [parent, sibling]);
+
+        scene.despawn(parent);
+        assert!(!scene.world.contains(parent));
+        assert!(!scene.world.contains(child));
+        assert_eq!(scene.children(root), [sibling]);
+    }
+}
@@ -0,0 +1,689 @@
+use crate::assets::Assets;
+use crate::config::Config;
+use crate::directional_light::DirectionalLight;
+use crate::ecs::Entity;
+use crate::geometry::{self, MeshData};
+use crate::material::MaterialError;
+use crate::point_light::PointLight;
+use crate::primitives::{self, Shape};
+use crate::scene::{MeshRenderer, NodeId, Scene, SceneNode, Transform};
+use crate::shadow::{CascadedShadowMap, CubeShadowMap, SpotShadowMap, MAX_CASCADES};
+use crate::spot_light::SpotLight;
+use crate::texture::TextureKind;
+use crate::{model, vfs, Camera, Mesh};
+use glam::{Mat4, Vec3};
+use log::warn;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Component, Path, PathBuf};
+use std::{fs, io};
+
+/// Contents of a scene file, a tree of nodes written in RON:
+///
+/// ```ron
+/// (
+///     nodes: [
+///         (
+///             name: "floor",
+///             mesh: Some(Plane(size: 4.0, material: "../materials/planks.ron")),
+///         ),
+///         (
+///             transform: (translation: (0.0, 0.3, 0.0)),
+///             light: Some(Point(color: (1.0, 1.0, 1.0), intensity: 0.5, range: 10.0)),
+///         ),
+///     ],
+/// )
+/// ```
+///
+/// Asset paths are relative to the scene file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    #[serde(default)]
+    pub nodes: Vec<NodeDescription>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeDescription {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(default)]
+    pub transform: Transform,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<MeshSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub light: Option<LightDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<CameraDescription>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<NodeDescription>,
+}
+
+/// Asset a node draws.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MeshSource {
+    /// glTF file, loaded with its materials and node hierarchy under the node
+    Model(PathBuf),
+    /// Mesh file made by the `cook` binary, all its parts drawn with the
+    /// material
+    Cooked { mesh: PathBuf, material: PathBuf },
+    /// Generated mesh, see `primitives`
+    Shape { shape: Shape, material: PathBuf },
+    /// Square on the XZ plane centered on the origin, facing up
+    Plane { size: f32, material: PathBuf },
+    /// Upright square centered on the origin, facing +Z
+    Quad { size: f32, material: PathBuf },
+    /// Two upright squares crossing at the origin, lit as if they were
+    /// facing up like the ground they stand on
+    Foliage { size: f32, material: PathBuf },
+}
+
+impl MeshSource {
+    fn map_paths(&self, map: impl Fn(&PathBuf) -> PathBuf) -> Self {
+        match self {
+            MeshSource::Model(path) => MeshSource::Model(map(path)),
+            MeshSource::Cooked { mesh, material } => MeshSource::Cooked {
+                mesh: map(mesh),
+                material: map(material),
+            },
+            MeshSource::Shape { shape, material } => MeshSource::Shape {
+                shape: *shape,
+                material: map(material),
+            },
+            MeshSource::Plane { size, material } => MeshSource::Plane {
+                size: *size,
+                material: map(material),
+            },
+            MeshSource::Quad { size, material } => MeshSource::Quad {
+                size: *size,
+                material: map(material),
+            },
+            MeshSource::Foliage { size, material } => MeshSource::Foliage {
+                size: *size,
+                material: map(material),
+            },
+        }
+    }
+}
+
+/// Light placed by a node. Spot and directional lights point to the -Z axis
+/// of their node.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum LightDescription {
+    Point {
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        #[serde(default)]
+        shadows: bool,
+    },
+    Spot {
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        /// Half angle in degrees of the fully lit part of the cone
+        inner_angle: f32,
+        /// Half angle in degrees after which nothing is lit
+        outer_angle: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cookie: Option<PathBuf>,
+        #[serde(default)]
+        shadows: bool,
+    },
+    Directional {
+        color: Vec3,
+        intensity: f32,
+        #[serde(default)]
+        shadows: bool,
+    },
+}
+
+/// Camera looking along the -Z axis of its node.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    /// Vertical field of view, as given to `Camera::new`
+    pub fov: f32,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    Serialize(ron::Error),
+    /// A file referenced by a node does not exist
+    MissingAsset {
+        node: String,
+        path: PathBuf,
+    },
+    Material {
+        node: String,
+        error: MaterialError,
+    },
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            SceneError::Parse(path, err) => write!(f, "{}: {err}", path.display()),
+            SceneError::Serialize(err) => write!(f, "{err}"),
+            SceneError::MissingAsset { node, path } => {
+                write!(f, "node {node}: missing asset {}", path.display())
+            }
+            SceneError::Material { node, error } => write!(f, "node {node}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// Builds the scene described by a scene file, `config` giving the shadow
+/// map sizes and `width` and `height` the viewport of the cameras.
+pub fn load(
+    path: &Path,
+    assets: &mut Assets,
+    config: &Config,
+    width: u32,
+    height: u32,
+) -> Result<Scene, SceneError> {
+    let source = vfs::read_to_string(path).map_err(|err| SceneError::Io(path.to_owned(), err))?;
+    let file: SceneFile =
+        ron::from_str(&source).map_err(|err| SceneError::Parse(path.to_owned(), err))?;
+
+    let mut loader = Loader {
+        scene: Scene::new(),
+        assets,
+        directory: path.parent().unwrap_or_else(|| Path::new("")),
+        config,
+        width,
+        height,
+    };
+    for (i, node) in file.nodes.iter().enumerate() {
+        loader.add_node(None, &node_label("", node, i), node)?;
+    }
+    Ok(loader.scene)
+}
+
+/// Writes the nodes of `scene` with their current transform and light
+/// parameters, with asset paths relative to `path`.
+pub fn save(scene: &Scene, path: &Path) -> Result<(), SceneError> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let relative = |asset: &PathBuf| relative_path(asset, directory);
+
+    let saved: HashMap<_, _> = scene
+        .world
+        .query2::<SceneNode, NodeId>()
+        .map(|(entity, node, &id)| (id, (entity, node)))
+        .collect();
+    let roots = describe_nodes(scene, &saved, scene.graph.roots(), &relative);
+
+    let source = ron::ser::to_string_pretty(&SceneFile { nodes: roots }, PrettyConfig::new())
+        .map_err(SceneError::Serialize)?;
+    fs::write(path, source).map_err(|err| SceneError::Io(path.to_owned(), err))
+}
+
+struct Loader<'a> {
+    scene: Scene,
+    assets: &'a mut Assets,
+    directory: &'a Path,
+    config: &'a Config,
+    width: u32,
+    height: u32,
+}
+
+impl Loader<'_> {
+    /// Adds `description` and its children, `label` naming the node in
+    /// errors.
+    fn add_node(
+        &mut self,
+        parent: Option<Entity>,
+        label: &str,
+        description: &NodeDescription,
+    ) -> Result<(), SceneError> {
+        let entity = self
+            .scene
+            .add_node(parent, &description.name, description.transform);
+
+        if let Some(mesh) = &description.mesh {
+            // Kept relative to the working directory, like all loaded paths
+            let mesh = mesh.map_paths(|path| self.directory.join(path));
+            self.load_mesh(entity, label, &mesh)?;
+            self.scene_node(entity).mesh = Some(mesh);
+        }
+        if let Some(light) = &description.light {
+            self.add_light(entity, label, light)?;
+        }
+        if let Some(camera) = &description.camera {
+            let camera = Camera::new(camera.fov, Vec3::ZERO, self.width, self.height);
+            self.scene.world.insert(entity, camera);
+        }
+
+        for (i, child) in description.children.iter().enumerate() {
+            self.add_node(Some(entity), &node_label(label, child, i), child)?;
+        }
+        Ok(())
+    }
+
+    fn load_mesh(
+        &mut self,
+        entity: Entity,
+        label: &str,
+        source: &MeshSource,
+    ) -> Result<(), SceneError> {
+        let (mesh, material) = match source {
+            MeshSource::Model(path) => {
+                check_exists(label, path)?;
+                model::load(&mut self.scene, self.assets, path, Some(entity));
+                return Ok(());
+            }
+            MeshSource::Cooked { mesh, material } => {
+                check_exists(label, mesh)?;
+                check_exists(label, material)?;
+                return model::load_cooked(&mut self.scene, self.assets, mesh, material, entity)
+                    .map_err(|error| SceneError::Material {
+                        node: label.to_owned(),
+                        error,
+                    });
+            }
+            MeshSource::Shape { shape, material } => (shape.mesh(), material),
+            MeshSource::Plane { size, material } => (primitives::plane(*size, 1), material),
+            MeshSource::Quad { size, material } => {
+                let mut quad = primitives::plane(*size, 1);
+                quad.transform(Mat4::from_rotation_x(FRAC_PI_2));
+                (quad, material)
+            }
+            MeshSource::Foliage { size, material } => (foliage(*size), material),
+        };
+        check_exists(label, material)?;
+        let material = self
+            .assets
+            .material(material)
+            .map_err(|error| SceneError::Material {
+                node: label.to_owned(),
+                error,
+            })?;
+        // Nodes generating the same mesh share it
+        let mesh = self.assets.mesh(&format!("{source:?}"), |_| {
+            Mesh::new(&mesh.vertices, &mesh.indices, material)
+        });
+        self.scene
+            .world
+            .insert(entity, MeshRenderer { meshes: vec![mesh] });
+        Ok(())
+    }
+
+    fn add_light(
+        &mut self,
+        entity: Entity,
+        label: &str,
+        light: &LightDescription,
+    ) -> Result<(), SceneError> {
+        match *light {
+            LightDescription::Point {
+                color,
+                intensity,
+                range,
+                shadows,
+            } => {
+                let mut light = PointLight::new();
+                let taken = self.world_has::<PointLight>(|light| light.shadow_map.is_some());
+                if shadows && can_cast_shadows(label, taken) {
+                    light =
+                        light.with_shadow_map(CubeShadowMap::new(self.config.shadow_resolution));
+                }
+                light.color = color;
+                light.intensity = intensity;
+                light.range = range;
+                self.scene.world.insert(entity, light);
+            }
+            LightDescription::Spot {
+                color,
+                intensity,
+                range,
+                inner_angle,
+                outer_angle,
+                ref cookie,
+                shadows,
+            } => {
+                let mut light = SpotLight::new(
+                    Vec3::ZERO,
+                    -Vec3::Z,
+                    inner_angle.to_radians(),
+                    outer_angle.to_radians(),
+                );
+                if let Some(cookie) = cookie {
+                    let path = self.directory.join(cookie);
+                    check_exists(label, &path)?;
+                    let texture = self.assets.texture(&path, TextureKind::Cookie);
+                    light = light.with_cookie(texture);
+                    self.scene_node(entity).cookie = Some(path);
+                }
+                let taken = self.world_has::<SpotLight>(|light| light.shadow_map.is_some());
+                if shadows && can_cast_shadows(label, taken) {
+                    light =
+                        light.with_shadow_map(SpotShadowMap::new(self.config.shadow_resolution));
+                }
+                light.color = color;
+                light.intensity = intensity;
+                light.range = range;
+                self.scene.world.insert(entity, light);
+            }
+            LightDescription::Directional {
+                color,
+                intensity,
+                shadows,
+            } => {
+                let mut light = DirectionalLight::new(-Vec3::Z, color, intensity);
+                let taken = self.world_has::<DirectionalLight>(|light| light.shadow_map.is_some());
+                if shadows && can_cast_shadows(label, taken) {
+                    light = light.with_shadow_map(CascadedShadowMap::new(
+                        self.config.cascade_resolution,
+                        self.config.cascade_count.clamp(1, MAX_CASCADES),
+                    ));
+                }
+                self.scene.world.insert(entity, light);
+            }
+        }
+        Ok(())
+    }
+
+    fn scene_node(&mut self, entity: Entity) -> &mut SceneNode {
+        self.scene.world.get_mut(entity).unwrap()
+    }
+
+    fn world_has<T: 'static>(&self, predicate: impl Fn(&T) -> bool) -> bool {
+        self.scene
+            .world
+            .query::<T>()
+            .any(|(_, component)| predicate(component))
+    }
+}
+
+/// Whether the light of the node labelled `label` can get a shadow map,
+/// `taken` telling that a light of its kind already has one. The lighting
+/// shaders only sample the first shadow map of each kind.
+fn can_cast_shadows(label: &str, taken: bool) -> bool {
+    if taken {
+        warn!("{label}: only the first light of each kind casts shadows, ignoring its shadows");
+    }
+    !taken
+}
+
+/// Descriptions of the saved `nodes` and of their saved descendants, the
+/// children of the other nodes taking their place.
+fn describe_nodes(
+    scene: &Scene,
+    saved: &HashMap<NodeId, (Entity, &SceneNode)>,
+    nodes: &[NodeId],
+    relative: &impl Fn(&PathBuf) -> PathBuf,
+) -> Vec<NodeDescription> {
+    let mut descriptions = vec![];
+    for &id in nodes {
+        let children = describe_nodes(scene, saved, scene.graph.children(id), relative);
+        match saved.get(&id) {
+            Some(&(entity, node)) => {
+                let mut description = describe_node(scene, entity, node, relative);
+                description.children = children;
+                descriptions.push(description);
+            }
+            None => descriptions.extend(children),
+        }
+    }
+    descriptions
+}
+
+fn describe_node(
+    scene: &Scene,
+    entity: Entity,
+    node: &SceneNode,
+    relative: impl Fn(&PathBuf) -> PathBuf,
+) -> NodeDescription {
+    let mesh = node.mesh.as_ref().map(|mesh| mesh.map_paths(&relative));
+
+    let world = &scene.world;
+    let light = if let Some(point) = world.get::<PointLight>(entity) {
+        Some(LightDescription::Point {
+            color: point.color,
+            intensity: point.intensity,
+            range: point.range,
+            shadows: point.shadow_map.is_some(),
+        })
+    } else if let Some(spot) = world.get::<SpotLight>(entity) {
+        Some(LightDescription::Spot {
+            color: spot.color,
+            intensity: spot.intensity,
+            range: spot.range,
+            inner_angle: spot.inner_angle.to_degrees(),
+            outer_angle: spot.outer_angle.to_degrees(),
+            cookie: node.cookie.as_ref().map(&relative),
+            shadows: spot.shadow_map.is_some(),
+        })
+    } else {
+        world
+            .get::<DirectionalLight>(entity)
+            .map(|directional| LightDescription::Directional {
+                color: directional.color,
+                intensity: directional.intensity,
+                shadows: directional.shadow_map.is_some(),
+            })
+    };
+    let camera = world
+        .get::<Camera>(entity)
+        .map(|camera| CameraDescription { fov: camera.fov() });
+
+    NodeDescription {
+        name: node.name.clone(),
+        transform: scene
+            .world
+            .get::<Transform>(entity)
+            .copied()
+            .unwrap_or_default(),
+        mesh,
+        light,
+        camera,
+        children: vec![],
+    }
+}
+
+/// Names a node in errors by the path of names, or of indices for the
+/// anonymous nodes, from the root.
+fn node_label(parent: &str, node: &NodeDescription, index: usize) -> String {
+    let name = if node.name.is_empty() {
+        format!("#{index}")
+    } else {
+        node.name.clone()
+    };
+    if parent.is_empty() {
+        name
+    } else {
+        format!("{parent}/{name}")
+    }
+}
+
+fn check_exists(label: &str, path: &Path) -> Result<(), SceneError> {
+    if vfs::exists(path) {
+        Ok(())
+    } else {
+        Err(SceneError::MissingAsset {
+            node: label.to_owned(),
+            path: path.to_owned(),
+        })
+    }
+}
+
+/// Path from `base` to `path`, both relative to the same directory, with
+/// `..` components resolved lexically. Leaving a base that goes up from that
+/// directory needs its name, `path` is made absolute instead.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path_components = normalize(path);
+    let base = normalize(base);
+    let common = path_components
+        .iter()
+        .zip(base.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if base[common..]
+        .iter()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+        return normalize(&absolute).iter().collect();
+    }
+    let path = path_components;
+    base.iter()
+        .skip(common)
+        .map(|_| Path::new(".."))
+        .chain(path.iter().skip(common).map(Path::new))
+        .collect()
+}
+
+fn normalize(path: &Path) -> Vec<Component<'_>> {
+    let mut components = vec![];
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if matches!(components.last(), Some(Component::Normal(_))) => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    components
+}
+
+fn foliage(size: f32) -> MeshData {
+    let upright = Mat4::from_translation(Vec3::Y * size / 2.0) * Mat4::from_rotation_x(FRAC_PI_2);
+    let quad = primitives::plane(size, 1);
+    let mut foliage = geometry::merge([
+        (&quad, upright),
+        (&quad, Mat4::from_rotation_y(FRAC_PI_2) * upright),
+    ]);
+    for vertex in &mut foliage.vertices {
+        vertex.normals = Vec3::Y;
+    }
+    geometry::compute_tangents(&mut foliage.vertices, &foliage.indices);
+    foliage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn relative_paths() {
+        let relative = |path: &str, base: &str| relative_path(Path::new(path), Path::new(base));
+        assert_eq!(relative("res/x", "res/scenes"), PathBuf::from("../x"));
+        assert_eq!(
+            relative("res/scenes/x", "./res/scenes/"),
+            PathBuf::from("x")
+        );
+        assert_eq!(relative("./res/a/../x", "res"), PathBuf::from("x"));
+        assert_eq!(relative("x", ""), PathBuf::from("x"));
+        assert_eq!(
+            relative("../shared/x", "scenes"),
+            PathBuf::from("../../shared/x")
+        );
+        assert_eq!(relative("../scenes/x", "../scenes"), PathBuf::from("x"));
+        assert_eq!(relative("../res/x", "../scenes"), PathBuf::from("../res/x"));
+        // Going up from `..` needs the name of the working directory
+        assert_eq!(
+            relative("res/x", "../scenes"),
+            env::current_dir().unwrap().join("res/x")
+        );
+    }
+
+    #[test]
+    fn node_labels() {
+        let node = |name: &str| NodeDescription {
+            name: name.to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(node_label("", &node("floor"), 0), "floor");
+        assert_eq!(node_label("", &node(""), 2), "#2");
+        assert_eq!(node_label("lamp", &node("bulb"), 0), "lamp/bulb");
+        assert_eq!(node_label("#1", &node(""), 3), "#1/#3");
+    }
+
+    /// Loads `source` as a scene file in a mounted directory.
+    fn load_source(name: &str, source: &str) -> Result<Scene, SceneError> {
+        let directory = env::temp_dir().join(format!("scene-file-test-{}", std::process::id()));
+        fs::create_dir_all(directory.join("scenes")).unwrap();
+        vfs::mount_directory(&directory);
+        let path = Path::new("scenes").join(name);
+        fs::write(directory.join(&path), source).unwrap();
+        load(&path, &mut Assets::new(), &Config::default(), 1, 1)
+    }
+
+    #[test]
+    fn invalid_files_fail_to_parse() {
+        match load_source("invalid.ron", "(nodes: [(name: 3)])") {
+            Err(SceneError::Parse(path, _)) => assert_eq!(path, Path::new("scenes/invalid.ron")),
+            result => panic!("expected a parse error, got {:?}", result.err()),
+        }
+        match load_source("unknown.ron", "(nodes: [(size: 3.0)])") {
+            Err(SceneError::Parse(..)) => (),
+            result => panic!("expected a parse error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn lights_load_without_shadows() {
+        let source = r#"(nodes: [
+            (light: Some(Point(color: (1.0, 1.0, 1.0), intensity: 1.0, range: 5.0))),
+            (light: Some(Directional(color: (1.0, 1.0, 1.0), intensity: 1.0))),
+            (light: Some(Spot(
+                color: (1.0, 1.0, 1.0),
+                intensity: 1.0,
+                range: 5.0,
+                inner_angle: 15.0,
+                outer_angle: 25.0,
+            ))),
+        ])"#;
+        let scene = load_source("unshadowed.ron", source).unwrap();
+        let (_, point) = scene.world.query::<PointLight>().next().unwrap();
+        assert!(point.shadow_map.is_none());
+        let (_, directional) = scene.world.query::<DirectionalLight>().next().unwrap();
+        assert!(directional.shadow_map.is_none());
+        let (_, spot) = scene.world.query::<SpotLight>().next().unwrap();
+        assert!(spot.shadow_map.is_none() && !spot.has_cookie());
+    }
+
+    #[test]
+    fn saved_children_follow_their_parent() {
+        let mut scene = Scene::new();
+        let removed = scene.add_node(None, "removed", Transform::default());
+        let parent = scene.add_node(None, "parent", Transform::default());
+        scene.despawn(removed);
+        // Reuses the slots before the ones of its parent
+        scene.add_node(Some(parent), "child", Transform::from_translation(Vec3::X));
+
+        let path = env::temp_dir().join(format!("scene-save-test-{}.ron", std::process::id()));
+        save(&scene, &path).unwrap();
+        let file: SceneFile = ron::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(file.nodes.len(), 1);
+        assert_eq!(file.nodes[0].name, "parent");
+        let child = &file.nodes[0].children[0];
+        assert_eq!(child.name, "child");
+        assert_eq!(child.transform, Transform::from_translation(Vec3::X));
+    }
+
+    #[test]
+    fn missing_assets_name_their_node() {
+        let source =
+            r#"(nodes: [(name: "lamp", children: [(), (mesh: Some(Model("../missing.gltf")))])])"#;
+        match load_source("missing.ron", source) {
+            Err(SceneError::MissingAsset { node, path }) => {
+                assert_eq!(node, "lamp/#1");
+                assert_eq!(path, Path::new("scenes/../missing.gltf"));
+            }
+            result => panic!("expected a missing asset, got {:?}", result.err()),
+        }
+    }
+}
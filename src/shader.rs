@@ -1,49 +1,72 @@
-use glam::{Mat4, Vec3};
+use glam::{Mat4, UVec3, Vec2, Vec3, Vec4};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::Read;
+use std::io;
 use std::os::raw::c_char;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use std::ptr::null;
 
 use log::error;
 
-use crate::gl;
 use crate::gl::GLint;
+use crate::{gl, vfs};
 
 pub struct Shader {
     pub id: u32,
-    location_cache: HashMap<String, GLint>,
+    location_cache: RefCell<HashMap<String, GLint>>,
 }
 
 impl Shader {
     pub fn new(vertex_file: &Path, fragment_file: &Path) -> Self {
-        let mut vertex_file = File::open(vertex_file).expect("Could not open vertex shader file");
-        let mut vertex_source = String::new();
-        vertex_file.read_to_string(&mut vertex_source).unwrap();
+        Shader::from_stages(&[
+            (gl::VERTEX_SHADER, vertex_file),
+            (gl::FRAGMENT_SHADER, fragment_file),
+        ])
+    }
+
+    pub fn with_geometry(vertex_file: &Path, geometry_file: &Path, fragment_file: &Path) -> Self {
+        Shader::from_stages(&[
+            (gl::VERTEX_SHADER, vertex_file),
+            (gl::GEOMETRY_SHADER, geometry_file),
+            (gl::FRAGMENT_SHADER, fragment_file),
+        ])
+    }
 
-        let mut fragment_file =
-            File::open(fragment_file).expect("Could not open fragment shader file");
-        let mut fragment_source = String::new();
-        fragment_file.read_to_string(&mut fragment_source).unwrap();
+    pub fn compute(compute_file: &Path) -> Self {
+        Shader::from_stages(&[(gl::COMPUTE_SHADER, compute_file)])
+    }
 
+    fn from_stages(stages: &[(u32, &Path)]) -> Self {
         let program_id;
 
         unsafe {
-            let vert_id = compile_shader(gl::VERTEX_SHADER, vertex_source);
-            let frag_id = compile_shader(gl::FRAGMENT_SHADER, fragment_source);
             program_id = gl::CreateProgram();
-            gl::AttachShader(program_id, vert_id);
-            gl::AttachShader(program_id, frag_id);
+            let shader_ids: Vec<u32> = stages
+                .iter()
+                .map(|(shader_type, file)| {
+                    let source = read_source(file).unwrap_or_else(|err| {
+                        panic!(
+                            "Could not read {} shader file: {}",
+                            stage_name(*shader_type),
+                            err
+                        )
+                    });
+                    compile_shader(*shader_type, source)
+                })
+                .collect();
+            for shader_id in &shader_ids {
+                gl::AttachShader(program_id, *shader_id);
+            }
             gl::LinkProgram(program_id);
-            gl::DeleteShader(vert_id);
-            gl::DeleteShader(frag_id);
+            for shader_id in shader_ids {
+                gl::DeleteShader(shader_id);
+            }
         }
 
         Shader {
             id: program_id,
-            location_cache: HashMap::new(),
+            location_cache: RefCell::new(HashMap::new()),
         }
     }
 
@@ -53,37 +76,75 @@ impl Shader {
         }
     }
 
+    /// Size of the linked program binary, as reported by the driver.
+    pub fn memory_size(&self) -> usize {
+        let mut length = 0;
+        unsafe {
+            gl::GetProgramiv(self.id, gl::PROGRAM_BINARY_LENGTH, &mut length);
+        }
+        length as usize
+    }
+
     pub fn unbind(&self) {
         unsafe {
             gl::UseProgram(0);
         }
     }
 
-    pub fn set_uniform_1i(&mut self, location: &str, value: i32) {
+    pub fn set_uniform_1i(&self, location: &str, value: i32) {
         unsafe {
             let location_index = self.get_location(location);
             gl::Uniform1i(location_index, value);
         }
     }
 
-    pub fn set_uniform_vec3(&mut self, location: &str, value: Vec3) {
+    pub fn set_uniform_1f(&self, location: &str, value: f32) {
+        unsafe {
+            let location_index = self.get_location(location);
+            gl::Uniform1f(location_index, value);
+        }
+    }
+
+    pub fn set_uniform_vec2(&self, location: &str, value: Vec2) {
+        unsafe {
+            let location_index = self.get_location(location);
+            gl::Uniform2f(location_index, value.x, value.y);
+        }
+    }
+
+    pub fn set_uniform_vec3(&self, location: &str, value: Vec3) {
         unsafe {
             let location_index = self.get_location(location);
             gl::Uniform3f(location_index, value.x, value.y, value.z);
         }
     }
 
-    pub fn set_uniform_mat4(&mut self, location: &str, matrix: Mat4) {
+    pub fn set_uniform_vec4(&self, location: &str, value: Vec4) {
+        unsafe {
+            let location_index = self.get_location(location);
+            gl::Uniform4f(location_index, value.x, value.y, value.z, value.w);
+        }
+    }
+
+    pub fn set_uniform_uvec3(&self, location: &str, value: UVec3) {
+        unsafe {
+            let location_index = self.get_location(location);
+            gl::Uniform3ui(location_index, value.x, value.y, value.z);
+        }
+    }
+
+    pub fn set_uniform_mat4(&self, location: &str, matrix: Mat4) {
         unsafe {
             let location_index = self.get_location(location);
             gl::UniformMatrix4fv(location_index, 1, gl::FALSE, matrix.as_ref().as_ptr());
         }
     }
 
-    fn get_location(&mut self, location: &str) -> GLint {
+    fn get_location(&self, location: &str) -> GLint {
         unsafe {
             *self
                 .location_cache
+                .borrow_mut()
                 .entry(location.to_string())
                 .or_insert_with(|| {
                     let location = CString::new(location).unwrap();
@@ -107,7 +168,7 @@ unsafe fn compile_shader(shader_type: u32, source: String) -> u32 {
     gl::ShaderSource(id, 1, &raw_source.as_ptr() as *const _, null());
     gl::CompileShader(id);
     check_compile_status(id, shader_type);
-    return id;
+    id
 }
 
 unsafe fn check_compile_status(shader: u32, shader_type: u32) {
@@ -122,12 +183,135 @@ unsafe fn check_compile_status(shader: u32, shader_type: u32) {
         let message = CStr::from_ptr(message.as_ptr()).to_string_lossy();
         error!(
             "Failed to compile {} shader: {}",
-            if shader_type == gl::VERTEX_SHADER {
-                "vertex"
-            } else {
-                "fragment"
-            },
+            stage_name(shader_type),
             message
         );
     }
 }
+
+/// Reads a shader file, replacing each `#include "file"` line with the
+/// content of `file`, relative to the including file.
+fn read_source(path: &Path) -> io::Result<String> {
+    read_included(path, &mut Vec::new())
+}
+
+/// Expands the includes of `path`, `including` holding the files it is
+/// included from so that an include cycle fails instead of recursing forever.
+fn read_included(path: &Path, including: &mut Vec<PathBuf>) -> io::Result<String> {
+    let normalized = normalize(path);
+    if including.contains(&normalized) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} includes itself", path.display()),
+        ));
+    }
+    let source = vfs::read_to_string(path)?;
+
+    including.push(normalized);
+    let mut expanded = String::with_capacity(source.len());
+    for line in source.lines() {
+        match line.trim().strip_prefix("#include") {
+            Some(include) => {
+                let include_path = path
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(include.trim().trim_matches('"'));
+                expanded.push_str(&read_included(&include_path, including)?);
+            }
+            None => expanded.push_str(line),
+        }
+        expanded.push('\n');
+    }
+    including.pop();
+    Ok(expanded)
+}
+
+/// Resolves the `.` and `..` components of `path` so that the same file
+/// included through different relative paths compares equal.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn stage_name(shader_type: u32) -> &'static str {
+    match shader_type {
+        gl::VERTEX_SHADER => "vertex",
+        gl::GEOMETRY_SHADER => "geometry",
+        gl::COMPUTE_SHADER => "compute",
+        _ => "fragment",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    /// Writes `files` to a mounted directory and reads the first one.
+    fn read_files(name: &str, files: &[(&str, &str)]) -> io::Result<String> {
+        let directory = env::temp_dir().join(format!("shader-test-{}-{name}", std::process::id()));
+        for (path, source) in files {
+            let path = directory.join(name).join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        vfs::mount_directory(&directory);
+        read_source(&Path::new(name).join(files[0].0))
+    }
+
+    #[test]
+    fn includes_are_expanded() {
+        let files = [
+            (
+                "main.frag",
+                "#include \"common/light.glsl\"\nvoid main() {}",
+            ),
+            (
+                "common/light.glsl",
+                "#include \"../constants.glsl\"\nstruct Light {};",
+            ),
+            ("constants.glsl", "#define PI 3.14"),
+        ];
+        let source = read_files("expanded", &files).unwrap();
+        assert_eq!(
+            source,
+            "#define PI 3.14\n\nstruct Light {};\n\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn include_cycles_fail() {
+        let files = [("main.frag", "#include \"main.frag\"")];
+        let err = read_files("self", &files).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let files = [
+            ("main.frag", "#include \"common/a.glsl\""),
+            ("common/a.glsl", "#include \"../b.glsl\""),
+            ("b.glsl", "#include \"./common/a.glsl\""),
+        ];
+        let err = read_files("cycle", &files).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn files_included_twice_are_not_cycles() {
+        let files = [
+            ("main.frag", "#include \"a.glsl\"\n#include \"a.glsl\""),
+            ("a.glsl", "float a;"),
+        ];
+        assert_eq!(
+            read_files("twice", &files).unwrap(),
+            "float a;\n\nfloat a;\n\n"
+        );
+    }
+}
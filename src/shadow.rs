@@ -0,0 +1,501 @@
+use crate::{gl, Camera, Shader};
+use glam::{const_vec3, Mat4, Vec3};
+use log::error;
+use std::path::Path;
+
+/// Look direction and up vector of each cubemap face, in the
+/// `GL_TEXTURE_CUBE_MAP_POSITIVE_X + face` order.
+const CUBE_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, const_vec3!([0.0, -1.0, 0.0])),
+    (const_vec3!([-1.0, 0.0, 0.0]), const_vec3!([0.0, -1.0, 0.0])),
+    (Vec3::Y, Vec3::Z),
+    (const_vec3!([0.0, -1.0, 0.0]), const_vec3!([0.0, 0.0, -1.0])),
+    (Vec3::Z, const_vec3!([0.0, -1.0, 0.0])),
+    (const_vec3!([0.0, 0.0, -1.0]), const_vec3!([0.0, -1.0, 0.0])),
+];
+
+/// Omnidirectional shadow map storing, for each direction around a point
+/// light, the distance to the closest occluder divided by the far plane.
+pub struct CubeShadowMap {
+    framebuffer: u32,
+    texture: u32,
+    resolution: u32,
+    shader: Shader,
+    /// Depth offset in world units applied before comparing distances
+    pub bias: f32,
+    /// Radius in world units of the PCF sampling disk, 0 for hard shadows
+    pub pcf_radius: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl CubeShadowMap {
+    pub fn new(resolution: u32) -> Self {
+        let mut framebuffer = 0;
+        let mut texture = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_CUBE_MAP, 1, &mut texture);
+            gl::TextureStorage2D(
+                texture,
+                1,
+                gl::DEPTH_COMPONENT32F,
+                resolution as i32,
+                resolution as i32,
+            );
+            gl::TextureParameteri(texture, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TextureParameteri(texture, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TextureParameteri(texture, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(texture, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(texture, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+
+            gl::CreateFramebuffers(1, &mut framebuffer);
+            gl::NamedFramebufferTexture(framebuffer, gl::DEPTH_ATTACHMENT, texture, 0);
+            gl::NamedFramebufferDrawBuffer(framebuffer, gl::NONE);
+            gl::NamedFramebufferReadBuffer(framebuffer, gl::NONE);
+
+            let status = gl::CheckNamedFramebufferStatus(framebuffer, gl::FRAMEBUFFER);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                error!("Shadow framebuffer is incomplete: 0x{:X}", status);
+            }
+        }
+
+        let shader = Shader::with_geometry(
+            Path::new("shaders/shadow/point_shadow.vert"),
+            Path::new("shaders/shadow/point_shadow.geom"),
+            Path::new("shaders/shadow/point_shadow.frag"),
+        );
+
+        CubeShadowMap {
+            framebuffer,
+            texture,
+            resolution,
+            shader,
+            bias: 0.02,
+            pcf_radius: 0.01,
+            near: 0.05,
+            far: 25.0,
+        }
+    }
+
+    /// Renders the six faces of the cubemap in a single pass. `draw_casters`
+    /// draws every shadow caster with the given depth shader bound.
+    pub fn render(&mut self, light_position: Vec3, mut draw_casters: impl FnMut(&Shader)) {
+        let projection = Mat4::perspective_rh_gl(90f32.to_radians(), 1.0, self.near, self.far);
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(0, 0, self.resolution as i32, self.resolution as i32);
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+        }
+
+        self.shader.bind();
+        for (face, (direction, up)) in CUBE_FACES.iter().enumerate() {
+            let view = Mat4::look_at_rh(light_position, light_position + *direction, *up);
+            self.shader
+                .set_uniform_mat4(&format!("uShadowMatrices[{face}]"), projection * view);
+        }
+        self.shader
+            .set_uniform_vec3("uLightPosition", light_position);
+        self.shader.set_uniform_1f("uFarPlane", self.far);
+        draw_casters(&self.shader);
+        self.shader.unbind();
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Binds the cubemap to `unit` and sets the shadow uniforms of a lighting
+    /// shader.
+    pub fn bind(&self, shader: &Shader, unit: u32) {
+        unsafe {
+            gl::BindTextureUnit(unit, self.texture);
+        }
+        shader.set_uniform_1i("uShadowMap", unit as i32);
+        shader.set_uniform_1f("uShadowFarPlane", self.far);
+        shader.set_uniform_1f("uShadowBias", self.bias);
+        shader.set_uniform_1f("uShadowPcfRadius", self.pcf_radius);
+    }
+}
+
+impl Drop for CubeShadowMap {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+/// Maximum number of cascades, matching the uniform arrays of the lighting
+/// shader.
+pub const MAX_CASCADES: usize = 4;
+
+/// Shadow map of a directional light split into cascades covering
+/// consecutive slices of the camera frustum, each stored in a layer of a
+/// depth texture array.
+pub struct CascadedShadowMap {
+    framebuffer: u32,
+    texture: u32,
+    resolution: u32,
+    shader: Shader,
+    light_matrices: Vec<Mat4>,
+    splits: Vec<f32>,
+    /// Blend between the logarithmic (1) and uniform (0) split schemes
+    pub split_lambda: f32,
+    /// Distance from the camera after which nothing is shadowed
+    pub max_distance: f32,
+    /// Depth offset in light clip space applied before comparing depths
+    pub bias: f32,
+    /// Fraction of each cascade over which it fades into the next one
+    pub blend_fraction: f32,
+    /// Tints each cascade with a different color
+    pub debug: bool,
+}
+
+impl CascadedShadowMap {
+    pub fn new(resolution: u32, cascade_count: usize) -> Self {
+        assert!(
+            (1..=MAX_CASCADES).contains(&cascade_count),
+            "Cascade count must be between 1 and {MAX_CASCADES}"
+        );
+
+        let mut framebuffer = 0;
+        let mut texture = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_2D_ARRAY, 1, &mut texture);
+            gl::TextureStorage3D(
+                texture,
+                1,
+                gl::DEPTH_COMPONENT32F,
+                resolution as i32,
+                resolution as i32,
+                cascade_count as i32,
+            );
+            gl::TextureParameteri(texture, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TextureParameteri(texture, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TextureParameteri(texture, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as i32);
+            gl::TextureParameteri(texture, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as i32);
+            let border = [1.0f32; 4];
+            gl::TextureParameterfv(texture, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+
+            gl::CreateFramebuffers(1, &mut framebuffer);
+            gl::NamedFramebufferDrawBuffer(framebuffer, gl::NONE);
+            gl::NamedFramebufferReadBuffer(framebuffer, gl::NONE);
+        }
+
+        let shader = Shader::new(
+            Path::new("shaders/shadow/depth.vert"),
+            Path::new("shaders/shadow/depth.frag"),
+        );
+
+        CascadedShadowMap {
+            framebuffer,
+            texture,
+            resolution,
+            shader,
+            light_matrices: vec![Mat4::IDENTITY; cascade_count],
+            splits: vec![0.0; cascade_count],
+            split_lambda: 0.75,
+            max_distance: 20.0,
+            bias: 0.0015,
+            blend_fraction: 0.1,
+            debug: false,
+        }
+    }
+
+    /// Fits each cascade to its slice of the camera frustum and renders it.
+    /// `draw_casters` draws every shadow caster with the given depth shader
+    /// bound.
+    pub fn render(
+        &mut self,
+        camera: &Camera,
+        light_direction: Vec3,
+        mut draw_casters: impl FnMut(&Shader),
+    ) {
+        let far = camera.far().min(self.max_distance);
+        self.splits = practical_splits(camera.near(), far, self.splits.len(), self.split_lambda);
+
+        let mut cascade_near = camera.near();
+        for cascade in 0..self.splits.len() {
+            let corners = camera.frustum_corners(cascade_near, self.splits[cascade]);
+            self.light_matrices[cascade] = fit_cascade(&corners, light_direction, self.resolution);
+            cascade_near = self.splits[cascade];
+        }
+
+        self.shader.bind();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(0, 0, self.resolution as i32, self.resolution as i32);
+        }
+        for (cascade, light_matrix) in self.light_matrices.iter().enumerate() {
+            unsafe {
+                gl::NamedFramebufferTextureLayer(
+                    self.framebuffer,
+                    gl::DEPTH_ATTACHMENT,
+                    self.texture,
+                    0,
+                    cascade as i32,
+                );
+                gl::Clear(gl::DEPTH_BUFFER_BIT);
+            }
+            self.shader
+                .set_uniform_mat4("uLightViewProjection", *light_matrix);
+            draw_casters(&self.shader);
+        }
+        self.shader.unbind();
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Binds the texture array to `unit` and sets the cascade uniforms of a
+    /// lighting shader.
+    pub fn bind(&self, shader: &Shader, unit: u32) {
+        unsafe {
+            gl::BindTextureUnit(unit, self.texture);
+        }
+        shader.set_uniform_1i("uCascadeShadowMap", unit as i32);
+        shader.set_uniform_1i("uCascadeCount", self.splits.len() as i32);
+        for (cascade, (split, light_matrix)) in
+            self.splits.iter().zip(&self.light_matrices).enumerate()
+        {
+            shader.set_uniform_1f(&format!("uCascadeSplits[{cascade}]"), *split);
+            shader.set_uniform_mat4(&format!("uCascadeMatrices[{cascade}]"), *light_matrix);
+        }
+        shader.set_uniform_1f("uCascadeBias", self.bias);
+        shader.set_uniform_1f("uCascadeBlend", self.blend_fraction);
+        shader.set_uniform_1i("uCascadeDebug", self.debug as i32);
+    }
+}
+
+impl Drop for CascadedShadowMap {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+/// Far distance of each cascade using the practical split scheme, a blend
+/// of the logarithmic and uniform schemes weighted by `lambda`.
+fn practical_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let fraction = i as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// Orthographic light view projection enclosing the bounding sphere of a
+/// frustum slice. The sphere keeps the projection size constant when the
+/// camera rotates and the projection is snapped to whole texels so that
+/// shadow edges do not shimmer when the camera moves.
+fn fit_cascade(corners: &[Vec3; 8], light_direction: Vec3, resolution: u32) -> Mat4 {
+    let center = corners.iter().fold(Vec3::ZERO, |sum, corner| sum + *corner) / 8.0;
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0, f32::max);
+    // Rounding avoids size changes caused by floating point imprecision
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    // Pull the light back so that casters outside of the slice still write
+    // their depth
+    let caster_margin = radius * 2.0;
+    let direction = light_direction.normalize();
+    let up = if direction.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let eye = center - direction * (radius + caster_margin);
+    let view = Mat4::look_at_rh(eye, center, up);
+    let mut projection = Mat4::orthographic_rh_gl(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        0.0,
+        2.0 * radius + caster_margin,
+    );
+
+    let origin = (projection * view).project_point3(Vec3::ZERO) * resolution as f32 / 2.0;
+    let offset = (origin.round() - origin) * 2.0 / resolution as f32;
+    projection.w_axis.x += offset.x;
+    projection.w_axis.y += offset.y;
+
+    projection * view
+}
+
+/// Perspective shadow map of a spot light, covering its outer cone.
+pub struct SpotShadowMap {
+    framebuffer: u32,
+    texture: u32,
+    resolution: u32,
+    shader: Shader,
+    /// Depth offset in light clip space applied before comparing depths
+    pub bias: f32,
+}
+
+impl SpotShadowMap {
+    pub fn new(resolution: u32) -> Self {
+        let mut framebuffer = 0;
+        let mut texture = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_2D, 1, &mut texture);
+            gl::TextureStorage2D(
+                texture,
+                1,
+                gl::DEPTH_COMPONENT32F,
+                resolution as i32,
+                resolution as i32,
+            );
+            gl::TextureParameteri(texture, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TextureParameteri(texture, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TextureParameteri(texture, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as i32);
+            gl::TextureParameteri(texture, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as i32);
+            let border = [1.0f32; 4];
+            gl::TextureParameterfv(texture, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+
+            gl::CreateFramebuffers(1, &mut framebuffer);
+            gl::NamedFramebufferTexture(framebuffer, gl::DEPTH_ATTACHMENT, texture, 0);
+            gl::NamedFramebufferDrawBuffer(framebuffer, gl::NONE);
+            gl::NamedFramebufferReadBuffer(framebuffer, gl::NONE);
+
+            let status = gl::CheckNamedFramebufferStatus(framebuffer, gl::FRAMEBUFFER);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                error!("Shadow framebuffer is incomplete: 0x{:X}", status);
+            }
+        }
+
+        let shader = Shader::new(
+            Path::new("shaders/shadow/depth.vert"),
+            Path::new("shaders/shadow/depth.frag"),
+        );
+
+        SpotShadowMap {
+            framebuffer,
+            texture,
+            resolution,
+            shader,
+            bias: 0.0005,
+        }
+    }
+
+    /// Renders the depth seen through `light_matrix`. `draw_casters` draws
+    /// every shadow caster with the given depth shader bound.
+    pub fn render(&mut self, light_matrix: Mat4, mut draw_casters: impl FnMut(&Shader)) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(0, 0, self.resolution as i32, self.resolution as i32);
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+        }
+
+        self.shader.bind();
+        self.shader
+            .set_uniform_mat4("uLightViewProjection", light_matrix);
+        draw_casters(&self.shader);
+        self.shader.unbind();
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Binds the shadow map to `unit` and sets the shadow uniforms of a
+    /// lighting shader.
+    pub fn bind(&self, shader: &Shader, unit: u32) {
+        unsafe {
+            gl::BindTextureUnit(unit, self.texture);
+        }
+        shader.set_uniform_1i("uSpotShadowMap", unit as i32);
+        shader.set_uniform_1f("uSpotShadowBias", self.bias);
+    }
+}
+
+impl Drop for SpotShadowMap {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_increase_up_to_the_far_plane() {
+        for lambda in [0.0, 0.5, 1.0] {
+            let splits = practical_splits(0.1, 100.0, 4, lambda);
+            assert_eq!(splits.len(), 4);
+            assert!(splits[0] > 0.1);
+            assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!((splits[3] - 100.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn lambda_blends_uniform_and_logarithmic_splits() {
+        let uniform = practical_splits(1.0, 101.0, 4, 0.0);
+        assert_eq!(uniform[..3], [26.0, 51.0, 76.0]);
+        let logarithmic = practical_splits(1.0, 10000.0, 4, 1.0);
+        for (split, expected) in logarithmic.iter().zip([10.0, 100.0, 1000.0]) {
+            assert!((split - expected).abs() < expected * 1e-4);
+        }
+        // Logarithmic splits give more resolution close to the camera
+        let close = practical_splits(0.1, 100.0, 4, 0.9);
+        let far = practical_splits(0.1, 100.0, 4, 0.1);
+        assert!(close[..3]
+            .iter()
+            .zip(&far[..3])
+            .all(|(close, far)| close < far));
+    }
+
+    /// Corners of the slice of a perspective frustum looking down -Z from
+    /// `position` between `near` and `far`.
+    fn slice_corners(position: Vec3, near: f32, far: f32) -> [Vec3; 8] {
+        let projection = Mat4::perspective_rh(45f32.to_radians(), 16.0 / 9.0, near, far);
+        let view = Mat4::from_translation(-position);
+        let inverse = (projection * view).inverse();
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let ndc = Vec3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+            );
+            *corner = inverse.project_point3(ndc);
+        }
+        corners
+    }
+
+    #[test]
+    fn cascades_contain_their_slice() {
+        let resolution = 2048;
+        // Snapping moves the projection by less than a texel
+        let margin = 1.0 + 2.0 / resolution as f32;
+        let corners = slice_corners(Vec3::new(3.0, 1.0, -2.0), 2.0, 15.0);
+        for direction in [Vec3::new(-0.3, -1.0, 0.2), -Vec3::Y, Vec3::X] {
+            let light_matrix = fit_cascade(&corners, direction, resolution);
+            for corner in corners {
+                let projected = light_matrix.project_point3(corner);
+                assert!(projected.x.abs() <= margin && projected.y.abs() <= margin);
+                assert!(projected.z.abs() <= 1.0);
+            }
+
+            // The world origin falls on a texel corner
+            let origin = light_matrix.project_point3(Vec3::ZERO) * resolution as f32 / 2.0;
+            assert!((origin.x - origin.x.round()).abs() < 1e-2);
+            assert!((origin.y - origin.y.round()).abs() < 1e-2);
+        }
+    }
+}
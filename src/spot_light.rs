@@ -0,0 +1,97 @@
+use crate::shadow::SpotShadowMap;
+use crate::texture::{Texture, TextureKind};
+use crate::Shader;
+use glam::{Mat4, Vec3};
+use std::rc::Rc;
+
+/// Light emitted from a point inside a cone, fading out between the inner
+/// and outer angles and over its range.
+pub struct SpotLight {
+    pub position: Vec3,
+    /// Direction the cone points to
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Half angle in radians of the fully lit part of the cone
+    pub inner_angle: f32,
+    /// Half angle in radians after which nothing is lit
+    pub outer_angle: f32,
+    /// Distance after which the light has no effect
+    pub range: f32,
+    cookie: Option<Rc<Texture>>,
+    pub shadow_map: Option<SpotShadowMap>,
+}
+
+impl SpotLight {
+    pub fn new(position: Vec3, direction: Vec3, inner_angle: f32, outer_angle: f32) -> Self {
+        SpotLight {
+            position,
+            direction: direction.normalize(),
+            color: Vec3::ONE,
+            intensity: 1.0,
+            inner_angle,
+            outer_angle,
+            range: 10.0,
+            cookie: None,
+            shadow_map: None,
+        }
+    }
+
+    /// Places the light at the origin of `transform`, pointing to its -Z axis.
+    pub fn set_world_transform(&mut self, transform: Mat4) {
+        self.position = transform.w_axis.truncate();
+        self.direction = transform.transform_vector3(-Vec3::Z).normalize();
+    }
+
+    /// Projects the texture over the cone, tinting the light.
+    pub fn with_cookie(mut self, cookie: Rc<Texture>) -> Self {
+        assert_eq!(cookie.kind(), TextureKind::Cookie);
+        self.cookie = Some(cookie);
+        self
+    }
+
+    pub fn with_shadow_map(mut self, shadow_map: SpotShadowMap) -> Self {
+        self.shadow_map = Some(shadow_map);
+        self
+    }
+
+    /// View projection through which the light sees its outer cone, used to
+    /// project the cookie and the shadow map.
+    pub fn light_matrix(&self) -> Mat4 {
+        let up = if self.direction.y.abs() > 0.99 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        let view = Mat4::look_at_rh(self.position, self.position + self.direction, up);
+        let projection = Mat4::perspective_rh_gl(2.0 * self.outer_angle, 1.0, 0.05, self.range);
+        projection * view
+    }
+
+    /// Updates the shadow map, if any, from the current light placement.
+    pub fn render_shadow_map(&mut self, draw_casters: impl FnMut(&Shader)) {
+        let light_matrix = self.light_matrix();
+        if let Some(shadow_map) = &mut self.shadow_map {
+            shadow_map.render(light_matrix, draw_casters);
+        }
+    }
+
+    pub fn has_cookie(&self) -> bool {
+        self.cookie.is_some()
+    }
+
+    /// Sets the cookie and shadow uniforms of a lighting shader, binding the
+    /// shadow map to `shadow_unit` if the light casts shadows.
+    pub fn bind_shadow_map(&self, shader: &Shader, shadow_unit: u32) {
+        shader.set_uniform_mat4("uSpotLightMatrix", self.light_matrix());
+
+        if let Some(cookie) = &self.cookie {
+            cookie.bind();
+            shader.set_uniform_1i("uSpotLightCookie", TextureKind::Cookie.unit() as i32);
+        }
+
+        if let Some(shadow_map) = &self.shadow_map {
+            shadow_map.bind(shader, shadow_unit);
+        }
+    }
+}
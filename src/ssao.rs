@@ -0,0 +1,247 @@
+use crate::deferred::NORMAL_ATTACHMENT;
+use crate::framebuffer::Framebuffer;
+use crate::{gl, Camera, Shader, Vao};
+use glam::{Vec2, Vec3};
+use std::path::Path;
+
+/// Size of the kernel array of the SSAO shader.
+pub const MAX_SSAO_SAMPLES: usize = 64;
+/// Width and height of the tiled random rotation texture.
+const NOISE_SIZE: u32 = 4;
+
+// Texture units used by the SSAO passes, and by the lighting shaders for the
+// blurred result
+const NORMAL_UNIT: u32 = 0;
+const DEPTH_UNIT: u32 = 1;
+const NOISE_UNIT: u32 = 2;
+const OCCLUSION_UNIT: u32 = 16;
+
+/// Screen-space ambient occlusion: darkens the ambient lighting of points
+/// surrounded by nearby geometry, estimated from the depth and normals of
+/// the visible surfaces.
+///
+/// The deferred renderer provides its G-buffer, the forward renderer fills
+/// a depth and normal prepass owned by this struct.
+pub struct Ssao {
+    prepass: Framebuffer,
+    prepass_shader: Shader,
+    occlusion: Framebuffer,
+    blurred: Framebuffer,
+    ssao_shader: Shader,
+    blur_shader: Shader,
+    noise_texture: u32,
+    vao: Vao,
+    sample_count: usize,
+    /// Radius of the sampled hemisphere, in world units
+    pub radius: f32,
+    /// Exponent applied to the occlusion, higher values darken it
+    pub intensity: f32,
+    /// Depth offset avoiding self-occlusion on flat surfaces
+    pub bias: f32,
+    pub enabled: bool,
+}
+
+impl Ssao {
+    pub fn new(width: u32, height: u32, sample_count: usize) -> Self {
+        let sample_count = sample_count.min(MAX_SSAO_SAMPLES);
+        let mut random = XorShift(0x9e37_79b9);
+
+        let ssao_shader = Shader::new(
+            Path::new("shaders/post/fullscreen.vert"),
+            Path::new("shaders/ssao/ssao.frag"),
+        );
+        ssao_shader.bind();
+        ssao_shader.set_uniform_1i("uNormalTexture", NORMAL_UNIT as i32);
+        ssao_shader.set_uniform_1i("uDepthTexture", DEPTH_UNIT as i32);
+        ssao_shader.set_uniform_1i("uNoiseTexture", NOISE_UNIT as i32);
+        ssao_shader.set_uniform_1i("uSampleCount", sample_count as i32);
+        for i in 0..sample_count {
+            // Points in the hemisphere around +Z, denser close to the origin
+            let direction = Vec3::new(
+                random.next() * 2.0 - 1.0,
+                random.next() * 2.0 - 1.0,
+                random.next(),
+            )
+            .normalize_or_zero();
+            let scale = i as f32 / sample_count as f32;
+            let sample = direction * random.next() * (0.1 + 0.9 * scale * scale);
+            ssao_shader.set_uniform_vec3(&format!("uKernel[{i}]"), sample);
+        }
+        ssao_shader.unbind();
+
+        let blur_shader = Shader::new(
+            Path::new("shaders/post/fullscreen.vert"),
+            Path::new("shaders/ssao/blur.frag"),
+        );
+        blur_shader.bind();
+        blur_shader.set_uniform_1i("uOcclusionTexture", 0);
+        blur_shader.unbind();
+
+        let noise: Vec<[f32; 2]> = (0..NOISE_SIZE * NOISE_SIZE)
+            .map(|_| [random.next() * 2.0 - 1.0, random.next() * 2.0 - 1.0])
+            .collect();
+        let mut noise_texture = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_2D, 1, &mut noise_texture);
+            gl::TextureStorage2D(
+                noise_texture,
+                1,
+                gl::RG16F,
+                NOISE_SIZE as i32,
+                NOISE_SIZE as i32,
+            );
+            gl::TextureSubImage2D(
+                noise_texture,
+                0,
+                0,
+                0,
+                NOISE_SIZE as i32,
+                NOISE_SIZE as i32,
+                gl::RG,
+                gl::FLOAT,
+                noise.as_ptr() as *const _,
+            );
+            gl::TextureParameteri(noise_texture, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TextureParameteri(noise_texture, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TextureParameteri(noise_texture, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TextureParameteri(noise_texture, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+        }
+
+        Ssao {
+            prepass: Framebuffer::new(width, height)
+                .with_color_attachment(gl::RGBA16F)
+                .with_depth_attachment(),
+            prepass_shader: Shader::new(
+                Path::new("shaders/default.vert"),
+                Path::new("shaders/ssao/prepass.frag"),
+            ),
+            occlusion: Framebuffer::new(width, height).with_color_attachment(gl::RG16F),
+            blurred: Framebuffer::new(width, height).with_color_attachment(gl::R8),
+            ssao_shader,
+            blur_shader,
+            noise_texture,
+            vao: Vao::new(),
+            sample_count,
+            radius: 0.5,
+            intensity: 1.5,
+            bias: 0.025,
+            enabled: sample_count > 0,
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.prepass.resize(width, height);
+        self.occlusion.resize(width, height);
+        self.blurred.resize(width, height);
+    }
+
+    /// Fills the depth and normal prepass used when there is no G-buffer,
+    /// `draw` sets `uModel`, the material uniforms and `uAlphaCutoff` and
+    /// draws the opaque meshes.
+    pub fn render_prepass(&mut self, camera: &Camera, mut draw: impl FnMut(&Shader)) {
+        if !self.enabled {
+            return;
+        }
+        self.prepass.bind();
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+        self.prepass_shader.bind();
+        self.prepass_shader
+            .set_uniform_mat4("uCameraViewProjection", camera.get_matrix());
+        draw(&self.prepass_shader);
+        self.prepass_shader.unbind();
+    }
+
+    /// Computes and blurs the occlusion from the deferred renderer G-buffer,
+    /// or from the prepass when `gbuffer` is `None`.
+    pub fn render(&mut self, camera: &Camera, gbuffer: Option<&Framebuffer>) {
+        if !self.enabled {
+            return;
+        }
+        let (source, normal_attachment) = match gbuffer {
+            Some(gbuffer) => (gbuffer, NORMAL_ATTACHMENT),
+            None => (&self.prepass, 0),
+        };
+
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+        }
+        self.vao.bind();
+
+        self.occlusion.bind();
+        source.bind_color_texture(normal_attachment, NORMAL_UNIT);
+        source.bind_depth_texture(DEPTH_UNIT);
+        unsafe {
+            gl::BindTextureUnit(NOISE_UNIT, self.noise_texture);
+        }
+        let projection = camera.get_projection_matrix();
+        let noise_scale = Vec2::new(
+            self.occlusion.width() as f32,
+            self.occlusion.height() as f32,
+        ) / NOISE_SIZE as f32;
+        self.ssao_shader.bind();
+        self.ssao_shader
+            .set_uniform_mat4("uView", camera.get_view_matrix());
+        self.ssao_shader.set_uniform_mat4("uProjection", projection);
+        self.ssao_shader
+            .set_uniform_mat4("uInverseProjection", projection.inverse());
+        self.ssao_shader
+            .set_uniform_vec2("uNoiseScale", noise_scale);
+        self.ssao_shader.set_uniform_1f("uRadius", self.radius);
+        self.ssao_shader.set_uniform_1f("uBias", self.bias);
+        self.ssao_shader
+            .set_uniform_1f("uIntensity", self.intensity);
+        unsafe {
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+
+        self.blurred.bind();
+        self.occlusion.bind_color_texture(0, 0);
+        self.blur_shader.bind();
+        unsafe {
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+        self.blur_shader.unbind();
+
+        self.vao.unbind();
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+
+    /// Binds the blurred occlusion to a lighting shader.
+    pub fn bind(&self, shader: &Shader) {
+        self.blurred.bind_color_texture(0, OCCLUSION_UNIT);
+        shader.set_uniform_1i("uAmbientOcclusion", OCCLUSION_UNIT as i32);
+        shader.set_uniform_1i("uSsaoEnabled", self.enabled as i32);
+    }
+
+    /// Flips `enabled`, which stays off when there are no samples.
+    pub fn toggle(&mut self) -> bool {
+        self.enabled = !self.enabled && self.sample_count > 0;
+        self.enabled
+    }
+}
+
+impl Drop for Ssao {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.noise_texture);
+        }
+    }
+}
+
+/// Small deterministic generator for the kernel and noise, so that the
+/// occlusion looks the same on every run.
+struct XorShift(u32);
+
+impl XorShift {
+    /// Returns a number in [0, 1).
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
@@ -0,0 +1,49 @@
+use crate::gl;
+use std::mem::size_of_val;
+use std::ptr::null;
+
+/// Shader storage buffer of a fixed size, rewritten from the CPU or filled
+/// by compute shaders.
+#[derive(Debug)]
+pub struct Ssbo {
+    pub id: u32,
+    size: usize,
+}
+
+impl Ssbo {
+    pub fn new(size: usize) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::CreateBuffers(1, &mut id);
+            gl::NamedBufferData(id, size as _, null(), gl::DYNAMIC_DRAW);
+        }
+        Ssbo { id, size }
+    }
+
+    /// Writes `data` at the start of the buffer.
+    pub fn upload<T>(&self, data: &[T]) {
+        let data_size = size_of_val(data);
+        assert!(
+            data_size <= self.size,
+            "{data_size} bytes do not fit in a {} bytes buffer",
+            self.size
+        );
+        unsafe {
+            gl::NamedBufferSubData(self.id, 0, data_size as _, data.as_ptr() as *const _);
+        }
+    }
+
+    pub fn bind_base(&self, binding: u32) {
+        unsafe {
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, self.id);
+        }
+    }
+}
+
+impl Drop for Ssbo {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+        }
+    }
+}
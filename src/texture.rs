@@ -1,32 +1,124 @@
-use crate::gl;
-use image::EncodableLayout;
+use crate::cooked::{self, PixelFormat};
+use crate::{gl, vfs};
+use image::{DynamicImage, ImageError, ImageFormat, ImageResult};
+use serde::Deserialize;
+use std::cell::Cell;
 use std::path::Path;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
 pub enum TextureKind {
-    DIFFUSE,
-    SPECULAR,
+    /// sRGB albedo, alpha used for alpha testing
+    BaseColor,
+    /// Linear roughness in green and metallic in blue, as in glTF
+    MetallicRoughness,
+    /// Linear ambient occlusion in red
+    Occlusion,
+    /// sRGB emitted color
+    Emissive,
+    /// Pattern projected by a spot light
+    Cookie,
+}
+
+impl TextureKind {
+    /// Texture unit from which the lighting shader samples this kind of texture
+    pub fn unit(&self) -> u32 {
+        match self {
+            TextureKind::BaseColor => 0,
+            TextureKind::MetallicRoughness => 1,
+            TextureKind::Cookie => 5,
+            TextureKind::Occlusion => 6,
+            TextureKind::Emissive => 7,
+        }
+    }
+
+    /// Whether the texels are colors to decode from sRGB when sampled
+    pub fn is_srgb(&self) -> bool {
+        matches!(
+            self,
+            TextureKind::BaseColor | TextureKind::Emissive | TextureKind::Cookie
+        )
+    }
+}
+
+// S3TC formats, from EXT_texture_compression_s3tc and EXT_texture_sRGB
+const COMPRESSED_RGBA_S3TC_DXT5: u32 = 0x83F3;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT5: u32 = 0x8C4F;
+
+/// Texels of a texture and of its mipmaps, starting from the bottom row.
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    /// Texels of each mipmap level, from the full size one. The mipmaps are
+    /// generated on upload when there is a single RGBA level.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl TextureData {
+    /// Decoded 8 bits RGBA texels without mipmaps.
+    pub fn rgba(width: u32, height: u32, texels: Vec<u8>) -> Self {
+        TextureData {
+            width,
+            height,
+            format: PixelFormat::Rgba8,
+            levels: vec![texels],
+        }
+    }
+
+    /// Reads a texture cooked by the `cook` binary (`.ctex`) or decodes an
+    /// image file, which can be done off the GL thread.
+    pub fn open(path: &Path) -> ImageResult<Self> {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "ctex")
+        {
+            let cooked = cooked::read_texture(&vfs::read(path)?).map_err(ImageError::IoError)?;
+            return Ok(TextureData {
+                width: cooked.width,
+                height: cooked.height,
+                format: cooked.format,
+                levels: cooked.levels,
+            });
+        }
+        let image = open_image(path)?.flipv().into_rgba8();
+        Ok(TextureData::rgba(
+            image.width(),
+            image.height(),
+            image.into_raw(),
+        ))
+    }
+
+    fn generates_mipmaps(&self) -> bool {
+        self.format == PixelFormat::Rgba8 && self.levels.len() == 1
+    }
 }
 
 #[derive(Debug)]
 pub struct Texture {
     id: u32,
-    kind: TextureKind
+    kind: TextureKind,
+    memory_size: Cell<usize>,
 }
 
 impl Texture {
-    pub fn new(path: &Path, kind: TextureKind) -> Self {
-        let image = image::open(path).expect("Unable to open texture").flipv();
-        let rgba_image = image.into_rgba8();
+    /// Single texel texture standing for one still loading, neutral for the
+    /// shading of its kind.
+    pub fn placeholder(kind: TextureKind) -> Self {
+        let texel = match kind {
+            TextureKind::BaseColor => [200, 200, 200, 255],
+            TextureKind::MetallicRoughness | TextureKind::Occlusion | TextureKind::Cookie => {
+                [255; 4]
+            }
+            TextureKind::Emissive => [0, 0, 0, 255],
+        };
+        Texture::new(&TextureData::rgba(1, 1, texel.to_vec()), kind)
+    }
 
+    pub fn new(data: &TextureData, kind: TextureKind) -> Self {
         let mut id = 0;
         unsafe {
             gl::GenTextures(1, &mut id);
-            if kind == TextureKind::DIFFUSE {
-                gl::ActiveTexture(gl::TEXTURE0);
-            } else if kind == TextureKind::SPECULAR {
-                gl::ActiveTexture(gl::TEXTURE1);
-            }
+            gl::ActiveTexture(gl::TEXTURE0 + kind.unit());
             gl::BindTexture(gl::TEXTURE_2D, id);
 
             gl::TexParameteri(
@@ -35,41 +127,112 @@ impl Texture {
                 gl::LINEAR_MIPMAP_LINEAR as i32,
             );
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+            // Cookies are projected once, repeating them would leak on the cone edges
+            let wrap = if kind == TextureKind::Cookie {
+                gl::CLAMP_TO_EDGE
+            } else {
+                gl::REPEAT
+            };
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap as i32);
+        }
 
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::RGBA as i32,
-                rgba_image.width() as i32,
-                rgba_image.height() as i32,
-                0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                rgba_image.as_bytes().as_ptr() as *const _,
-            );
-            gl::GenerateMipmap(gl::TEXTURE_2D);
+        let texture = Texture {
+            id,
+            kind,
+            memory_size: Cell::new(0),
+        };
+        texture.upload(data);
+        texture
+    }
+
+    /// Replaces the texels and their mipmaps, keeping the texture object so
+    /// that its users see the new ones.
+    pub fn upload(&self, data: &TextureData) {
+        let srgb = self.kind.is_srgb();
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + self.kind.unit());
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            for (level, texels) in data.levels.iter().enumerate() {
+                // GL reads the texels the dimensions ask for
+                assert_eq!(
+                    texels.len(),
+                    data.format.level_size(data.width, data.height, level)
+                );
+                let width = (data.width >> level).max(1) as i32;
+                let height = (data.height >> level).max(1) as i32;
+                match data.format {
+                    PixelFormat::Rgba8 => {
+                        let internal_format = if srgb { gl::SRGB8_ALPHA8 } else { gl::RGBA8 };
+                        gl::TexImage2D(
+                            gl::TEXTURE_2D,
+                            level as i32,
+                            internal_format as i32,
+                            width,
+                            height,
+                            0,
+                            gl::RGBA,
+                            gl::UNSIGNED_BYTE,
+                            texels.as_ptr() as *const _,
+                        );
+                    }
+                    PixelFormat::Dxt5 => {
+                        let internal_format = if srgb {
+                            COMPRESSED_SRGB_ALPHA_S3TC_DXT5
+                        } else {
+                            COMPRESSED_RGBA_S3TC_DXT5
+                        };
+                        gl::CompressedTexImage2D(
+                            gl::TEXTURE_2D,
+                            level as i32,
+                            internal_format,
+                            width,
+                            height,
+                            0,
+                            texels.len() as i32,
+                            texels.as_ptr() as *const _,
+                        );
+                    }
+                }
+            }
+            if data.generates_mipmaps() {
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 1000);
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            } else {
+                // Sampling stops at the last level given instead of missing ones
+                let max_level = data.levels.len() as i32 - 1;
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, max_level);
+            }
             gl::BindTexture(gl::TEXTURE_2D, 0);
         }
 
-        Texture { id, kind }
+        let size: usize = data.levels.iter().map(Vec::len).sum();
+        self.memory_size.set(if data.generates_mipmaps() {
+            size * 4 / 3
+        } else {
+            size
+        });
     }
 
     pub fn bind(&self) {
         unsafe {
-            if self.kind == TextureKind::DIFFUSE {
-                gl::ActiveTexture(gl::TEXTURE0);
-            } else if self.kind == TextureKind::SPECULAR {
-                gl::ActiveTexture(gl::TEXTURE1);
-            }
+            gl::ActiveTexture(gl::TEXTURE0 + self.kind.unit());
             gl::BindTexture(gl::TEXTURE_2D, self.id);
         }
     }
 
+    pub fn kind(&self) -> TextureKind {
+        self.kind
+    }
+
+    /// Bytes used by the texels and their mipmaps.
+    pub fn memory_size(&self) -> usize {
+        self.memory_size.get()
+    }
+
     pub fn unbind(&self) {
         unsafe {
-            gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::BindTextureUnit(self.kind.unit(), 0);
         }
     }
 }
@@ -81,3 +244,90 @@ impl Drop for Texture {
         }
     }
 }
+
+/// 3D color lookup table used for color grading.
+#[derive(Debug)]
+pub struct LutTexture {
+    id: u32,
+    /// Texels along each axis
+    size: u32,
+}
+
+impl LutTexture {
+    /// Loads a LUT stored as a horizontal strip of `size` slices of
+    /// `size`x`size` pixels, blue increasing from one slice to the next.
+    pub fn from_strip(path: &Path) -> Self {
+        let image = open_image(path).expect("Unable to open LUT").into_rgba8();
+        let size = image.height();
+        assert_eq!(
+            image.width(),
+            size * size,
+            "LUT strip must be {}x{} pixels",
+            size * size,
+            size
+        );
+
+        let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    texels.extend_from_slice(&image.get_pixel(b * size + r, g).0);
+                }
+            }
+        }
+
+        LutTexture::from_texels(size, &texels)
+    }
+
+    fn from_texels(size: u32, texels: &[u8]) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_3D, 1, &mut id);
+            gl::TextureStorage3D(id, 1, gl::RGBA8, size as i32, size as i32, size as i32);
+            gl::TextureSubImage3D(
+                id,
+                0,
+                0,
+                0,
+                0,
+                size as i32,
+                size as i32,
+                size as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                texels.as_ptr() as *const _,
+            );
+            gl::TextureParameteri(id, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TextureParameteri(id, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TextureParameteri(id, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(id, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(id, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        }
+        LutTexture { id, size }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn bind(&self, unit: u32) {
+        unsafe {
+            gl::BindTextureUnit(unit, self.id);
+        }
+    }
+}
+
+impl Drop for LutTexture {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+}
+
+/// Decodes an image file read from the mounted directories and archives,
+/// its format being guessed from the extension.
+fn open_image(path: &Path) -> ImageResult<DynamicImage> {
+    let bytes = vfs::read(path)?;
+    image::load_from_memory_with_format(&bytes, ImageFormat::from_path(path)?)
+}
@@ -0,0 +1,142 @@
+use std::fmt::{self, Display, Formatter};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Worker threads running jobs in the order they were spawned. A job that
+/// panics fails its task, the worker moving on to the next job.
+pub struct ThreadPool {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    pub fn new(name: &str, threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("{name} {i}"))
+                    .spawn(move || loop {
+                        // The lock is released before running the job
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            // The panic message is printed by the panic hook
+                            Ok(job) => {
+                                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                            }
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Unable to spawn a worker thread")
+            })
+            .collect();
+        ThreadPool {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Runs `job` on a worker, its result being polled from the returned
+    /// task.
+    pub fn spawn<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> Task<T> {
+        let (sender, receiver) = mpsc::channel();
+        let job: Job = Box::new(move || {
+            // The task may have been dropped meanwhile
+            let _ = sender.send(job());
+        });
+        self.sender.as_ref().unwrap().send(job).unwrap();
+        Task { receiver }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Closing the channel stops the workers once the queued jobs are done
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Result of a job running on a `ThreadPool`.
+pub struct Task<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Task<T> {
+    /// Returns the result once the job is done, only once, or an error if
+    /// the job panicked.
+    pub fn poll(&self) -> Result<Option<T>, TaskFailed> {
+        match self.receiver.try_recv() {
+            Ok(result) => Ok(Some(result)),
+            Err(TryRecvError::Empty) => Ok(None),
+            // The job dropped its sender without sending
+            Err(TryRecvError::Disconnected) => Err(TaskFailed),
+        }
+    }
+}
+
+impl<T, E: Display> Task<Result<T, E>> {
+    /// Returns the result of a job that can fail once it is done, its error
+    /// or its panic being turned into a message.
+    pub fn poll_result(&self) -> Option<Result<T, String>> {
+        match self.poll() {
+            Ok(result) => Some(result?.map_err(|err| err.to_string())),
+            Err(failed) => Some(Err(failed.to_string())),
+        }
+    }
+}
+
+/// Error of a task whose job panicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskFailed;
+
+impl Display for TaskFailed {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "the loading job panicked")
+    }
+}
+
+impl std::error::Error for TaskFailed {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait<T>(task: &Task<T>) -> Result<T, TaskFailed> {
+        let start = Instant::now();
+        loop {
+            if let Some(result) = task.poll()? {
+                return Ok(result);
+            }
+            assert!(start.elapsed() < Duration::from_secs(10), "task timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn tasks_return_their_result() {
+        let pool = ThreadPool::new("test", 2);
+        let tasks: Vec<_> = (0..8).map(|i| pool.spawn(move || i * 2)).collect();
+        let results: Vec<_> = tasks.iter().map(|task| wait(task).unwrap()).collect();
+        assert_eq!(results, [0, 2, 4, 6, 8, 10, 12, 14]);
+    }
+
+    #[test]
+    fn panics_fail_their_task_only() {
+        let pool = ThreadPool::new("test", 1);
+        let failed = pool.spawn(|| -> u32 { panic!("corrupt file") });
+        assert_eq!(wait(&failed), Err(TaskFailed));
+        // The only worker is still running
+        let task = pool.spawn(|| 1);
+        assert_eq!(wait(&task), Ok(1));
+    }
+}
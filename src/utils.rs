@@ -37,3 +37,20 @@ pub fn print_debug_infos() {
         );
     }
 }
+
+/// Highest sample count usable by multisampled framebuffers, which store
+/// their color and depth attachments in multisample textures.
+pub fn max_texture_samples() -> u32 {
+    let mut max_samples = 0;
+    let mut max_color_samples = 0;
+    let mut max_depth_samples = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_samples);
+        gl::GetIntegerv(gl::MAX_COLOR_TEXTURE_SAMPLES, &mut max_color_samples);
+        gl::GetIntegerv(gl::MAX_DEPTH_TEXTURE_SAMPLES, &mut max_depth_samples);
+    }
+    max_samples
+        .min(max_color_samples)
+        .min(max_depth_samples)
+        .max(0) as u32
+}
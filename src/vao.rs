@@ -45,7 +45,7 @@ impl Vao {
             gl::VertexArrayAttribBinding(self.id, index, 0);
             gl::EnableVertexArrayAttrib(self.id, index);
         }
-        return self;
+        self
     }
 
     pub fn with_vbo(self, vbo: &Vbo, stride: i32) -> Self {
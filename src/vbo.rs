@@ -1,25 +1,53 @@
 use crate::gl;
 use crate::vertex::Vertex;
-use std::mem::size_of;
+use std::cell::Cell;
 
 #[derive(Debug)]
 pub struct Vbo {
     pub id: u32,
+    size: Cell<usize>,
 }
 
 impl Vbo {
     pub fn new(vertices: &[Vertex]) -> Self {
+        Vbo::from_bytes(Vertex::as_bytes(vertices))
+    }
+
+    /// Buffer of vertices already laid out as `Vertex`, like the ones of
+    /// cooked meshes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
         let mut id = 0;
         unsafe {
             gl::CreateBuffers(1, &mut id);
+        }
+        let vbo = Vbo {
+            id,
+            size: Cell::new(0),
+        };
+        vbo.update_bytes(bytes);
+        vbo
+    }
+
+    /// Replaces the vertices, keeping the buffer object.
+    pub fn update(&self, vertices: &[Vertex]) {
+        self.update_bytes(Vertex::as_bytes(vertices));
+    }
+
+    fn update_bytes(&self, bytes: &[u8]) {
+        unsafe {
             gl::NamedBufferData(
-                id,
-                (vertices.len() * size_of::<Vertex>()) as _,
-                vertices.as_ptr() as *const _,
+                self.id,
+                bytes.len() as _,
+                bytes.as_ptr() as *const _,
                 gl::STATIC_DRAW,
             );
         }
-        Vbo { id }
+        self.size.set(bytes.len());
+    }
+
+    /// Size of the buffer in bytes.
+    pub fn size(&self) -> usize {
+        self.size.get()
     }
 }
 
@@ -1,20 +1,30 @@
+use crate::cooked::VERTEX_SIZE;
 use glam::{Vec2, Vec3};
+use std::mem::{size_of, size_of_val};
+use std::slice;
 
+/// Vertex of a mesh as laid out in vertex buffers and cooked mesh files,
+/// without padding.
 #[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Vertex {
     pub position: Vec3,
     pub normals: Vec3,
     pub color: Vec3,
     pub texture_coordinates: Vec2,
+    /// Direction of increasing u in the plane of the surface
+    pub tangent: Vec3,
+    /// Orientation of the bitangent, `normal.cross(tangent) * bitangent_sign`,
+    /// read with `tangent` as a single vec4 attribute
+    pub bitangent_sign: f32,
 }
 
-impl Default for Vertex {
-    fn default() -> Self {
-        Vertex {
-            position: Default::default(),
-            normals: Default::default(),
-            color: Default::default(),
-            texture_coordinates: Default::default(),
-        }
+// Cooked meshes are uploaded as they are read
+const _: () = assert!(size_of::<Vertex>() == VERTEX_SIZE);
+
+impl Vertex {
+    pub fn as_bytes(vertices: &[Vertex]) -> &[u8] {
+        // Vertex only holds f32 without padding
+        unsafe { slice::from_raw_parts(vertices.as_ptr() as *const u8, size_of_val(vertices)) }
     }
 }
@@ -0,0 +1,106 @@
+use crate::archive::Archive;
+use log::info;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+/// Where files are looked up.
+enum Mount {
+    Directory(PathBuf),
+    Archive(Archive),
+}
+
+/// Mounted directories and archives, the last mounted being searched first.
+/// Global so that the loaders and their worker threads can read files from
+/// paths alone.
+static MOUNTS: RwLock<Vec<Mount>> = RwLock::new(Vec::new());
+
+/// Makes the files of `directory` readable by their path relative to it.
+pub fn mount_directory(directory: &Path) {
+    info!("Mounted directory {}", directory.display());
+    MOUNTS
+        .write()
+        .unwrap()
+        .push(Mount::Directory(directory.to_owned()));
+}
+
+/// Makes the files of an archive built by the `pack` binary readable by the
+/// path they were packed with.
+pub fn mount_archive(path: &Path) -> io::Result<()> {
+    let archive = Archive::open(path)?;
+    info!(
+        "Mounted archive {} ({} files)",
+        path.display(),
+        archive.entries().count()
+    );
+    MOUNTS.write().unwrap().push(Mount::Archive(archive));
+    Ok(())
+}
+
+/// Mounts an archive if `path` is a file, a directory otherwise.
+pub fn mount(path: &Path) -> io::Result<()> {
+    if path.is_file() {
+        mount_archive(path)
+    } else if path.is_dir() {
+        mount_directory(path);
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::NotFound, "nothing to mount"))
+    }
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    for mount in MOUNTS.read().unwrap().iter().rev() {
+        match mount {
+            Mount::Directory(directory) => match fs::read(directory.join(path)) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+                result => return result,
+            },
+            Mount::Archive(archive) => {
+                if let Some(result) = archive.read(path) {
+                    return result;
+                }
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "{} not found in the mounted directories and archives",
+            path.display()
+        ),
+    ))
+}
+
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    String::from_utf8(read(path)?).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn exists(path: &Path) -> bool {
+    MOUNTS.read().unwrap().iter().any(|mount| match mount {
+        Mount::Directory(directory) => directory.join(path).is_file(),
+        Mount::Archive(archive) => archive.contains(path),
+    })
+}
+
+/// Modification time of the file read from `path`, `None` if it is in an
+/// archive since archives do not change.
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    for mount in MOUNTS.read().unwrap().iter().rev() {
+        match mount {
+            Mount::Directory(directory) => {
+                if let Ok(metadata) = fs::metadata(directory.join(path)) {
+                    return metadata.modified().ok();
+                }
+            }
+            Mount::Archive(archive) => {
+                if archive.contains(path) {
+                    return None;
+                }
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,53 @@
+use crate::vfs;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Polls the modification time of files read through `vfs`, each file being tagged with what
+/// to do when it changes.
+pub struct FileWatcher<T> {
+    files: HashMap<PathBuf, (T, Option<SystemTime>)>,
+    interval: Duration,
+    last_poll: Instant,
+}
+
+impl<T: Copy> FileWatcher<T> {
+    /// Watcher checking the files at most once per `interval`.
+    pub fn new(interval: Duration) -> Self {
+        FileWatcher {
+            files: HashMap::new(),
+            interval,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn watch(&mut self, path: &Path, tag: T) {
+        self.files
+            .entry(path.to_owned())
+            .or_insert_with(|| (tag, vfs::modified(path)));
+    }
+
+    pub fn unwatch(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    /// Files modified since the last call, none if it was less than the
+    /// interval ago.
+    pub fn modified(&mut self) -> Vec<(PathBuf, T)> {
+        if self.last_poll.elapsed() < self.interval {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+
+        let mut modified = vec![];
+        for (path, (tag, time)) in &mut self.files {
+            let new_time = vfs::modified(path);
+            // A file being rewritten may briefly be missing
+            if new_time.is_some() && new_time != *time {
+                *time = new_time;
+                modified.push((path.clone(), *tag));
+            }
+        }
+        modified
+    }
+}